@@ -0,0 +1,132 @@
+//! Generates the C header for the API in `src/lib.rs` and compiles the C smoke test that the
+//! library's tests link against.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The file the C API is defined in
+const FFI_SOURCE: &str = "src/lib.rs";
+
+/// The C smoke test
+const SMOKE_TEST: &str = "smoke.c";
+
+fn main() {
+    println!("cargo:rerun-if-changed={FFI_SOURCE}");
+    println!("cargo:rerun-if-changed={SMOKE_TEST}");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let source = fs::read_to_string(FFI_SOURCE).unwrap();
+    fs::write(Path::new(&out_dir).join("chip8.h"), header(&source)).unwrap();
+
+    cc::Build::new()
+        .file(SMOKE_TEST)
+        .include(&out_dir)
+        .warnings_into_errors(true)
+        .compile("chip8_smoke");
+}
+
+/// Generates the header from the FFI source. Opaque structs, constants with literal values and
+/// `extern "C"` functions outside the tests are declared, with their doc comments
+fn header(source: &str) -> String {
+    let mut header = String::from("\
+/* A C API for the chip-8-emulator interpreter. Generated from src/lib.rs by build.rs, don't edit. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+");
+    let mut docs = Vec::new();
+
+    for line in source.lines() {
+        let line = line.trim();
+        if line.starts_with("#[cfg(test)]") {
+            break;
+        }
+
+        let declaration = if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.to_string());
+            continue;
+        } else if let Some(name) = line.strip_prefix("pub struct ").and_then(|rest| rest.split_whitespace().next()) {
+            Some(format!("typedef struct {name} {name};"))
+        } else if let Some(constant) = line.strip_prefix("pub const ") {
+            constant_define(constant)
+        } else if let Some(function) = line.split_once("extern \"C\" fn ").map(|(_, function)| function) {
+            Some(function_declaration(function))
+        } else if line.starts_with("#[") {
+            continue;
+        } else {
+            None
+        };
+
+        if let Some(declaration) = declaration {
+            header.push('\n');
+            for doc in &docs {
+                header.push_str(format!("//{doc}").trim_end());
+                header.push('\n');
+            }
+            header.push_str(&declaration);
+            header.push('\n');
+        }
+        docs.clear();
+    }
+
+    header.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n#endif\n");
+    header
+}
+
+/// Turns `NAME: TYPE = VALUE;` into a define, if the value is a number
+fn constant_define(constant: &str) -> Option<String> {
+    let (name, rest) = constant.split_once(':')?;
+    let value = rest.split_once('=')?.1.trim().trim_end_matches(';');
+    value.chars().all(|c| c.is_ascii_hexdigit() || c == 'x' || c == '_')
+        .then(|| format!("#define {name} {}", value.replace('_', "")))
+}
+
+/// Turns `name(argument: TYPE, ...) -> TYPE {` into a C prototype
+fn function_declaration(function: &str) -> String {
+    let (name, rest) = function.split_once('(').unwrap();
+    let (arguments, rest) = rest.split_once(')').unwrap();
+    let return_type = rest.split_once("->").map_or("void".to_string(), |(_, rest)| c_type(rest.trim_end_matches('{')));
+
+    let arguments: Vec<String> = arguments.split(',')
+        .filter(|argument| !argument.trim().is_empty())
+        .map(|argument| {
+            let (name, argument_type) = argument.split_once(':').unwrap();
+            format!("{} {}", c_type(argument_type), name.trim()).replace("* ", "*")
+        })
+        .collect();
+    let arguments = if arguments.is_empty() { "void".to_string() } else { arguments.join(", ") };
+
+    let separator = if return_type.ends_with('*') { "" } else { " " };
+    format!("{return_type}{separator}{name}({arguments});")
+}
+
+/// Translates a Rust type in the API to C
+fn c_type(rust_type: &str) -> String {
+    let rust_type = rust_type.trim();
+    if let Some(pointee) = rust_type.strip_prefix("*const ") {
+        return format!("const {} *", c_type(pointee));
+    }
+    if let Some(pointee) = rust_type.strip_prefix("*mut ") {
+        return format!("{} *", c_type(pointee));
+    }
+
+    match rust_type {
+        "bool" => "bool",
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "u64" => "uint64_t",
+        "i32" => "int32_t",
+        "usize" => "size_t",
+        other => other,
+    }.to_string()
+}
@@ -0,0 +1,116 @@
+/* A C API for the chip-8-emulator interpreter. Generated from src/lib.rs by build.rs, don't edit. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// The width of the frame buffer in pixels
+#define CHIP8_DISPLAY_WIDTH 64
+
+// The height of the frame buffer in pixels
+#define CHIP8_DISPLAY_HEIGHT 32
+
+// Interprets instructions as the COSMAC VIP did
+#define CHIP8_TYPE_COSMAC_VIP 0
+
+// Interprets instructions as CHIP-48 did
+#define CHIP8_TYPE_CHIP48 1
+
+// An interpreter created for C code, which only ever sees pointers to it
+typedef struct Chip8Handle Chip8Handle;
+
+// Creates an interpreter of one of the `CHIP8_TYPE_*` types, with its random number generator
+// seeded from `seed`. Returns NULL if the type is unknown. Free it with `chip8_destroy`
+Chip8Handle *chip8_create(uint32_t emulator_type, uint64_t seed);
+
+// Frees an interpreter. Does nothing if `handle` is NULL
+//
+// # Safety
+//
+// `handle` must be NULL or come from `chip8_create` and not have been freed
+void chip8_destroy(Chip8Handle *handle);
+
+// Loads a ROM into memory at 0x200. Returns false without loading anything if it doesn't fit
+//
+// # Safety
+//
+// `handle` must be a live interpreter and `rom` must point to `length` readable bytes
+bool chip8_load_rom(Chip8Handle *handle, const uint8_t *rom, size_t length);
+
+// Executes one instruction. Returns false if it was invalid, in which case the interpreter
+// shouldn't be stepped any further
+//
+// # Safety
+//
+// `handle` must be a live interpreter
+bool chip8_step(Chip8Handle *handle);
+
+// Runs one 60 Hz frame, executing `instructions_per_frame` instructions and then decrementing
+// the timers. Returns false if an instruction was invalid, in which case the interpreter
+// shouldn't be stepped any further
+//
+// # Safety
+//
+// `handle` must be a live interpreter
+bool chip8_run_frame(Chip8Handle *handle, uint32_t instructions_per_frame);
+
+// Presses the key with the value 0x0-0xF. Other values are ignored
+//
+// # Safety
+//
+// `handle` must be a live interpreter
+void chip8_key_down(Chip8Handle *handle, uint8_t key);
+
+// Releases the key with the value 0x0-0xF. Other values are ignored
+//
+// # Safety
+//
+// `handle` must be a live interpreter
+void chip8_key_up(Chip8Handle *handle, uint8_t key);
+
+// Gets the frame buffer, CHIP8_DISPLAY_WIDTH * CHIP8_DISPLAY_HEIGHT bytes a row at a time with
+// one byte per pixel, 1 if lit and 0 if not. It stays valid until the interpreter is freed
+//
+// # Safety
+//
+// `handle` must be a live interpreter
+const uint8_t *chip8_frame_buffer(const Chip8Handle *handle);
+
+// Returns whether the sound timer is running, so a tone should be playing
+//
+// # Safety
+//
+// `handle` must be a live interpreter
+bool chip8_is_playing_sound(const Chip8Handle *handle);
+
+// Gets the size in bytes of a saved state
+size_t chip8_state_size(void);
+
+// Saves the interpreter's state, including its random number generator, into `buffer`.
+// Returns the number of bytes written, or 0 if `length` is less than `chip8_state_size()`
+//
+// # Safety
+//
+// `handle` must be a live interpreter and `buffer` must point to `length` writable bytes
+size_t chip8_save_state(const Chip8Handle *handle, uint8_t *buffer, size_t length);
+
+// Restores a state saved with `chip8_save_state`. Returns false, leaving the interpreter
+// unchanged, if it isn't a valid state
+//
+// # Safety
+//
+// `handle` must be a live interpreter and `state` must point to `length` readable bytes
+bool chip8_load_state(Chip8Handle *handle, const uint8_t *state, size_t length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,250 @@
+//! A minimal libretro frontend for checking the core builds into a working shared library. Runs a
+//! ROM headlessly through the libretro API and checks the picture, sound and save states.
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use chip_8_emulator::command_line::{exit_on_error, next_value, parse_args, parse_value};
+use chip8::libretro::{RetroGameInfo, RetroSystemAvInfo, RetroSystemInfo, RetroVariable};
+use libloading::Library;
+
+/// The usage text printed when the arguments can't be parsed
+const USAGE: &str = "\
+usage: cargo run -p chip-8-ffi --example libretro-frontend -- [OPTIONS] <CORE> <ROM>
+
+Loads a libretro core from a shared library, e.g. target/release/libchip8.so, and runs
+the ROM on it with no input. Checks a picture and a frame of sound come back every frame and that
+loading a state saved halfway through replays the second half identically, then prints the last
+picture.
+
+options:
+    --frames <N>           the number of frames to run (default: 600)
+    --option <KEY=VALUE>   set a core option, e.g. chip8_speed=20. Can be repeated";
+
+/// The default number of frames to run
+const DEFAULT_FRAMES: u64 = 600;
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_int = 1;
+
+/// Options for the tool parsed from the command line
+struct Options {
+    core_path: PathBuf,
+    rom_path: PathBuf,
+    frames: u64,
+    core_options: Vec<(String, String)>,
+}
+
+impl Options {
+    /// Parses options from command line arguments, not including the program name
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut paths = Vec::new();
+        let mut frames = DEFAULT_FRAMES;
+        let mut core_options = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let mut value = || next_value(&mut args, &arg);
+            match arg.as_str() {
+                "--frames" => frames = parse_value(&value()?, &arg)?,
+                "--option" => {
+                    let option = value()?;
+                    let (key, value) = option.split_once('=').ok_or_else(|| anyhow!("expected KEY=VALUE, got {option}"))?;
+                    core_options.push((key.to_string(), value.to_string()));
+                }
+                _ if arg.starts_with("--") => bail!("unknown option {arg}"),
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [core_path, rom_path]: [PathBuf; 2] = paths.try_into().map_err(|_| anyhow!("expected a core and a ROM"))?;
+        Ok(Self { core_path, rom_path, frames, core_options })
+    }
+}
+
+/// What the core has told the frontend through the callbacks
+struct FrontendState {
+    /// Core options as keys and chosen values, kept alive for the core to read
+    core_options: Vec<(CString, CString)>,
+    /// The last picture, in XRGB8888
+    picture: Vec<u32>,
+    width: usize,
+    height: usize,
+    video_frames: u64,
+    audio_frames: u64,
+}
+
+static STATE: Mutex<FrontendState> = Mutex::new(FrontendState {
+    core_options: Vec::new(),
+    picture: Vec::new(),
+    width: 0,
+    height: 0,
+    video_frames: 0,
+    audio_frames: 0,
+});
+
+unsafe extern "C" fn environment(command: c_uint, data: *mut c_void) -> bool {
+    let mut state = STATE.lock().unwrap();
+    match command {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => *(data as *const c_int) == RETRO_PIXEL_FORMAT_XRGB8888,
+        // Declared options default to their first value unless set on the command line
+        RETRO_ENVIRONMENT_SET_VARIABLES => {
+            let mut variable = data as *const RetroVariable;
+            while !(*variable).key.is_null() {
+                let key = CStr::from_ptr((*variable).key).to_owned();
+                let declaration = CStr::from_ptr((*variable).value).to_string_lossy().into_owned();
+                let default = declaration.split_once("; ")
+                    .and_then(|(_, values)| values.split('|').next())
+                    .unwrap_or_default();
+                if !state.core_options.iter().any(|(existing, _)| *existing == key) {
+                    state.core_options.push((key, CString::new(default).unwrap()));
+                }
+                variable = variable.add(1);
+            }
+            true
+        }
+        RETRO_ENVIRONMENT_GET_VARIABLE => {
+            let variable = &mut *(data as *mut RetroVariable);
+            let key = CStr::from_ptr(variable.key);
+            match state.core_options.iter().find(|(existing, _)| existing.as_c_str() == key) {
+                Some((_, value)) => {
+                    variable.value = value.as_ptr();
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    let mut state = STATE.lock().unwrap();
+    state.picture = (0..height as usize)
+        .flat_map(|y| std::slice::from_raw_parts((data as *const u8).add(y * pitch) as *const u32, width as usize))
+        .copied()
+        .collect();
+    state.width = width as usize;
+    state.height = height as usize;
+    state.video_frames += 1;
+}
+
+unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    STATE.lock().unwrap().audio_frames += frames as u64;
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(_port: c_uint, _device: c_uint, _index: c_uint, _id: c_uint) -> i16 {
+    0
+}
+
+fn main() {
+    let options = parse_args(USAGE, Options::parse);
+    exit_on_error(run(&options));
+}
+
+/// Loads the core and runs the ROM on it
+fn run(options: &Options) -> Result<()> {
+    let program = std::fs::read(&options.rom_path)
+        .with_context(|| format!("failed to read {}", options.rom_path.display()))?;
+    STATE.lock().unwrap().core_options = options.core_options.iter()
+        .map(|(key, value)| Ok((CString::new(key.as_str())?, CString::new(value.as_str())?)))
+        .collect::<Result<_>>()?;
+
+    unsafe {
+        let core = Library::new(&options.core_path)
+            .with_context(|| format!("failed to load {}", options.core_path.display()))?;
+        let api_version: unsafe extern "C" fn() -> c_uint = *core.get(b"retro_api_version")?;
+        ensure!(api_version() == 1, "unsupported libretro API version {}", api_version());
+
+        let set_environment: unsafe extern "C" fn(unsafe extern "C" fn(c_uint, *mut c_void) -> bool) = *core.get(b"retro_set_environment")?;
+        let set_video_refresh: unsafe extern "C" fn(unsafe extern "C" fn(*const c_void, c_uint, c_uint, usize)) = *core.get(b"retro_set_video_refresh")?;
+        let set_audio_sample_batch: unsafe extern "C" fn(unsafe extern "C" fn(*const i16, usize) -> usize) = *core.get(b"retro_set_audio_sample_batch")?;
+        let set_input_poll: unsafe extern "C" fn(unsafe extern "C" fn()) = *core.get(b"retro_set_input_poll")?;
+        let set_input_state: unsafe extern "C" fn(unsafe extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16) = *core.get(b"retro_set_input_state")?;
+        let init: unsafe extern "C" fn() = *core.get(b"retro_init")?;
+        let deinit: unsafe extern "C" fn() = *core.get(b"retro_deinit")?;
+        let get_system_info: unsafe extern "C" fn(*mut RetroSystemInfo) = *core.get(b"retro_get_system_info")?;
+        let get_system_av_info: unsafe extern "C" fn(*mut RetroSystemAvInfo) = *core.get(b"retro_get_system_av_info")?;
+        let load_game: unsafe extern "C" fn(*const RetroGameInfo) -> bool = *core.get(b"retro_load_game")?;
+        let unload_game: unsafe extern "C" fn() = *core.get(b"retro_unload_game")?;
+        let run_frame: unsafe extern "C" fn() = *core.get(b"retro_run")?;
+        let serialize_size: unsafe extern "C" fn() -> usize = *core.get(b"retro_serialize_size")?;
+        let serialize: unsafe extern "C" fn(*mut c_void, usize) -> bool = *core.get(b"retro_serialize")?;
+        let unserialize: unsafe extern "C" fn(*const c_void, usize) -> bool = *core.get(b"retro_unserialize")?;
+
+        set_environment(environment);
+        set_video_refresh(video_refresh);
+        set_audio_sample_batch(audio_sample_batch);
+        set_input_poll(input_poll);
+        set_input_state(input_state);
+        init();
+
+        let mut system_info: RetroSystemInfo = std::mem::zeroed();
+        get_system_info(&mut system_info);
+        println!("Loaded {} {}",
+                 CStr::from_ptr(system_info.library_name).to_string_lossy(),
+                 CStr::from_ptr(system_info.library_version).to_string_lossy());
+
+        let game = RetroGameInfo {
+            path: std::ptr::null::<c_char>(),
+            data: program.as_ptr() as *const c_void,
+            size: program.len(),
+            meta: std::ptr::null(),
+        };
+        ensure!(load_game(&game), "the core failed to load the ROM");
+
+        let mut av_info: RetroSystemAvInfo = std::mem::zeroed();
+        get_system_av_info(&mut av_info);
+        let samples_per_frame = (av_info.timing.sample_rate / av_info.timing.fps) as u64;
+
+        let first_half = options.frames / 2;
+        for _ in 0..first_half {
+            run_frame();
+        }
+        let mut saved = vec![0u8; serialize_size()];
+        ensure!(serialize(saved.as_mut_ptr() as *mut c_void, saved.len()), "the core failed to save its state");
+
+        let mut pictures = Vec::new();
+        for _ in first_half..options.frames {
+            run_frame();
+            pictures.push(STATE.lock().unwrap().picture.clone());
+        }
+        ensure!(unserialize(saved.as_ptr() as *const c_void, saved.len()), "the core failed to load its state");
+        for (frame, picture) in (first_half..options.frames).zip(&pictures) {
+            run_frame();
+            ensure!(STATE.lock().unwrap().picture == *picture, "the picture after loading the state differs at frame {frame}");
+        }
+
+        let state = STATE.lock().unwrap();
+        let frames_run = options.frames + pictures.len() as u64;
+        ensure!(state.video_frames == frames_run, "expected {frames_run} pictures, got {}", state.video_frames);
+        ensure!(state.audio_frames == frames_run * samples_per_frame,
+                "expected {} audio frames, got {}", frames_run * samples_per_frame, state.audio_frames);
+        println!("Ran {} frames at {}x{}, and a saved state replayed identically", options.frames, state.width, state.height);
+        print_picture(&state);
+        drop(state);
+
+        unload_game();
+        deinit();
+    }
+
+    Ok(())
+}
+
+/// Prints the last picture as text, with pixels that aren't the background color as #
+fn print_picture(state: &FrontendState) {
+    let Some(&background) = state.picture.iter().min_by_key(|color| state.picture.iter().filter(|other| other != color).count()) else {
+        return;
+    };
+
+    for row in state.picture.chunks(state.width.max(1)) {
+        let line: String = row.iter().map(|color| if *color == background { ' ' } else { '#' }).collect();
+        println!("{}", line.trim_end());
+    }
+}
@@ -0,0 +1,75 @@
+/* Exercises the C API from C. Compiled by build.rs and run by the library's tests. */
+
+#include <stdlib.h>
+#include <string.h>
+#include "chip8.h"
+
+#define CHECK(condition) do { if (!(condition)) { result = __LINE__; goto done; } } while (0)
+
+/* Draws an 8 at (2, 2), puts a random number in V2, waits for a key into V3 and beeps */
+static const uint8_t ROM[] = {
+    0x60, 0x08, 0x61, 0x02, 0xF0, 0x29, 0xD1, 0x15,
+    0xC2, 0xFF, 0xF3, 0x0A, 0x64, 0x10, 0xF4, 0x18,
+    0x12, 0x10,
+};
+
+int chip8_smoke_test(void) {
+    int result = 0;
+    size_t state_size = chip8_state_size();
+    uint8_t *state = malloc(state_size);
+    uint8_t *copy_state = malloc(state_size);
+    static const uint8_t invalid_rom[4096] = {0};
+    Chip8Handle *chip8 = chip8_create(CHIP8_TYPE_COSMAC_VIP, 42);
+    Chip8Handle *copy = chip8_create(CHIP8_TYPE_CHIP48, 7);
+
+    CHECK(state != NULL && copy_state != NULL && chip8 != NULL && copy != NULL);
+    CHECK(chip8_create(99, 0) == NULL);
+    CHECK(!chip8_load_rom(chip8, invalid_rom, sizeof invalid_rom));
+    CHECK(chip8_load_rom(chip8, ROM, sizeof ROM));
+
+    /* Runs up to the random number and checks the 8 was drawn */
+    for (int i = 0; i < 4; i++) {
+        CHECK(chip8_step(chip8));
+    }
+    const uint8_t *frame_buffer = chip8_frame_buffer(chip8);
+    CHECK(frame_buffer[2 * CHIP8_DISPLAY_WIDTH + 2] == 1);
+    CHECK(frame_buffer[3 * CHIP8_DISPLAY_WIDTH + 3] == 0);
+
+    /* The copy gets the random number generator too, so should draw the same number */
+    CHECK(chip8_save_state(chip8, state, state_size - 1) == 0);
+    CHECK(chip8_save_state(chip8, state, state_size) == state_size);
+    CHECK(chip8_load_state(copy, state, state_size));
+    CHECK(memcmp(chip8_frame_buffer(copy), frame_buffer, CHIP8_DISPLAY_WIDTH * CHIP8_DISPLAY_HEIGHT) == 0);
+
+    /* Key 7 is pressed and released to get past the wait, then the beep starts */
+    Chip8Handle *handles[] = { chip8, copy };
+    for (int i = 0; i < 2; i++) {
+        chip8_key_down(handles[i], 0x7);
+        CHECK(chip8_run_frame(handles[i], 3));
+        chip8_key_up(handles[i], 0x7);
+        chip8_key_up(handles[i], 0x20);
+        CHECK(chip8_run_frame(handles[i], 3));
+        CHECK(chip8_is_playing_sound(handles[i]));
+    }
+
+    CHECK(chip8_save_state(chip8, state, state_size) == state_size);
+    CHECK(chip8_save_state(copy, copy_state, state_size) == state_size);
+    CHECK(memcmp(state, copy_state, state_size) == 0);
+
+    state[0] = 'X';
+    CHECK(!chip8_load_state(chip8, state, state_size));
+
+    /* A zero opcode is invalid */
+    chip8_destroy(NULL);
+    chip8_destroy(chip8);
+    chip8 = chip8_create(CHIP8_TYPE_CHIP48, 0);
+    CHECK(chip8_load_rom(chip8, invalid_rom, 2));
+    CHECK(!chip8_step(chip8));
+
+done:
+    free(state);
+    free(copy_state);
+    chip8_destroy(chip8);
+    chip8_destroy(copy);
+    return result;
+}
@@ -0,0 +1,246 @@
+//! A C API and a libretro core for the chip-8-emulator interpreter, built as the `chip8` shared
+//! library.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{ptr, slice};
+use chip_8_emulator::state::STATE_SIZE;
+use chip_8_emulator::{Chip8, Chip8Key, Chip8Rng, EmulatorType, DISPLAY_HEIGHT, DISPLAY_WIDTH, MAX_PROGRAM_SIZE};
+
+pub mod libretro;
+
+/// The C header declaring this API, generated from this file by the build script
+pub const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/chip8.h"));
+
+/// The width of the frame buffer in pixels
+pub const CHIP8_DISPLAY_WIDTH: u32 = 64;
+
+/// The height of the frame buffer in pixels
+pub const CHIP8_DISPLAY_HEIGHT: u32 = 32;
+
+/// Interprets instructions as the COSMAC VIP did
+pub const CHIP8_TYPE_COSMAC_VIP: u32 = 0;
+
+/// Interprets instructions as CHIP-48 did
+pub const CHIP8_TYPE_CHIP48: u32 = 1;
+
+const _: () = assert!(CHIP8_DISPLAY_WIDTH as usize == DISPLAY_WIDTH && CHIP8_DISPLAY_HEIGHT as usize == DISPLAY_HEIGHT);
+
+/// A random number generator whose whole state is one number, so it can be seeded and saved
+/// through the API
+#[derive(Debug)]
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    /// Advances the state and generates the next number
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Chip8Rng for SplitMix64 {
+    fn random_byte(&mut self) -> u8 {
+        (self.next_u64() >> 32) as u8
+    }
+}
+
+/// An interpreter created for C code, which only ever sees pointers to it
+#[derive(Debug)]
+pub struct Chip8Handle {
+    chip8: Chip8<SplitMix64>,
+}
+
+/// Creates an interpreter of one of the `CHIP8_TYPE_*` types, with its random number generator
+/// seeded from `seed`. Returns NULL if the type is unknown. Free it with `chip8_destroy`
+#[no_mangle]
+pub extern "C" fn chip8_create(emulator_type: u32, seed: u64) -> *mut Chip8Handle {
+    let emulator_type = match emulator_type {
+        CHIP8_TYPE_COSMAC_VIP => EmulatorType::CosmacVip,
+        CHIP8_TYPE_CHIP48 => EmulatorType::Chip48,
+        _ => return ptr::null_mut(),
+    };
+
+    Box::into_raw(Box::new(Chip8Handle { chip8: Chip8::new(emulator_type, SplitMix64(seed)) }))
+}
+
+/// Frees an interpreter. Does nothing if `handle` is NULL
+///
+/// # Safety
+///
+/// `handle` must be NULL or come from `chip8_create` and not have been freed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_destroy(handle: *mut Chip8Handle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Loads a ROM into memory at 0x200. Returns false without loading anything if it doesn't fit
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter and `rom` must point to `length` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(handle: *mut Chip8Handle, rom: *const u8, length: usize) -> bool {
+    if length > MAX_PROGRAM_SIZE {
+        return false;
+    }
+
+    (*handle).chip8.load_program(slice::from_raw_parts(rom, length));
+    true
+}
+
+/// Executes one instruction. Returns false if it was invalid, in which case the interpreter
+/// shouldn't be stepped any further
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(handle: *mut Chip8Handle) -> bool {
+    let chip8 = &mut (*handle).chip8;
+    catch_unwind(AssertUnwindSafe(|| chip8.execute_next_instruction())).is_ok()
+}
+
+/// Runs one 60 Hz frame, executing `instructions_per_frame` instructions and then decrementing
+/// the timers. Returns false if an instruction was invalid, in which case the interpreter
+/// shouldn't be stepped any further
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(handle: *mut Chip8Handle, instructions_per_frame: u32) -> bool {
+    let chip8 = &mut (*handle).chip8;
+    catch_unwind(AssertUnwindSafe(|| chip8.run_frame(instructions_per_frame as usize))).is_ok()
+}
+
+/// Presses the key with the value 0x0-0xF. Other values are ignored
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_down(handle: *mut Chip8Handle, key: u8) {
+    if let Some(key) = Chip8Key::from_value(key) {
+        (*handle).chip8.key_down(key);
+    }
+}
+
+/// Releases the key with the value 0x0-0xF. Other values are ignored
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_up(handle: *mut Chip8Handle, key: u8) {
+    if let Some(key) = Chip8Key::from_value(key) {
+        (*handle).chip8.key_up(key);
+    }
+}
+
+/// Gets the frame buffer, CHIP8_DISPLAY_WIDTH * CHIP8_DISPLAY_HEIGHT bytes a row at a time with
+/// one byte per pixel, 1 if lit and 0 if not. It stays valid until the interpreter is freed
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter
+#[no_mangle]
+pub unsafe extern "C" fn chip8_frame_buffer(handle: *const Chip8Handle) -> *const u8 {
+    (*handle).chip8.frame_buffer().as_ptr()
+}
+
+/// Returns whether the sound timer is running, so a tone should be playing
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter
+#[no_mangle]
+pub unsafe extern "C" fn chip8_is_playing_sound(handle: *const Chip8Handle) -> bool {
+    (*handle).chip8.is_playing_sound()
+}
+
+/// Gets the size in bytes of a saved state
+#[no_mangle]
+pub extern "C" fn chip8_state_size() -> usize {
+    STATE_SIZE + 8
+}
+
+/// Saves the interpreter's state, including its random number generator, into `buffer`.
+/// Returns the number of bytes written, or 0 if `length` is less than `chip8_state_size()`
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter and `buffer` must point to `length` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(handle: *const Chip8Handle, buffer: *mut u8, length: usize) -> usize {
+    let size = chip8_state_size();
+    if length < size {
+        return 0;
+    }
+
+    save_state(&(*handle).chip8, slice::from_raw_parts_mut(buffer, size));
+    size
+}
+
+/// Restores a state saved with `chip8_save_state`. Returns false, leaving the interpreter
+/// unchanged, if it isn't a valid state
+///
+/// # Safety
+///
+/// `handle` must be a live interpreter and `state` must point to `length` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(handle: *mut Chip8Handle, state: *const u8, length: usize) -> bool {
+    load_state(&mut (*handle).chip8, slice::from_raw_parts(state, length))
+}
+
+/// Saves an interpreter's state followed by its random number generator's into a buffer of
+/// exactly `chip8_state_size()` bytes
+pub(crate) fn save_state(chip8: &Chip8<SplitMix64>, buffer: &mut [u8]) {
+    buffer[..STATE_SIZE].copy_from_slice(&chip8.save_state());
+    buffer[STATE_SIZE..].copy_from_slice(&chip8.rng().0.to_le_bytes());
+}
+
+/// Restores a state saved with [`save_state`], returning false and leaving the interpreter
+/// unchanged if it isn't valid
+pub(crate) fn load_state(chip8: &mut Chip8<SplitMix64>, state: &[u8]) -> bool {
+    if state.len() != chip8_state_size() {
+        return false;
+    }
+
+    let (state, rng) = state.split_at(STATE_SIZE);
+    if chip8.load_state(state).is_err() {
+        return false;
+    }
+
+    chip8.rng_mut().0 = u64::from_le_bytes(rng.try_into().unwrap());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" {
+        /// Defined in `ffi/smoke.c`, which the build script compiles and links. Returns 0 if
+        /// every check passes, or the line of the first failing one
+        fn chip8_smoke_test() -> i32;
+    }
+
+    #[test]
+    fn passes_the_c_smoke_test() {
+        assert_eq!(0, unsafe { chip8_smoke_test() });
+    }
+
+    #[test]
+    fn ships_the_generated_header() {
+        let shipped = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/chip8.h")).unwrap();
+
+        assert_eq!(HEADER, shipped, "chip8.h is out of date, copy it from the build script's output");
+        assert!(HEADER.contains("#define CHIP8_TYPE_CHIP48 1\n"));
+        assert!(HEADER.contains("bool chip8_load_rom(Chip8Handle *handle, const uint8_t *rom, size_t length);\n"));
+    }
+}
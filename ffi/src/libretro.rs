@@ -0,0 +1,646 @@
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Mutex, OnceLock};
+use std::{ptr, slice};
+use chip_8_emulator::audio::{BuzzerTone, FRAMES_PER_SECOND, SAMPLES_PER_FRAME, SAMPLE_RATE};
+use chip_8_emulator::detection::Detection;
+use chip_8_emulator::palette::Palette;
+use chip_8_emulator::{Chip8, Chip8Key, EmulatorType, DISPLAY_HEIGHT, DISPLAY_WIDTH, MAX_PROGRAM_SIZE};
+use crate::{chip8_state_size, load_state, save_state, SplitMix64};
+
+/// The version of the libretro API implemented
+const RETRO_API_VERSION: c_uint = 1;
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+
+const RETRO_PIXEL_FORMAT_XRGB8888: c_int = 1;
+
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+
+const RETRO_REGION_NTSC: c_uint = 0;
+
+/// The seed of the random number generator when a game is loaded or reset, fixed so runs are
+/// deterministic, e.g. for netplay
+const SEED: u64 = 0;
+
+/// The frequency of the buzzer's tone in Hz
+const TONE_FREQUENCY: f32 = 440.0;
+
+/// The amplitude of the buzzer's tone
+const TONE_AMPLITUDE: f32 = 0.5;
+
+/// Maps the left side of a QWERTY keyboard to the keypad, as in the desktop emulator. Keys are
+/// libretro key codes, which are ASCII for digits and lowercase letters
+const KEYBOARD: [(u8, Chip8Key); 16] = [
+    (b'1', Chip8Key::One), (b'2', Chip8Key::Two), (b'3', Chip8Key::Three), (b'4', Chip8Key::C),
+    (b'q', Chip8Key::Four), (b'w', Chip8Key::Five), (b'e', Chip8Key::Six), (b'r', Chip8Key::D),
+    (b'a', Chip8Key::Seven), (b's', Chip8Key::Eight), (b'd', Chip8Key::Nine), (b'f', Chip8Key::E),
+    (b'z', Chip8Key::A), (b'x', Chip8Key::Zero), (b'c', Chip8Key::B), (b'v', Chip8Key::F),
+];
+
+/// Maps each of the 16 RetroPad buttons, indexed by libretro button ID, to a key. The D-pad is
+/// 2, 4, 6 and 8 and A is 5, which most games use for movement and action
+const JOYPAD: [Chip8Key; 16] = [
+    Chip8Key::Zero, // B
+    Chip8Key::Three, // Y
+    Chip8Key::E, // Select
+    Chip8Key::F, // Start
+    Chip8Key::Two, // Up
+    Chip8Key::Eight, // Down
+    Chip8Key::Four, // Left
+    Chip8Key::Six, // Right
+    Chip8Key::Five, // A
+    Chip8Key::One, // X
+    Chip8Key::Seven, // L
+    Chip8Key::Nine, // R
+    Chip8Key::A, // L2
+    Chip8Key::B, // R2
+    Chip8Key::C, // L3
+    Chip8Key::D, // R3
+];
+
+/// The speeds offered as a core option in instructions per frame, the default first
+const SPEEDS: [usize; 11] = [12, 7, 10, 15, 20, 30, 50, 100, 200, 500, 1000];
+
+/// Describes the core to the frontend
+#[repr(C)]
+pub struct RetroSystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+/// The size of the picture
+#[repr(C)]
+pub struct RetroGameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+/// The frame and sample rates
+#[repr(C)]
+pub struct RetroSystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+/// The picture size and timing
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    pub geometry: RetroGameGeometry,
+    pub timing: RetroSystemTiming,
+}
+
+/// A game the frontend asks the core to load
+#[repr(C)]
+pub struct RetroGameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+/// A core option, declared as `Description; default|other|...` and read back as the chosen value
+#[repr(C)]
+pub struct RetroVariable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+/// Carries out a command from the core, e.g. reading an option
+pub type RetroEnvironment = unsafe extern "C" fn(command: c_uint, data: *mut c_void) -> bool;
+/// Takes a frame's picture
+pub type RetroVideoRefresh = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+/// Takes one stereo sample
+pub type RetroAudioSample = unsafe extern "C" fn(left: i16, right: i16);
+/// Takes interleaved stereo samples, returning how many frames of them were used
+pub type RetroAudioSampleBatch = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+/// Reads the controls
+pub type RetroInputPoll = unsafe extern "C" fn();
+/// Returns whether a button or key is held down
+pub type RetroInputState = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+/// The callbacks the frontend has given the core
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+/// The loaded game, since libretro cores run one at a time
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+/// The settings from the core options
+#[derive(Debug, Clone)]
+struct CoreOptions {
+    /// The emulator type whose quirks to use, or `None` to detect it from the ROM
+    emulator_type: Option<EmulatorType>,
+    instructions_per_frame: usize,
+    palette: Palette,
+}
+
+impl Default for CoreOptions {
+    fn default() -> Self {
+        Self {
+            emulator_type: None,
+            instructions_per_frame: SPEEDS[0],
+            palette: Palette::builtin().remove(0),
+        }
+    }
+}
+
+/// A loaded game
+struct Core {
+    program: Vec<u8>,
+    chip8: Chip8<SplitMix64>,
+    options: CoreOptions,
+    tone: BuzzerTone,
+    /// Set once the ROM executes an invalid instruction, after which it's no longer run
+    crashed: bool,
+    /// The picture sent to the frontend, in XRGB8888
+    pixels: Vec<u32>,
+    /// The sound sent to the frontend, in interleaved stereo
+    samples: Vec<i16>,
+}
+
+impl Core {
+    /// Loads a ROM with the given options
+    fn new(program: &[u8], options: CoreOptions) -> Self {
+        let mut core = Self {
+            program: program.to_vec(),
+            chip8: Chip8::new(EmulatorType::CosmacVip, SplitMix64(SEED)),
+            options,
+            tone: BuzzerTone::new(TONE_FREQUENCY, TONE_AMPLITUDE),
+            crashed: false,
+            pixels: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            samples: vec![0; SAMPLES_PER_FRAME * 2],
+        };
+        core.reset();
+        core
+    }
+
+    /// Restarts the ROM, applying the quirks option
+    fn reset(&mut self) {
+        let emulator_type = self.options.emulator_type.unwrap_or_else(|| Detection::detect(&self.program).emulator_type());
+        self.chip8 = Chip8::new(emulator_type, SplitMix64(SEED));
+        self.chip8.load_program(&self.program);
+        self.crashed = false;
+    }
+
+    /// Runs one frame with the keys held down, rendering the picture and sound
+    fn run_frame(&mut self, keys: [bool; 16]) {
+        for (key, is_down) in Chip8Key::ALL.into_iter().zip(keys) {
+            if is_down { self.chip8.key_down(key) } else { self.chip8.key_up(key) }
+        }
+
+        if !self.crashed {
+            let (chip8, instructions_per_frame) = (&mut self.chip8, self.options.instructions_per_frame);
+            self.crashed = catch_unwind(AssertUnwindSafe(|| chip8.run_frame(instructions_per_frame))).is_err();
+        }
+
+        for (pixel, value) in self.pixels.iter_mut().zip(self.chip8.frame_buffer()) {
+            let [red, green, blue] = self.options.palette.color(*value);
+            *pixel = u32::from_be_bytes([0, red, green, blue]);
+        }
+
+        let is_playing_sound = self.chip8.is_playing_sound();
+        for frame in self.samples.chunks_mut(2) {
+            frame.fill(self.tone.next_sample(is_playing_sound));
+        }
+    }
+}
+
+/// Gets the callbacks, copied so none are called with the lock held
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+/// Gets the core options' declarations, which must outlive every call that uses them
+fn variables() -> &'static [(&'static CStr, CString)] {
+    static VARIABLES: OnceLock<Vec<(&'static CStr, CString)>> = OnceLock::new();
+    VARIABLES.get_or_init(|| {
+        let speeds: Vec<String> = SPEEDS.iter().map(usize::to_string).collect();
+        let palettes: Vec<String> = Palette::builtin().iter().map(|palette| palette.name().to_string()).collect();
+        vec![
+            (c"chip8_quirks", c"Quirks, applied on restart; auto|vip|chip48".to_owned()),
+            (c"chip8_speed", CString::new(format!("Instructions per frame; {}", speeds.join("|"))).unwrap()),
+            (c"chip8_palette", CString::new(format!("Palette; {}", palettes.join("|"))).unwrap()),
+        ]
+    })
+}
+
+/// Reads the core options from the frontend, keeping the current value of any it doesn't have
+/// or that is invalid
+unsafe fn read_options(environment: RetroEnvironment, options: &CoreOptions) -> CoreOptions {
+    let mut options = options.clone();
+    for (key, _) in variables() {
+        let mut variable = RetroVariable { key: key.as_ptr(), value: ptr::null() };
+        if !environment(RETRO_ENVIRONMENT_GET_VARIABLE, &mut variable as *mut _ as *mut c_void) || variable.value.is_null() {
+            continue;
+        }
+
+        let Ok(value) = CStr::from_ptr(variable.value).to_str() else { continue };
+        match key.to_bytes() {
+            b"chip8_quirks" => options.emulator_type = value.parse().ok(),
+            b"chip8_speed" => options.instructions_per_frame = value.parse().unwrap_or(options.instructions_per_frame),
+            b"chip8_palette" => options.palette = Palette::named(value).unwrap_or(options.palette),
+            _ => {}
+        }
+    }
+
+    options
+}
+
+/// Gets the version of the libretro API implemented
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+/// Sets the callback for talking to the frontend, and declares the core options with it
+///
+/// # Safety
+///
+/// `environment` must be NULL or a valid callback
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(environment: Option<RetroEnvironment>) {
+    CALLBACKS.lock().unwrap().environment = environment;
+    let Some(environment) = environment else { return };
+
+    let mut declarations: Vec<RetroVariable> = variables().iter()
+        .map(|(key, value)| RetroVariable { key: key.as_ptr(), value: value.as_ptr() })
+        .collect();
+    declarations.push(RetroVariable { key: ptr::null(), value: ptr::null() });
+    environment(RETRO_ENVIRONMENT_SET_VARIABLES, declarations.as_mut_ptr() as *mut c_void);
+}
+
+/// Sets the callback that takes each frame's picture
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: Option<RetroVideoRefresh>) {
+    CALLBACKS.lock().unwrap().video_refresh = video_refresh;
+}
+
+/// Unused, since sound is sent a frame at a time with the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: Option<RetroAudioSample>) {}
+
+/// Sets the callback that takes each frame's sound
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: Option<RetroAudioSampleBatch>) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = audio_sample_batch;
+}
+
+/// Sets the callback that reads the controls before they're queried
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: Option<RetroInputPoll>) {
+    CALLBACKS.lock().unwrap().input_poll = input_poll;
+}
+
+/// Sets the callback that queries whether a button or key is held down
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: Option<RetroInputState>) {
+    CALLBACKS.lock().unwrap().input_state = input_state;
+}
+
+/// Initializes the core, which has nothing to do until a game is loaded
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+/// Drops the loaded game, if any
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// Describes the core
+///
+/// # Safety
+///
+/// `info` must point to a writable system info
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    static VERSION: OnceLock<CString> = OnceLock::new();
+    let version = VERSION.get_or_init(|| CString::new(env!("CARGO_PKG_VERSION")).unwrap());
+
+    *info = RetroSystemInfo {
+        library_name: c"CHIP-8".as_ptr(),
+        library_version: version.as_ptr(),
+        valid_extensions: c"ch8|c8|rom".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// Gets the picture size and timing
+///
+/// # Safety
+///
+/// `info` must point to a writable AV info
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: DISPLAY_WIDTH as c_uint,
+            base_height: DISPLAY_HEIGHT as c_uint,
+            max_width: DISPLAY_WIDTH as c_uint,
+            max_height: DISPLAY_HEIGHT as c_uint,
+            aspect_ratio: DISPLAY_WIDTH as f32 / DISPLAY_HEIGHT as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: FRAMES_PER_SECOND as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+/// Ignored, since the joypad and keyboard are always read from port 0
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Restarts the game, applying the quirks option
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.reset();
+    }
+}
+
+/// Runs one frame, reading the controls and sending the picture and sound to the frontend.
+/// Joypad and keyboard are both mapped to the keypad. A ROM that executes an invalid
+/// instruction stops running until it's reset
+///
+/// # Safety
+///
+/// The callbacks must be valid
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    let callbacks = callbacks();
+    let mut core = CORE.lock().unwrap();
+    let Some(core) = core.as_mut() else { return };
+
+    if let Some(environment) = callbacks.environment {
+        let mut updated = false;
+        if environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void) && updated {
+            core.options = read_options(environment, &core.options);
+        }
+    }
+
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    let mut keys = [false; 16];
+    if let Some(input_state) = callbacks.input_state {
+        for (id, key) in JOYPAD.iter().enumerate() {
+            keys[key.value() as usize] |= input_state(0, RETRO_DEVICE_JOYPAD, 0, id as c_uint) != 0;
+        }
+        for (code, key) in KEYBOARD {
+            keys[key.value() as usize] |= input_state(0, RETRO_DEVICE_KEYBOARD, 0, code as c_uint) != 0;
+        }
+    }
+
+    core.run_frame(keys);
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        video_refresh(core.pixels.as_ptr() as *const c_void, DISPLAY_WIDTH as c_uint, DISPLAY_HEIGHT as c_uint, DISPLAY_WIDTH * 4);
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        audio_sample_batch(core.samples.as_ptr(), SAMPLES_PER_FRAME);
+    }
+}
+
+/// Gets the size in bytes of a saved state
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    chip8_state_size()
+}
+
+/// Saves the interpreter's state, including its random number generator
+///
+/// # Safety
+///
+/// `data` must point to `size` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let core = CORE.lock().unwrap();
+    let Some(core) = core.as_ref() else { return false };
+    if size < chip8_state_size() {
+        return false;
+    }
+
+    save_state(&core.chip8, slice::from_raw_parts_mut(data as *mut u8, chip8_state_size()));
+    true
+}
+
+/// Restores a state saved with [`retro_serialize`]
+///
+/// # Safety
+///
+/// `data` must point to `size` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut core = CORE.lock().unwrap();
+    let Some(core) = core.as_mut() else { return false };
+    if size < chip8_state_size() || !load_state(&mut core.chip8, slice::from_raw_parts(data as *const u8, chip8_state_size())) {
+        return false;
+    }
+
+    core.crashed = false;
+    true
+}
+
+/// Ignored, since cheats aren't supported
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+/// Ignored, since cheats aren't supported
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// Loads a ROM from memory, reading the core options
+///
+/// # Safety
+///
+/// `game` must be NULL or point to a game whose data is `size` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let callbacks = callbacks();
+    let Some(game) = game.as_ref() else { return false };
+    if game.data.is_null() || game.size > MAX_PROGRAM_SIZE {
+        return false;
+    }
+
+    let mut options = CoreOptions::default();
+    if let Some(environment) = callbacks.environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_int as *mut c_void) {
+            return false;
+        }
+        options = read_options(environment, &options);
+    }
+
+    let program = slice::from_raw_parts(game.data as *const u8, game.size);
+    *CORE.lock().unwrap() = Some(Core::new(program, options));
+    true
+}
+
+/// Fails, since there are no special game types
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const RetroGameInfo, _info_count: usize) -> bool {
+    false
+}
+
+/// Drops the loaded game
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// Gets the region, NTSC since the timers run at 60 Hz
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// Memory isn't exposed, since the interpreter's can only be written through its methods
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+/// Returns 0, since memory isn't exposed
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use super::*;
+
+    /// Draws a 0 at the top left, then starts a beep once key 5 is held
+    const PROGRAM: [u8; 18] = [
+        0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x6A, 0x05,
+        0xEA, 0x9E, 0x12, 0x08, 0x6B, 0x10, 0xFB, 0x18,
+        0x12, 0x10,
+    ];
+
+    /// Serializes tests, since the core is global
+    static LOCK: Mutex<()> = Mutex::new(());
+    static DECLARED_OPTIONS: AtomicUsize = AtomicUsize::new(0);
+    static PIXELS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+    static LOUDEST_SAMPLE: AtomicUsize = AtomicUsize::new(0);
+    static PRESSING_A: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "C" fn environment(command: c_uint, data: *mut c_void) -> bool {
+        match command {
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => *(data as *const c_int) == RETRO_PIXEL_FORMAT_XRGB8888,
+            RETRO_ENVIRONMENT_SET_VARIABLES => {
+                let variables = data as *const RetroVariable;
+                let count = (0..).take_while(|index| !(*variables.add(*index)).key.is_null()).count();
+                DECLARED_OPTIONS.store(count, Ordering::SeqCst);
+                true
+            }
+            RETRO_ENVIRONMENT_GET_VARIABLE => {
+                let variable = &mut *(data as *mut RetroVariable);
+                if CStr::from_ptr(variable.key) == c"chip8_palette" {
+                    variable.value = c"lcd".as_ptr();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    unsafe extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+        assert_eq!((DISPLAY_WIDTH * 4, DISPLAY_WIDTH * DISPLAY_HEIGHT), (pitch, (width * height) as usize));
+        *PIXELS.lock().unwrap() = slice::from_raw_parts(data as *const u32, (width * height) as usize).to_vec();
+    }
+
+    unsafe extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+        let loudest = slice::from_raw_parts(data, frames * 2).iter().map(|sample| sample.unsigned_abs() as usize).max().unwrap();
+        LOUDEST_SAMPLE.store(loudest, Ordering::SeqCst);
+        frames
+    }
+
+    unsafe extern "C" fn input_state(_port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+        (device == RETRO_DEVICE_JOYPAD && id == 8 && PRESSING_A.load(Ordering::SeqCst)) as i16
+    }
+
+    /// Loads the test ROM with the test callbacks
+    unsafe fn load() {
+        retro_set_environment(Some(environment));
+        retro_set_video_refresh(Some(video_refresh));
+        retro_set_audio_sample_batch(Some(audio_sample_batch));
+        retro_set_input_state(Some(input_state));
+        retro_init();
+
+        let game = RetroGameInfo { path: ptr::null(), data: PROGRAM.as_ptr() as *const c_void, size: PROGRAM.len(), meta: ptr::null() };
+        assert!(retro_load_game(&game));
+    }
+
+    #[test]
+    fn runs_games_through_the_callbacks() {
+        let _lock = LOCK.lock().unwrap();
+        unsafe {
+            load();
+            PRESSING_A.store(false, Ordering::SeqCst);
+            retro_run();
+
+            assert_eq!(3, DECLARED_OPTIONS.load(Ordering::SeqCst));
+            let pixels = PIXELS.lock().unwrap().clone();
+            assert_eq!(0x000F380F, pixels[0]);
+            assert_eq!(0x009BBC0F, pixels[4]);
+            assert_eq!(0, LOUDEST_SAMPLE.load(Ordering::SeqCst));
+
+            PRESSING_A.store(true, Ordering::SeqCst);
+            retro_run();
+            assert!(LOUDEST_SAMPLE.load(Ordering::SeqCst) > 1000);
+
+            retro_deinit();
+        }
+    }
+
+    #[test]
+    fn restores_serialized_states() {
+        let _lock = LOCK.lock().unwrap();
+        unsafe {
+            load();
+            PRESSING_A.store(true, Ordering::SeqCst);
+            retro_run();
+
+            let mut state = vec![0u8; retro_serialize_size()];
+            assert!(retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()));
+            retro_reset();
+            assert!(retro_unserialize(state.as_ptr() as *const c_void, state.len()));
+
+            let mut restored = vec![0u8; retro_serialize_size()];
+            assert!(retro_serialize(restored.as_mut_ptr() as *mut c_void, restored.len()));
+            assert_eq!(state, restored);
+            assert!(!retro_unserialize(state.as_ptr() as *const c_void, state.len() - 1));
+
+            retro_unload_game();
+            assert!(!retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()));
+        }
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use crate::instruction::{disassemble, DisassembledInstruction, Instruction};
+use crate::{FONT_START_ADDRESS, MEMORY_SIZE, PROGRAM_START_ADDRESS};
+
+/// The number of data bytes listed per line
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// How control passes from one basic block to another
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction
+    FallThrough,
+    /// A 1NNN jump
+    Jump,
+    /// A skip instruction skipped the next instruction
+    Skip,
+    /// A 2NNN call into a subroutine
+    Call,
+}
+
+/// An edge of the control flow graph between the basic blocks starting at two addresses
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Edge {
+    /// The start of the block control passes from
+    pub from: u16,
+    /// The start of the block control passes to
+    pub to: u16,
+    /// How control passes
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions that's only ever entered at its first instruction and left at its last
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BasicBlock {
+    /// The instructions in the block in order
+    pub instructions: Vec<DisassembledInstruction>,
+}
+
+impl BasicBlock {
+    /// Gets the address of the first instruction
+    pub fn start(&self) -> u16 {
+        self.instructions[0].address
+    }
+
+    /// Gets the address just past the last instruction
+    pub fn end(&self) -> u16 {
+        self.instructions[self.instructions.len() - 1].address + 2
+    }
+}
+
+/// Something static analysis couldn't resolve, so the code and data it found may be incomplete
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Unknown {
+    /// A BNNN jump whose target depends on V0 at run time
+    ComputedJump { address: u16 },
+    /// A memory write whose target overlaps code that was found
+    SelfModifyingWrite { address: u16, target: u16 },
+    /// A memory write whose target depends on values only known at run time
+    UnresolvedWrite { address: u16 },
+    /// An opcode that isn't a valid instruction was reached
+    InvalidOpcode { address: u16, opcode: u16 },
+}
+
+impl Unknown {
+    /// Gets the address of the instruction that couldn't be resolved
+    pub fn address(&self) -> u16 {
+        match *self {
+            Unknown::ComputedJump { address }
+            | Unknown::SelfModifyingWrite { address, .. }
+            | Unknown::UnresolvedWrite { address }
+            | Unknown::InvalidOpcode { address, .. } => address,
+        }
+    }
+}
+
+impl std::fmt::Display for Unknown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Unknown::ComputedJump { address } => write!(f, "{address:03X}: computed jump"),
+            Unknown::SelfModifyingWrite { address, target } => write!(f, "{address:03X}: writes to code at {target:03X}"),
+            Unknown::UnresolvedWrite { address } => write!(f, "{address:03X}: writes to an unknown address"),
+            Unknown::InvalidOpcode { address, opcode } => write!(f, "{address:03X}: invalid opcode {opcode:04X}"),
+        }
+    }
+}
+
+/// A static analysis of a program that separates code from data by following every path
+/// execution can take from the start address, through jumps, calls, skips and returns
+pub struct Analysis {
+    memory: Vec<u8>,
+    program_length: usize,
+    /// The basic blocks found, by start address
+    blocks: BTreeMap<u16, BasicBlock>,
+    edges: Vec<Edge>,
+    /// The entry addresses of subroutines, found from calls
+    subroutines: BTreeSet<u16>,
+    /// Addresses loaded into the index register that aren't code, most likely sprites
+    data_references: BTreeSet<u16>,
+    /// Whether each byte of memory is part of an instruction that was found
+    code: Vec<bool>,
+    unknowns: Vec<Unknown>,
+}
+
+impl Analysis {
+    /// Analyzes a program as it would be loaded at the usual start address
+    pub fn analyze(program: &[u8]) -> Self {
+        let mut memory = vec![0; MEMORY_SIZE];
+        let start = PROGRAM_START_ADDRESS as usize;
+        let program_length = program.len().min(MEMORY_SIZE - start);
+        memory[start..start + program_length].copy_from_slice(&program[..program_length]);
+
+        let mut analysis = Self {
+            memory,
+            program_length,
+            blocks: BTreeMap::new(),
+            edges: Vec::new(),
+            subroutines: BTreeSet::new(),
+            data_references: BTreeSet::new(),
+            code: vec![false; MEMORY_SIZE],
+            unknowns: Vec::new(),
+        };
+
+        let (instructions, leaders) = analysis.explore();
+        analysis.build_blocks(&instructions, &leaders);
+        analysis.find_unknowns();
+        analysis
+    }
+
+    /// Gets the basic blocks in address order
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// Gets the edges between basic blocks
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Gets the entry addresses of subroutines in address order
+    pub fn subroutines(&self) -> impl Iterator<Item = u16> + '_ {
+        self.subroutines.iter().copied()
+    }
+
+    /// Gets what couldn't be resolved, in address order
+    pub fn unknowns(&self) -> &[Unknown] {
+        &self.unknowns
+    }
+
+    /// Returns whether the byte at an address is part of an instruction that can be reached
+    pub fn is_code(&self, address: u16) -> bool {
+        self.code.get(address as usize).copied().unwrap_or(false)
+    }
+
+    /// Gets the automatic name of an address: `start` for the start address, `sub_XXX` for
+    /// subroutines, `label_XXX` for other block starts and `data_XXX` for data the index register
+    /// is pointed at
+    pub fn label(&self, address: u16) -> Option<String> {
+        if address == PROGRAM_START_ADDRESS {
+            Some("start".to_string())
+        } else if self.subroutines.contains(&address) {
+            Some(format!("sub_{address:03X}"))
+        } else if self.blocks.contains_key(&address) {
+            Some(format!("label_{address:03X}"))
+        } else if self.data_references.contains(&address) {
+            Some(format!("data_{address:03X}"))
+        } else {
+            None
+        }
+    }
+
+    /// Writes a listing of the program with code disassembled under its labels and everything else
+    /// as data bytes, preceded by what couldn't be resolved
+    pub fn write_listing<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for unknown in &self.unknowns {
+            writeln!(writer, "; unknown: {unknown}")?;
+        }
+
+        let start = PROGRAM_START_ADDRESS as usize;
+        let end = start + self.program_length;
+        let mut address = start;
+        while address < end {
+            if let Some(label) = self.label(address as u16) {
+                writeln!(writer, "{label}:")?;
+            }
+
+            if self.is_instruction_start(address as u16) {
+                let instruction = disassemble(&self.memory, address as u16, 1)[0];
+                write!(writer, "    {:03X}  {:04X}  {}", address, instruction.opcode, instruction.instruction)?;
+                match target(&instruction.instruction).and_then(|target| self.label(target)) {
+                    Some(label) => writeln!(writer, "  ; {label}")?,
+                    None => writeln!(writer)?,
+                }
+                address += 2;
+            } else {
+                let line_start = address;
+                let mut bytes = Vec::new();
+                while address < end
+                    && bytes.len() < DATA_BYTES_PER_LINE
+                    && !self.is_instruction_start(address as u16)
+                    && (address == line_start || self.label(address as u16).is_none()) {
+                    bytes.push(format!("0x{:02X}", self.memory[address]));
+                    address += 1;
+                }
+                writeln!(writer, "    {:03X}        DB {}", line_start, bytes.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the control flow graph in Graphviz DOT format, one node per basic block, with
+    /// blocks containing something unresolved outlined in red
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "digraph cfg {{")?;
+        writeln!(writer, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        for block in self.blocks.values() {
+            let name = self.block_name(block.start());
+            let mut label = format!("{name}:\\l");
+            for instruction in &block.instructions {
+                label.push_str(&format!("{:03X}  {}\\l", instruction.address, instruction.instruction));
+            }
+
+            let has_unknown = self.unknowns.iter()
+                .any(|unknown| (block.start()..block.end()).contains(&unknown.address()));
+            let color = if has_unknown { ", color=red" } else { "" };
+            writeln!(writer, "    \"{name}\" [label=\"{label}\"{color}];")?;
+        }
+
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::FallThrough => "",
+                EdgeKind::Jump => " [label=\"jump\"]",
+                EdgeKind::Skip => " [label=\"skip\"]",
+                EdgeKind::Call => " [label=\"call\", style=dashed]",
+            };
+            writeln!(writer, "    \"{}\" -> \"{}\"{attributes};", self.block_name(edge.from), self.block_name(edge.to))?;
+        }
+
+        writeln!(writer, "}}")
+    }
+
+    /// Follows every path from the start address, returning the instructions found by address
+    /// and the addresses that start basic blocks
+    fn explore(&mut self) -> (BTreeMap<u16, DisassembledInstruction>, BTreeSet<u16>) {
+        let mut instructions = BTreeMap::new();
+        let mut leaders = BTreeSet::from([PROGRAM_START_ADDRESS]);
+        let mut worklist = vec![PROGRAM_START_ADDRESS];
+
+        while let Some(address) = worklist.pop() {
+            if instructions.contains_key(&address) {
+                continue;
+            }
+            let Some(instruction) = disassemble(&self.memory, address, 1).first().copied() else { continue };
+            instructions.insert(address, instruction);
+            self.code[address as usize] = true;
+            self.code[address as usize + 1] = true;
+
+            match instruction.instruction {
+                Instruction::Call { nnn } => {
+                    self.subroutines.insert(nnn);
+                }
+                Instruction::SetIndex { nnn } => {
+                    self.data_references.insert(nnn);
+                }
+                _ => {}
+            }
+
+            let successors = successors(&instruction);
+            if ends_block(&instruction) {
+                leaders.extend(successors.iter().map(|(target, _)| *target));
+            }
+            worklist.extend(successors.iter().map(|(target, _)| *target));
+        }
+
+        self.data_references.retain(|address| !self.code[*address as usize % MEMORY_SIZE]);
+        (instructions, leaders)
+    }
+
+    /// Splits the instructions found into basic blocks and connects them
+    fn build_blocks(&mut self, instructions: &BTreeMap<u16, DisassembledInstruction>, leaders: &BTreeSet<u16>) {
+        for &leader in leaders.iter().filter(|leader| instructions.contains_key(leader)) {
+            let mut block = BasicBlock { instructions: Vec::new() };
+            let mut address = leader;
+
+            loop {
+                let instruction = instructions[&address];
+                block.instructions.push(instruction);
+
+                if ends_block(&instruction) {
+                    for (target, kind) in successors(&instruction) {
+                        if instructions.contains_key(&target) {
+                            self.edges.push(Edge { from: leader, to: target, kind });
+                        }
+                    }
+                    break;
+                }
+
+                address += 2;
+                if leaders.contains(&address) {
+                    self.edges.push(Edge { from: leader, to: address, kind: EdgeKind::FallThrough });
+                    break;
+                }
+                if !instructions.contains_key(&address) {
+                    break;
+                }
+            }
+
+            self.blocks.insert(leader, block);
+        }
+    }
+
+    /// Finds computed jumps, invalid opcodes and writes that can't be resolved or that overwrite
+    /// code, following the index register through each block where it's set to a constant
+    fn find_unknowns(&mut self) {
+        let mut unknowns = Vec::new();
+
+        for block in self.blocks.values() {
+            let mut index = None;
+            for instruction in &block.instructions {
+                let address = instruction.address;
+                let written = match instruction.instruction {
+                    Instruction::StoreRegisters { x } => Some(x as u16 + 1),
+                    Instruction::StoreDecimalDigits { .. } => Some(3),
+                    _ => None,
+                };
+
+                if let Some(length) = written {
+                    match index {
+                        Some(index) => {
+                            if let Some(target) = (index..index + length).find(|target| self.is_code(*target)) {
+                                unknowns.push(Unknown::SelfModifyingWrite { address, target });
+                            }
+                        }
+                        None => unknowns.push(Unknown::UnresolvedWrite { address }),
+                    }
+                }
+
+                index = match instruction.instruction {
+                    Instruction::SetIndex { nnn } => Some(nnn),
+                    Instruction::SetIndexToFontCharacter { .. } => Some(FONT_START_ADDRESS as u16),
+                    // The VIP moves the index register past the registers stored or loaded
+                    Instruction::AddVxToIndex { .. }
+                    | Instruction::StoreRegisters { .. }
+                    | Instruction::LoadRegisters { .. } => None,
+                    _ => index,
+                };
+
+                match instruction.instruction {
+                    Instruction::JumpWithOffset { .. } => unknowns.push(Unknown::ComputedJump { address }),
+                    Instruction::Invalid { opcode } => unknowns.push(Unknown::InvalidOpcode { address, opcode }),
+                    _ => {}
+                }
+            }
+        }
+
+        unknowns.sort_by_key(Unknown::address);
+        self.unknowns = unknowns;
+    }
+
+    /// Returns whether an instruction that was found starts at an address
+    fn is_instruction_start(&self, address: u16) -> bool {
+        self.blocks.range(..=address)
+            .next_back()
+            .is_some_and(|(_, block)| block.instructions.iter().any(|instruction| instruction.address == address))
+    }
+
+    /// Gets the name of the block starting at an address
+    fn block_name(&self, address: u16) -> String {
+        self.label(address).unwrap_or_else(|| format!("label_{address:03X}"))
+    }
+}
+
+/// Gets the addresses execution can continue at after an instruction and how it gets there.
+/// Returns, computed jumps and invalid opcodes have no successors that can be known statically
+fn successors(instruction: &DisassembledInstruction) -> Vec<(u16, EdgeKind)> {
+    let next = instruction.address + 2;
+    match instruction.instruction {
+        Instruction::Jump { nnn } => vec![(nnn, EdgeKind::Jump)],
+        Instruction::Call { nnn } => vec![(nnn, EdgeKind::Call), (next, EdgeKind::FallThrough)],
+        Instruction::Return | Instruction::JumpWithOffset { .. } | Instruction::Invalid { .. } => Vec::new(),
+        instruction if instruction.is_skip() => vec![(next, EdgeKind::FallThrough), (next + 2, EdgeKind::Skip)],
+        _ => vec![(next, EdgeKind::FallThrough)],
+    }
+}
+
+/// Returns whether an instruction transfers control somewhere other than the next instruction, and
+/// so ends a basic block
+fn ends_block(instruction: &DisassembledInstruction) -> bool {
+    successors(instruction) != [(instruction.address + 2, EdgeKind::FallThrough)]
+}
+
+/// Gets the address an instruction refers to, if it refers to one
+fn target(instruction: &Instruction) -> Option<u16> {
+    match *instruction {
+        Instruction::Jump { nnn }
+        | Instruction::Call { nnn }
+        | Instruction::SetIndex { nnn }
+        | Instruction::JumpWithOffset { nnn } => Some(nnn),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls a subroutine that overwrites the call, then branches on V0 into a loop or a sprite
+    /// draw followed by a computed jump, with sprite data at the end
+    const PROGRAM: [u8; 22] = [
+        0xA2, 0x14, 0x22, 0x0E, 0x30, 0x01, 0x12, 0x0A,
+        0x12, 0x08, 0xD0, 0x01, 0xB2, 0x00, 0xA2, 0x02,
+        0xF0, 0x55, 0x00, 0xEE, 0xF0, 0x90,
+    ];
+
+    #[test]
+    fn separates_code_from_data() {
+        let analysis = Analysis::analyze(&PROGRAM);
+
+        assert!(analysis.is_code(0x200));
+        assert!(analysis.is_code(0x213));
+        assert!(!analysis.is_code(0x214));
+        assert_eq!(vec![0x20E], analysis.subroutines().collect::<Vec<_>>());
+        assert_eq!(Some("data_214".to_string()), analysis.label(0x214));
+        assert_eq!(Some("label_208".to_string()), analysis.label(0x208));
+        assert_eq!(None, analysis.label(0x202));
+    }
+
+    #[test]
+    fn builds_the_control_flow_graph() {
+        let analysis = Analysis::analyze(&PROGRAM);
+
+        let starts: Vec<u16> = analysis.blocks().map(BasicBlock::start).collect();
+        assert_eq!(vec![0x200, 0x204, 0x206, 0x208, 0x20A, 0x20E], starts);
+        assert_eq!(vec![
+            Edge { from: 0x200, to: 0x20E, kind: EdgeKind::Call },
+            Edge { from: 0x200, to: 0x204, kind: EdgeKind::FallThrough },
+            Edge { from: 0x204, to: 0x206, kind: EdgeKind::FallThrough },
+            Edge { from: 0x204, to: 0x208, kind: EdgeKind::Skip },
+            Edge { from: 0x206, to: 0x20A, kind: EdgeKind::Jump },
+            Edge { from: 0x208, to: 0x208, kind: EdgeKind::Jump },
+        ], analysis.edges());
+    }
+
+    #[test]
+    fn flags_computed_jumps_and_self_modifying_writes() {
+        let analysis = Analysis::analyze(&PROGRAM);
+
+        assert_eq!(&[
+            Unknown::ComputedJump { address: 0x20C },
+            Unknown::SelfModifyingWrite { address: 0x210, target: 0x202 },
+        ], analysis.unknowns());
+    }
+
+    #[test]
+    fn writes_listings_and_dot_graphs() {
+        let analysis = Analysis::analyze(&PROGRAM);
+        let mut listing = Vec::new();
+        let mut dot = Vec::new();
+
+        analysis.write_listing(&mut listing).unwrap();
+        analysis.write_dot(&mut dot).unwrap();
+
+        let listing = String::from_utf8(listing).unwrap();
+        assert!(listing.contains("start:\n    200  A214  LD I, 0x214  ; data_214\n    202  220E  CALL 0x20E  ; sub_20E\n"));
+        assert!(listing.contains("data_214:\n    214        DB 0xF0, 0x90\n"));
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("    \"start\" -> \"sub_20E\" [label=\"call\", style=dashed];\n"));
+        assert!(dot.contains("    \"label_20A\" [label=\"label_20A:\\l20A  DRW V0, V0, 1\\l20C  JP V0, 0x200\\l\", color=red];\n"));
+    }
+}
@@ -0,0 +1,186 @@
+#[cfg(feature = "export")]
+use std::fs::File;
+#[cfg(feature = "export")]
+use std::io::{self, BufWriter, Seek, Write};
+#[cfg(feature = "export")]
+use std::path::Path;
+
+/// The sample rate of exported audio in Hz
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// The number of emulated frames per second. The timers tick once per frame
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+/// The number of samples written for each emulated frame
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FRAMES_PER_SECOND) as usize;
+
+/// Generates the buzzer's tone one sample at a time at [`SAMPLE_RATE`]. The tone is phase
+/// continuous across calls so consecutive beeps don't click.
+#[derive(Debug, Clone)]
+pub struct BuzzerTone {
+    frequency: f32,
+    amplitude: f32,
+    /// The number of samples generated so far, used as the time base for the tone
+    sample_index: u64,
+}
+
+impl BuzzerTone {
+    /// Creates a tone with the given frequency in Hz and amplitude from 0 to 1
+    pub fn new(frequency: f32, amplitude: f32) -> Self {
+        Self {
+            frequency,
+            amplitude,
+            sample_index: 0,
+        }
+    }
+
+    /// Generates the next sample, the tone if the buzzer is sounding, otherwise silence
+    pub fn next_sample(&mut self, is_playing_sound: bool) -> i16 {
+        let sample = if is_playing_sound { self.tone_sample() } else { 0 };
+        self.sample_index += 1;
+        sample
+    }
+
+    /// Returns the number of samples generated so far
+    pub fn samples_generated(&self) -> u64 {
+        self.sample_index
+    }
+
+    /// Computes the tone's sample at the current sample index
+    fn tone_sample(&self) -> i16 {
+        let t = self.sample_index as f64 / SAMPLE_RATE as f64;
+        let value = (t * self.frequency as f64 * 2.0 * std::f64::consts::PI).sin();
+
+        (self.amplitude as f64 * value * i16::MAX as f64) as i16
+    }
+}
+
+#[cfg(feature = "export")]
+/// Writes the buzzer output of an emulated session to a 16-bit mono WAV stream, one frame at a
+/// time
+pub struct BuzzerWavWriter<W: Write + Seek> {
+    writer: hound::WavWriter<W>,
+    tone: BuzzerTone,
+}
+
+#[cfg(feature = "export")]
+impl BuzzerWavWriter<BufWriter<File>> {
+    /// Creates a WAV file at path that will hold a tone with the given frequency and amplitude
+    pub fn create<P: AsRef<Path>>(path: P, frequency: f32, amplitude: f32) -> io::Result<Self> {
+        let writer = hound::WavWriter::create(path, wav_spec()).map_err(to_io_error)?;
+
+        Ok(Self::from_writer(writer, frequency, amplitude))
+    }
+}
+
+#[cfg(feature = "export")]
+impl<W: Write + Seek> BuzzerWavWriter<W> {
+    /// Creates a new buzzer writer that writes WAV data to the given writer
+    pub fn new(writer: W, frequency: f32, amplitude: f32) -> io::Result<Self> {
+        let writer = hound::WavWriter::new(writer, wav_spec()).map_err(to_io_error)?;
+
+        Ok(Self::from_writer(writer, frequency, amplitude))
+    }
+
+    fn from_writer(writer: hound::WavWriter<W>, frequency: f32, amplitude: f32) -> Self {
+        Self {
+            writer,
+            tone: BuzzerTone::new(frequency, amplitude),
+        }
+    }
+
+    /// Writes one frame's worth of samples. Writes the tone if the buzzer is sounding, otherwise
+    /// silence
+    pub fn write_frame(&mut self, is_playing_sound: bool) -> io::Result<()> {
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = self.tone.next_sample(is_playing_sound);
+            self.writer.write_sample(sample).map_err(to_io_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of frames written so far
+    pub fn frames_written(&self) -> u64 {
+        self.tone.samples_generated() / SAMPLES_PER_FRAME as u64
+    }
+
+    /// Updates the WAV header and flushes the underlying writer
+    pub fn finalize(self) -> io::Result<()> {
+        self.writer.finalize().map_err(to_io_error)
+    }
+}
+
+#[cfg(feature = "export")]
+/// The spec for exported audio: 16-bit mono PCM at [`SAMPLE_RATE`]
+fn wav_spec() -> hound::WavSpec {
+    hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    }
+}
+
+#[cfg(feature = "export")]
+/// Converts a hound error into an io error so callers only have to deal with one error type
+fn to_io_error(error: hound::Error) -> io::Error {
+    match error {
+        hound::Error::IoError(error) => error,
+        error => io::Error::other(error),
+    }
+}
+
+#[cfg(all(test, feature = "export"))]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn read_samples(bytes: Vec<u8>) -> Vec<i16> {
+        let reader = hound::WavReader::new(Cursor::new(bytes)).unwrap();
+        reader.into_samples::<i16>().map(|sample| sample.unwrap()).collect()
+    }
+
+    #[test]
+    fn writes_one_frame_of_samples_per_call() {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = BuzzerWavWriter::new(&mut buffer, 440.0, 0.5).unwrap();
+
+        writer.write_frame(false).unwrap();
+        writer.write_frame(true).unwrap();
+        writer.write_frame(false).unwrap();
+        assert_eq!(3, writer.frames_written());
+        writer.finalize().unwrap();
+
+        let samples = read_samples(buffer.into_inner());
+        assert_eq!(3 * SAMPLES_PER_FRAME, samples.len());
+    }
+
+    #[test]
+    fn silent_frames_are_zero_and_sounding_frames_are_not() {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = BuzzerWavWriter::new(&mut buffer, 440.0, 0.5).unwrap();
+
+        writer.write_frame(false).unwrap();
+        writer.write_frame(true).unwrap();
+        writer.finalize().unwrap();
+
+        let samples = read_samples(buffer.into_inner());
+        assert!(samples[..SAMPLES_PER_FRAME].iter().all(|sample| *sample == 0));
+        assert!(samples[SAMPLES_PER_FRAME..].iter().any(|sample| *sample != 0));
+    }
+
+    #[test]
+    fn tone_respects_amplitude() {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = BuzzerWavWriter::new(&mut buffer, 440.0, 0.25).unwrap();
+
+        writer.write_frame(true).unwrap();
+        writer.finalize().unwrap();
+
+        let samples = read_samples(buffer.into_inner());
+        let max_sample = samples.iter().map(|sample| sample.unsigned_abs()).max().unwrap();
+        assert!(max_sample <= (i16::MAX as f32 * 0.25) as u16 + 1);
+        assert!(max_sample > (i16::MAX as f32 * 0.24) as u16);
+    }
+}
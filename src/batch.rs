@@ -0,0 +1,231 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use crate::{Chip8, Chip8Key, EmulatorType, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// The number of bytes each instance's frame buffer takes up in [`Chip8Batch::frame_buffers`]
+pub const FRAME_BUFFER_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
+
+/// Decides from an instance's state whether its episode is over
+type DonePredicate = Box<dyn Fn(&Chip8<StdRng>) -> bool + Send + Sync>;
+
+/// Runs many independent interpreters of the same program, stepping them all one frame at a time
+/// across threads. Each instance has its own random number generator, seeded by hashing the batch
+/// seed, its index and how many times it's been reset together, so a batch replays identically from the same
+/// seed and inputs whatever the number of threads. Frame buffers are gathered into one contiguous
+/// array of [`FRAME_BUFFER_SIZE`] bytes per instance after every step.
+pub struct Chip8Batch {
+    program: Vec<u8>,
+    emulator_type: EmulatorType,
+    instructions_per_frame: usize,
+    seed: u64,
+    machines: Vec<Chip8<StdRng>>,
+    /// The number of times each instance has been reset
+    episodes: Vec<u64>,
+    frame_buffers: Vec<u8>,
+    done: Vec<bool>,
+    is_done: Option<DonePredicate>,
+}
+
+impl Chip8Batch {
+    /// Creates `count` instances with the program loaded
+    pub fn new(program: &[u8], emulator_type: EmulatorType, count: usize, seed: u64, instructions_per_frame: usize) -> Self {
+        let mut batch = Self {
+            program: program.to_vec(),
+            emulator_type,
+            instructions_per_frame,
+            seed,
+            machines: Vec::with_capacity(count),
+            episodes: vec![0; count],
+            frame_buffers: vec![0; count * FRAME_BUFFER_SIZE],
+            done: vec![false; count],
+            is_done: None,
+        };
+        batch.machines = (0..count).map(|index| batch.new_machine(index)).collect();
+        batch
+    }
+
+    /// Sets how to tell an instance's episode is over, e.g. from a game over flag in memory. Any
+    /// instance that's done after a step is reset, ready for the next one. Without this instances
+    /// only reset when asked to
+    pub fn with_done<F: Fn(&Chip8<StdRng>) -> bool + Send + Sync + 'static>(mut self, is_done: F) -> Self {
+        self.is_done = Some(Box::new(is_done));
+        self
+    }
+
+    /// Gets the number of instances
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    /// Returns whether the batch has no instances
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    /// Gets an instance
+    pub fn machine(&self, index: usize) -> &Chip8<StdRng> {
+        &self.machines[index]
+    }
+
+    /// Gets the number of times an instance has been reset
+    pub fn episode(&self, index: usize) -> u64 {
+        self.episodes[index]
+    }
+
+    /// Gets every instance's frame buffer as of the last step, one after another
+    pub fn frame_buffers(&self) -> &[u8] {
+        &self.frame_buffers
+    }
+
+    /// Gets one instance's frame buffer as of the last step
+    pub fn frame_buffer(&self, index: usize) -> &[u8] {
+        &self.frame_buffers[index * FRAME_BUFFER_SIZE..(index + 1) * FRAME_BUFFER_SIZE]
+    }
+
+    /// Gets which instances finished an episode in the last step
+    pub fn done(&self) -> &[bool] {
+        &self.done
+    }
+
+    /// Runs one frame on every instance in parallel. `keys` holds each instance's keypad for the
+    /// frame, with bit `n` set if key `n` is held down. Instances whose episodes are over
+    /// afterwards are flagged in [`Chip8Batch::done`] and reset, so their frame buffers are the
+    /// last frame of the episode that ended and the next step starts a new one
+    pub fn step(&mut self, keys: &[u16]) {
+        assert_eq!(self.machines.len(), keys.len(), "expected keys for every instance");
+
+        let instructions_per_frame = self.instructions_per_frame;
+        let is_done = self.is_done.as_deref();
+        self.machines.par_iter_mut()
+            .zip(self.frame_buffers.par_chunks_mut(FRAME_BUFFER_SIZE))
+            .zip(self.done.par_iter_mut())
+            .zip(keys.par_iter())
+            .for_each(|(((chip8, frame_buffer), done), &keys)| {
+                for key in Chip8Key::ALL {
+                    if keys & (1 << key.value()) != 0 {
+                        chip8.key_down(key);
+                    } else {
+                        chip8.key_up(key);
+                    }
+                }
+
+                chip8.run_frame(instructions_per_frame);
+                frame_buffer.copy_from_slice(chip8.frame_buffer());
+                *done = is_done.is_some_and(|is_done| is_done(chip8));
+            });
+
+        for index in 0..self.machines.len() {
+            if self.done[index] {
+                self.reset(index);
+            }
+        }
+    }
+
+    /// Starts a new episode on an instance with a freshly seeded random number generator
+    pub fn reset(&mut self, index: usize) {
+        self.episodes[index] += 1;
+        self.machines[index] = self.new_machine(index);
+    }
+
+    /// Starts a new episode on every instance
+    pub fn reset_all(&mut self) {
+        for index in 0..self.machines.len() {
+            self.reset(index);
+        }
+    }
+
+    /// Creates an instance for the current episode of the given index
+    fn new_machine(&self, index: usize) -> Chip8<StdRng> {
+        let seed = mix(mix(mix(0, self.seed), index as u64), self.episodes[index]);
+        let mut chip8 = Chip8::new(self.emulator_type, StdRng::seed_from_u64(seed));
+        chip8.load_program(&self.program);
+        chip8
+    }
+}
+
+/// Hashes a value into a hash so far with a SplitMix64 step, so nearby inputs give unrelated seeds
+fn mix(hash: u64, value: u64) -> u64 {
+    let mut z = (hash ^ value).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Register;
+    use super::*;
+
+    /// Draws a random sprite each frame until key 0 (in V2) is held, then counts V1 up to 3 and ends
+    const PROGRAM: [u8; 16] = [
+        0x00, 0xE0, 0xC0, 0x0F, 0xF0, 0x29, 0xD0, 0x05,
+        0xE2, 0x9E, 0x12, 0x00, 0x71, 0x01, 0x12, 0x00,
+    ];
+
+    #[test]
+    fn steps_instances_independently() {
+        let mut batch = Chip8Batch::new(&PROGRAM, EmulatorType::CosmacVip, 3, 1, 10);
+
+        batch.step(&[0, 1, 0]);
+
+        assert_eq!(3 * FRAME_BUFFER_SIZE, batch.frame_buffers().len());
+        assert_eq!(0, batch.machine(0).register(Register::Variable(1)));
+        assert!(batch.machine(1).register(Register::Variable(1)) > 0);
+        assert_ne!(batch.frame_buffer(0), batch.frame_buffer(2));
+        assert_eq!(batch.machine(2).frame_buffer().as_slice(), batch.frame_buffer(2));
+    }
+
+    #[test]
+    fn replays_identically_from_the_same_seed() {
+        let mut first = Chip8Batch::new(&PROGRAM, EmulatorType::Chip48, 4, 7, 8);
+        let mut second = Chip8Batch::new(&PROGRAM, EmulatorType::Chip48, 4, 7, 8);
+
+        for _ in 0..10 {
+            first.step(&[0; 4]);
+            second.step(&[0; 4]);
+        }
+
+        assert_eq!(first.frame_buffers(), second.frame_buffers());
+    }
+
+    #[test]
+    fn replays_identically_whatever_the_number_of_threads() {
+        let run = || {
+            let mut batch = Chip8Batch::new(&PROGRAM, EmulatorType::Chip48, 64, 3, 24)
+                .with_done(|chip8| chip8.register(Register::Variable(1)) >= 3);
+            for step in 0..10 {
+                let keys: Vec<u16> = (0..64).map(|index| ((index + step) % 5 == 0) as u16).collect();
+                batch.step(&keys);
+            }
+            batch.frame_buffers().to_vec()
+        };
+        let one_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+
+        assert_eq!(one_thread.install(run), run());
+    }
+
+    #[test]
+    fn seeds_neighbouring_batches_differently() {
+        let first = Chip8Batch::new(&PROGRAM, EmulatorType::Chip48, 2, 1, 8);
+        let second = Chip8Batch::new(&PROGRAM, EmulatorType::Chip48, 2, 2, 8);
+
+        assert_ne!(first.machine(1).rng(), second.machine(0).rng());
+    }
+
+    #[test]
+    fn resets_instances_that_are_done() {
+        let mut batch = Chip8Batch::new(&PROGRAM, EmulatorType::CosmacVip, 2, 1, 24)
+            .with_done(|chip8| chip8.register(Register::Variable(1)) >= 3);
+
+        batch.step(&[1, 0]);
+        assert_eq!(&[true, false], batch.done());
+        assert_eq!(1, batch.episode(0));
+        assert_eq!(0, batch.machine(0).register(Register::Variable(1)));
+        assert_eq!(0x200, batch.machine(0).program_counter());
+
+        batch.step(&[0, 0]);
+        assert_eq!(&[false, false], batch.done());
+        assert_eq!(0, batch.episode(1));
+    }
+}
@@ -0,0 +1,86 @@
+//! Statically analyzes a ROM, separating code from data, and prints a labelled listing. Can also
+//! export the control flow graph for Graphviz.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use anyhow::{anyhow, bail, Context, Result};
+use chip_8_emulator::analysis::Analysis;
+use chip_8_emulator::command_line::{exit_on_error, next_value, parse_args};
+use chip_8_emulator::detection::Detection;
+
+/// The usage text printed when the arguments can't be parsed
+const USAGE: &str = "\
+usage: chip-8-analyze [OPTIONS] <ROM>
+
+Follows every path from 0x200 through jumps, calls, skips and returns and prints the ROM with
+reachable code disassembled under automatic labels and everything else as data. Computed jumps
+and writes into code are listed first, since code they lead to can't be found statically, along
+with the platform the ROM was most likely written for and the evidence for it.
+
+options:
+    --dot <PATH>           write the control flow graph in Graphviz DOT format";
+
+/// Options for the tool parsed from the command line
+struct Options {
+    rom_path: PathBuf,
+    dot_path: Option<PathBuf>,
+}
+
+impl Options {
+    /// Parses options from command line arguments, not including the program name
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut rom_path = None;
+        let mut dot_path = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || next_value(&mut args, &arg);
+            match arg.as_str() {
+                "--dot" => dot_path = Some(PathBuf::from(value()?)),
+                _ if arg.starts_with("--") => bail!("unknown option {arg}"),
+                _ if rom_path.is_none() => rom_path = Some(PathBuf::from(arg)),
+                _ => bail!("unexpected argument {arg}"),
+            }
+        }
+
+        Ok(Self { rom_path: rom_path.ok_or_else(|| anyhow!("no ROM given"))?, dot_path })
+    }
+}
+
+fn main() {
+    let options = parse_args(USAGE, Options::parse);
+    exit_on_error(run(&options));
+}
+
+/// Analyzes the ROM, printing the listing and writing the graph if requested
+fn run(options: &Options) -> Result<()> {
+    let program = std::fs::read(&options.rom_path)
+        .with_context(|| format!("failed to read {}", options.rom_path.display()))?;
+    let analysis = Analysis::analyze(&program);
+
+    let detection = Detection::from_analysis(&analysis);
+    let quirks = detection.quirks();
+    println!("; platform: {} ({:.0}% confidence), run as {:?}",
+             detection.platform,
+             detection.confidence * 100.0,
+             detection.emulator_type());
+    println!("; quirks: shift VY {}, FX55/FX65 increment I {}, BNNN uses VX {}, sprites wrap {}",
+             quirks.shift_uses_vy,
+             quirks.load_store_increments_index,
+             quirks.jump_uses_vx,
+             quirks.sprites_wrap);
+    for evidence in &detection.evidence {
+        println!("; evidence: {evidence}");
+    }
+
+    analysis.write_listing(&mut std::io::stdout().lock())?;
+
+    if let Some(path) = &options.dot_path {
+        let mut writer = BufWriter::new(File::create(path).with_context(|| format!("failed to create {}", path.display()))?);
+        analysis.write_dot(&mut writer)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
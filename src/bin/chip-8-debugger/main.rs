@@ -0,0 +1,148 @@
+//! A text debugger for Chip-8 programs with gdb-like commands. Commands are read interactively
+//! with line editing and history, or from script files given with `--script`.
+
+mod session;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use anyhow::{anyhow, bail, Context, Result};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use chip_8_emulator::command_line::{exit_on_error, next_value, parse_args, parse_value, seeded_rng};
+use chip_8_emulator::{Chip8, EmulatorType};
+use session::{Flow, Session};
+
+/// The usage text printed when the arguments can't be parsed
+const USAGE: &str = "\
+usage: chip-8-debugger [OPTIONS] <ROM>
+
+options:
+    --type <vip|chip48>    the emulator type to interpret instructions as (default: vip)
+    --speed <N>            instructions executed per 60 Hz frame (default: 12)
+    --seed <N>             seed the random number generator for reproducible runs
+    --script <FILE>        run the commands in FILE before reading commands interactively
+    --batch                exit after running the scripts instead of reading commands";
+
+/// The default number of instructions executed per frame
+const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 12;
+
+/// The prompt shown before each command
+const PROMPT: &str = "(chip-8) ";
+
+/// The file in the home directory that command history is kept in
+const HISTORY_FILE: &str = ".chip-8-debugger-history";
+
+/// Options for the debugger parsed from the command line
+struct Options {
+    rom_path: PathBuf,
+    emulator_type: EmulatorType,
+    instructions_per_frame: usize,
+    seed: Option<u64>,
+    scripts: Vec<PathBuf>,
+    batch: bool,
+}
+
+impl Options {
+    /// Parses options from command line arguments, not including the program name
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut rom_path = None;
+        let mut emulator_type = EmulatorType::CosmacVip;
+        let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+        let mut seed = None;
+        let mut scripts = Vec::new();
+        let mut batch = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = || next_value(&mut args, &arg);
+            match arg.as_str() {
+                "--type" => emulator_type = value()?.parse()?,
+                "--speed" => instructions_per_frame = parse_value(&value()?, &arg)?,
+                "--seed" => seed = Some(parse_value(&value()?, &arg)?),
+                "--script" => scripts.push(PathBuf::from(value()?)),
+                "--batch" => batch = true,
+                _ if arg.starts_with("--") => bail!("unknown option {arg}"),
+                _ if rom_path.is_none() => rom_path = Some(PathBuf::from(arg)),
+                _ => bail!("unexpected argument {arg}"),
+            }
+        }
+
+        Ok(Self {
+            rom_path: rom_path.ok_or_else(|| anyhow!("no ROM given"))?,
+            emulator_type,
+            instructions_per_frame,
+            seed,
+            scripts,
+            batch,
+        })
+    }
+}
+
+fn main() {
+    let options = parse_args(USAGE, Options::parse);
+    exit_on_error(run(options));
+}
+
+/// Loads the ROM, runs the scripts and then reads commands until the user quits
+fn run(options: Options) -> Result<()> {
+    let program = std::fs::read(&options.rom_path)
+        .with_context(|| format!("failed to read {}", options.rom_path.display()))?;
+
+    let mut chip8 = Chip8::new(options.emulator_type, seeded_rng(options.seed));
+    chip8.load_program(&program);
+
+    // Ctrl-C stops the program instead of exiting while it's running
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let handler_interrupt = interrupt.clone();
+    ctrlc::set_handler(move || handler_interrupt.store(true, Ordering::SeqCst))?;
+
+    let mut session = Session::new(chip8, options.instructions_per_frame, interrupt);
+    let mut out = std::io::stdout();
+
+    for script in &options.scripts {
+        if session.run_script(script, &mut out)? == Flow::Quit {
+            return Ok(());
+        }
+    }
+
+    if options.batch {
+        return Ok(());
+    }
+
+    let mut editor = DefaultEditor::new()?;
+    let history_path = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+    if let Some(path) = &history_path {
+        // There's no history the first time the debugger is run
+        let _ = editor.load_history(path);
+    }
+
+    let mut last_command = String::new();
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+
+        // An empty line repeats the last command, like in gdb
+        let command = if line.trim().is_empty() { last_command.clone() } else { line.trim().to_string() };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(&command)?;
+            last_command = command.clone();
+        }
+
+        match session.execute(&command, &mut out) {
+            Ok(Flow::Quit) => break,
+            Ok(Flow::Continue) => {}
+            Err(error) => eprintln!("error: {error:#}"),
+        }
+    }
+
+    if let Some(path) = &history_path {
+        editor.save_history(path)?;
+    }
+
+    Ok(())
+}
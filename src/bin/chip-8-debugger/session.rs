@@ -0,0 +1,489 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use anyhow::{anyhow, bail, Context, Result};
+use chip_8_emulator::debugger::{Debugger, RunOutcome};
+use chip_8_emulator::instruction::{disassemble, Instruction};
+use chip_8_emulator::{Chip8, Chip8Key, Chip8Rng, Register, DISPLAY_WIDTH};
+
+/// The help text printed by the `help` command
+const HELP: &str = "\
+break <ADDR>        stop before executing the instruction at ADDR (alias: b)
+delete <ADDR>       remove the breakpoint at ADDR
+info break          list breakpoints
+step [N]            execute N instructions, stepping into subroutines (alias: s)
+next [N]            execute N instructions, stepping over subroutine calls (alias: n)
+finish              run until the current subroutine returns
+continue            run until a breakpoint or Ctrl-C (alias: c)
+frames <N>          run N frames, stopping early at breakpoints
+regs                show the registers, timers and stack
+x/<N> <ADDR>        show N bytes of memory starting at ADDR
+disasm [ADDR] [N]   disassemble N instructions starting at ADDR, or around the program counter
+set <REG>=<VALUE>   set V0-VF, I, PC, SP, DT or ST
+press <KEY>         hold down a key, 0-F
+release <KEY>       let go of a key, 0-F
+screen              print the display
+source <FILE>       run the commands in FILE
+quit                exit (alias: q)
+
+Addresses and keys are hex. Counts and values are decimal, or hex with a 0x prefix.";
+
+/// The number of frames `continue` runs for before giving up, in case nothing ever stops it
+const CONTINUE_FRAME_LIMIT: usize = 60 * 60 * 10;
+
+/// The number of instructions `disasm` shows by default
+const DISASSEMBLY_LINES: usize = 10;
+
+/// Whether to keep reading commands after one has run
+#[derive(Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Read the next command
+    Continue,
+    /// Exit the debugger
+    Quit,
+}
+
+/// Why running stopped
+enum Stop {
+    /// The command's condition was met, e.g. a subroutine returned
+    Done,
+    /// Execution reached a breakpoint
+    Breakpoint,
+    /// The program counter points at an invalid opcode
+    InvalidOpcode { opcode: u16 },
+    /// The user pressed Ctrl-C
+    Interrupted,
+    /// The frame limit ran out
+    FrameLimit,
+}
+
+/// A debugging session for a single program, which runs commands and prints their results
+pub struct Session<R: Chip8Rng> {
+    chip8: Chip8<R>,
+    debugger: Debugger,
+    instructions_per_frame: usize,
+    /// Set from the Ctrl-C handler to stop running
+    interrupt: Arc<AtomicBool>,
+    /// The scripts being run, outermost first, so a script can't source itself
+    active_scripts: Vec<PathBuf>,
+}
+
+impl<R: Chip8Rng> Session<R> {
+    /// Creates a session for an interpreter with a program loaded. Running stops when interrupt is
+    /// set
+    pub fn new(chip8: Chip8<R>, instructions_per_frame: usize, interrupt: Arc<AtomicBool>) -> Self {
+        Self {
+            chip8,
+            debugger: Debugger::new(),
+            instructions_per_frame,
+            interrupt,
+            active_scripts: Vec::new(),
+        }
+    }
+
+    /// Runs a single command line, writing its output to out
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> Result<Flow> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else { return Ok(Flow::Continue) };
+        let arguments: Vec<&str> = words.collect();
+
+        match command {
+            "break" | "b" => {
+                let address = parse_address(argument(&arguments, 0)?)?;
+                self.debugger.add_breakpoint(address);
+                writeln!(out, "Breakpoint at {address:03X}")?;
+            }
+            "delete" | "d" => {
+                let address = parse_address(argument(&arguments, 0)?)?;
+                if !self.debugger.remove_breakpoint(address) {
+                    bail!("no breakpoint at {address:03X}");
+                }
+            }
+            "info" if arguments.first().is_some_and(|topic| topic.starts_with('b')) => {
+                for address in self.debugger.breakpoints() {
+                    writeln!(out, "{address:03X}")?;
+                }
+            }
+            "step" | "s" => {
+                for _ in 0..optional_count(&arguments, 0)? {
+                    if let RunOutcome::InvalidOpcode { opcode, .. } = self.debugger.step(&mut self.chip8, self.instructions_per_frame) {
+                        return self.report(Stop::InvalidOpcode { opcode }, out);
+                    }
+                }
+                self.report(Stop::Done, out)?;
+            }
+            "next" | "n" => {
+                for _ in 0..optional_count(&arguments, 0)? {
+                    let stop = self.next();
+                    if !matches!(stop, Stop::Done) {
+                        return self.report(stop, out);
+                    }
+                }
+                self.report(Stop::Done, out)?;
+            }
+            "finish" => {
+                let Some(depth) = self.chip8.stack_pointer().checked_sub(1) else { bail!("not in a subroutine") };
+                let stop = self.run_until(CONTINUE_FRAME_LIMIT, |chip8| chip8.stack_pointer() == depth);
+                self.report(stop, out)?;
+            }
+            "continue" | "c" => {
+                let stop = self.run_until(CONTINUE_FRAME_LIMIT, |_| false);
+                self.report(stop, out)?;
+            }
+            "frames" => {
+                let frames = parse_number(argument(&arguments, 0)?)?.into();
+                let stop = self.run_until(frames, |_| false);
+                self.report(stop, out)?;
+            }
+            "regs" => self.print_registers(out)?,
+            "disasm" => {
+                let address = match arguments.first() {
+                    Some(address) => parse_address(address)?,
+                    None => self.chip8.program_counter().saturating_sub(8),
+                };
+                let count = arguments.get(1).map_or(Ok(DISASSEMBLY_LINES), |count| parse_number(count).map(usize::from))?;
+                self.print_disassembly(address, count, out)?;
+            }
+            "set" => {
+                let (register, value) = arguments.concat().split_once('=')
+                    .map(|(register, value)| (register.to_string(), value.to_string()))
+                    .ok_or_else(|| anyhow!("usage: set <REG>=<VALUE>"))?;
+                self.chip8.set_register(parse_register(&register)?, parse_number(&value)?);
+            }
+            "press" => self.chip8.key_down(parse_key(argument(&arguments, 0)?)?),
+            "release" => self.chip8.key_up(parse_key(argument(&arguments, 0)?)?),
+            "screen" => self.print_screen(out)?,
+            "source" => return self.run_script(argument(&arguments, 0)?, out),
+            "help" | "h" => writeln!(out, "{HELP}")?,
+            "quit" | "q" => return Ok(Flow::Quit),
+            _ if command.starts_with("x/") || command == "x" => {
+                let count = match command.strip_prefix("x/") {
+                    Some(count) => parse_number(count)?.into(),
+                    None => 16,
+                };
+                self.print_memory(parse_address(argument(&arguments, 0)?)?, count, out)?;
+            }
+            _ => bail!("unknown command {command}, try help"),
+        }
+
+        Ok(Flow::Continue)
+    }
+
+    /// Runs each line of a command file, stopping at the first command that fails. Blank lines
+    /// and lines starting with `#` are skipped. A script that sources itself, directly or through
+    /// other scripts, is an error
+    pub fn run_script<P: AsRef<Path>, W: Write>(&mut self, path: P, out: &mut W) -> Result<Flow> {
+        let path = path.as_ref();
+        let script = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let canonical_path = path.canonicalize().with_context(|| format!("failed to read {}", path.display()))?;
+        if self.active_scripts.contains(&canonical_path) {
+            bail!("{} is already running", path.display());
+        }
+
+        self.active_scripts.push(canonical_path);
+        let result = self.run_script_lines(path, &script, out);
+        self.active_scripts.pop();
+        result
+    }
+
+    /// Runs the lines of a script read from path
+    fn run_script_lines<W: Write>(&mut self, path: &Path, script: &str, out: &mut W) -> Result<Flow> {
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let flow = self.execute(line, out).with_context(|| format!("{}:{}: {line}", path.display(), number + 1))?;
+            if flow == Flow::Quit {
+                return Ok(Flow::Quit);
+            }
+        }
+
+        Ok(Flow::Continue)
+    }
+
+    /// Executes one instruction, running the whole subroutine if it's a call
+    fn next(&mut self) -> Stop {
+        let program_counter = self.chip8.program_counter();
+        match disassemble(self.chip8.ram(), program_counter, 1).first().map(|line| line.instruction) {
+            Some(Instruction::Call { .. }) => {
+                let depth = self.chip8.stack_pointer();
+                self.run_until(CONTINUE_FRAME_LIMIT, |chip8| {
+                    chip8.stack_pointer() == depth && chip8.program_counter() == program_counter + 2
+                })
+            }
+            _ => self.run_until(usize::MAX, |_| true),
+        }
+    }
+
+    /// Executes instructions until stop returns true after one of them, a breakpoint or an invalid
+    /// opcode is reached, the user interrupts, or max_frames frames have completed. The
+    /// breakpoint at the starting address, if any, is ignored so that running can resume from it
+    fn run_until<F: Fn(&Chip8<R>) -> bool>(&mut self, max_frames: usize, stop: F) -> Stop {
+        self.interrupt.store(false, Ordering::SeqCst);
+        let mut frames = 0;
+        let mut first = true;
+
+        loop {
+            if !first && self.debugger.has_breakpoint(self.chip8.program_counter()) {
+                return Stop::Breakpoint;
+            }
+            first = false;
+
+            if frames >= max_frames {
+                return Stop::FrameLimit;
+            }
+
+            match self.debugger.step(&mut self.chip8, self.instructions_per_frame) {
+                RunOutcome::InvalidOpcode { opcode, .. } => return Stop::InvalidOpcode { opcode },
+                RunOutcome::FrameCompleted => {
+                    frames += 1;
+                    if self.interrupt.load(Ordering::SeqCst) {
+                        return Stop::Interrupted;
+                    }
+                }
+                _ => {}
+            }
+
+            if stop(&self.chip8) {
+                return Stop::Done;
+            }
+        }
+    }
+
+    /// Prints why running stopped, followed by the next instruction
+    fn report<W: Write>(&self, stop: Stop, out: &mut W) -> Result<Flow> {
+        let program_counter = self.chip8.program_counter();
+        match stop {
+            Stop::Done | Stop::FrameLimit => {}
+            Stop::Breakpoint => writeln!(out, "Breakpoint at {program_counter:03X}")?,
+            Stop::InvalidOpcode { opcode } => writeln!(out, "Invalid opcode {opcode:04X} at {program_counter:03X}")?,
+            Stop::Interrupted => writeln!(out, "Interrupted")?,
+        }
+
+        self.print_disassembly(program_counter, 1, out)?;
+        Ok(Flow::Continue)
+    }
+
+    /// Prints the registers, timers and stack
+    fn print_registers<W: Write>(&self, out: &mut W) -> Result<()> {
+        let registers = self.chip8.variable_registers();
+        for row in 0..4 {
+            let line: Vec<String> = (0..4)
+                .map(|column| row + column * 4)
+                .map(|x| format!("V{x:X}={:02X}", registers[x]))
+                .collect();
+            writeln!(out, "{}", line.join("  "))?;
+        }
+
+        writeln!(out, "I={:03X}  PC={:03X}  SP={:X}  DT={:02X}  ST={:02X}",
+                 self.chip8.index_register(),
+                 self.chip8.program_counter(),
+                 self.chip8.stack_pointer(),
+                 self.chip8.delay_timer(),
+                 self.chip8.sound_timer())?;
+
+        let stack: Vec<String> = self.chip8.stack().iter().map(|address| format!("{address:03X}")).collect();
+        writeln!(out, "stack: [{}]", stack.join(" "))?;
+        Ok(())
+    }
+
+    /// Prints count instructions starting at address, marking the program counter and breakpoints
+    fn print_disassembly<W: Write>(&self, address: u16, count: usize, out: &mut W) -> Result<()> {
+        for line in disassemble(self.chip8.ram(), address, count) {
+            let breakpoint = if self.debugger.has_breakpoint(line.address) { '*' } else { ' ' };
+            let marker = if line.address == self.chip8.program_counter() { '>' } else { ' ' };
+            writeln!(out, "{breakpoint}{marker}{:03X}  {:04X}  {}", line.address, line.opcode, line.instruction)?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints count bytes of memory as hex, 16 to a line
+    fn print_memory<W: Write>(&self, address: u16, count: usize, out: &mut W) -> Result<()> {
+        let ram = self.chip8.ram();
+        let start = address as usize;
+        let end = start.saturating_add(count).min(ram.len());
+        if start >= end {
+            bail!("address {address:03X} is outside memory");
+        }
+
+        for (line, bytes) in ram[start..end].chunks(16).enumerate() {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+            writeln!(out, "{:03X}: {}", start + line * 16, hex.join(" "))?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the frame buffer with `#` for pixels that are on and `.` for pixels that are off
+    fn print_screen<W: Write>(&self, out: &mut W) -> Result<()> {
+        for row in self.chip8.frame_buffer().chunks(DISPLAY_WIDTH) {
+            let line: String = row.iter().map(|pixel| if *pixel != 0 { '#' } else { '.' }).collect();
+            writeln!(out, "{line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets a required argument
+fn argument<'a>(arguments: &[&'a str], index: usize) -> Result<&'a str> {
+    arguments.get(index).copied().ok_or_else(|| anyhow!("missing argument"))
+}
+
+/// Gets an optional count argument, which defaults to 1
+fn optional_count(arguments: &[&str], index: usize) -> Result<usize> {
+    arguments.get(index).map_or(Ok(1), |count| parse_number(count).map(usize::from))
+}
+
+/// Parses a hex address, with or without a 0x prefix
+fn parse_address(text: &str) -> Result<u16> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).with_context(|| format!("invalid address {text}"))
+}
+
+/// Parses a decimal number, or a hex number with a 0x prefix
+fn parse_number(text: &str) -> Result<u16> {
+    match text.strip_prefix("0x") {
+        Some(digits) => u16::from_str_radix(digits, 16),
+        None => text.parse(),
+    }.with_context(|| format!("invalid number {text}"))
+}
+
+/// Parses a register name, e.g. `V3`, `I` or `PC`
+fn parse_register(name: &str) -> Result<Register> {
+    match name.to_ascii_uppercase().as_str() {
+        "I" => Ok(Register::Index),
+        "PC" => Ok(Register::ProgramCounter),
+        "SP" => Ok(Register::StackPointer),
+        "DT" => Ok(Register::DelayTimer),
+        "ST" => Ok(Register::SoundTimer),
+        upper => upper.strip_prefix('V')
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+            .map(Register::Variable)
+            .ok_or_else(|| anyhow!("unknown register {name}")),
+    }
+}
+
+/// Parses a key given as a hex digit
+fn parse_key(text: &str) -> Result<Chip8Key> {
+    u8::from_str_radix(text, 16).ok()
+        .and_then(Chip8Key::from_value)
+        .ok_or_else(|| anyhow!("unknown key {text}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chip_8_emulator::EmulatorType;
+    use chip_8_emulator::KeyState;
+    use super::*;
+
+    /// Calls a subroutine at 0x206 that adds 1 to V0, then adds 1 to V1 and loops
+    const CALLING_PROGRAM: [u8; 10] = [0x22, 0x06, 0x71, 0x01, 0x12, 0x00, 0x70, 0x01, 0x00, 0xEE];
+
+    fn session(program: &[u8]) -> Session<rand::rngs::ThreadRng> {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        chip8.load_program(program);
+        Session::new(chip8, 10, Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Runs commands, returning everything they printed
+    fn run(session: &mut Session<rand::rngs::ThreadRng>, commands: &[&str]) -> String {
+        let mut out = Vec::new();
+        for command in commands {
+            session.execute(command, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn continue_stops_at_breakpoints() {
+        let mut session = session(&CALLING_PROGRAM);
+
+        let output = run(&mut session, &["break 202", "continue"]);
+
+        assert_eq!("Breakpoint at 202\nBreakpoint at 202\n*>202  7101  ADD V1, 0x01\n", output);
+        assert_eq!(1, session.chip8.variable_registers()[0]);
+    }
+
+    #[test]
+    fn next_steps_over_calls_and_finish_returns() {
+        let mut session = session(&CALLING_PROGRAM);
+
+        run(&mut session, &["next"]);
+        assert_eq!(0x202, session.chip8.program_counter());
+        assert_eq!(1, session.chip8.variable_registers()[0]);
+
+        run(&mut session, &["next 2", "step"]);
+        assert_eq!(0x206, session.chip8.program_counter());
+        run(&mut session, &["finish"]);
+        assert_eq!(0x202, session.chip8.program_counter());
+        assert_eq!(2, session.chip8.variable_registers()[0]);
+
+        assert!(session.execute("finish", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn can_set_and_show_registers_and_memory() {
+        let mut session = session(&CALLING_PROGRAM);
+
+        let output = run(&mut session, &["set V3=0x2A", "set pc = 516", "regs", "x/4 200"]);
+
+        assert!(output.contains("V3=2A"));
+        assert!(output.contains("PC=204"));
+        assert!(output.ends_with("200: 22 06 71 01\n"));
+        assert!(session.execute("set VG=1", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn can_press_keys_and_print_the_screen() {
+        // Draws the font character for 0 at the top left
+        let mut session = session(&[0xF0, 0x29, 0xD0, 0x05]);
+
+        let output = run(&mut session, &["press a", "step 2", "screen"]);
+
+        assert_eq!(KeyState::Down, session.chip8.key_state(Chip8Key::A));
+        let screen: Vec<&str> = output.lines().skip(1).collect();
+        assert_eq!(32, screen.len());
+        assert!(screen[0].starts_with("####...."));
+        assert!(screen[1].starts_with("#..#...."));
+    }
+
+    #[test]
+    fn runs_scripts_until_an_error() {
+        let mut session = session(&CALLING_PROGRAM);
+        let path = std::env::temp_dir().join(format!("chip-8-debugger-script-{}.txt", std::process::id()));
+        std::fs::write(&path, "# set up\nset V5=7\n\nbogus\nset V6=1\n").unwrap();
+
+        let error = session.run_script(&path, &mut Vec::new()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(format!("{error:#}").contains(":4: bogus"));
+        assert_eq!(7, session.chip8.variable_registers()[5]);
+        assert_eq!(0, session.chip8.variable_registers()[6]);
+    }
+
+    #[test]
+    fn scripts_cannot_source_themselves() {
+        let mut session = session(&CALLING_PROGRAM);
+        let directory = std::env::temp_dir();
+        let first = directory.join(format!("chip-8-debugger-first-{}.txt", std::process::id()));
+        let second = directory.join(format!("chip-8-debugger-second-{}.txt", std::process::id()));
+        std::fs::write(&first, format!("set V5=7\nsource {}\n", second.display())).unwrap();
+        std::fs::write(&second, format!("source {}\n", first.display())).unwrap();
+
+        let error = session.run_script(&first, &mut Vec::new()).unwrap_err();
+        let sourced_again = session.run_script(&second, &mut Vec::new()).unwrap_err();
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+
+        assert!(format!("{error:#}").contains("is already running"));
+        assert!(format!("{sourced_again:#}").contains("is already running"));
+        assert!(session.active_scripts.is_empty());
+        assert_eq!(7, session.chip8.variable_registers()[5]);
+    }
+}
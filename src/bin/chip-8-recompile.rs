@@ -0,0 +1,78 @@
+//! Recompiles a ROM's reachable code into a Rust crate that runs it on this emulator's `Chip8`,
+//! with the interpreter as a fallback.
+
+use std::path::PathBuf;
+use anyhow::{anyhow, bail, Context, Result};
+use chip_8_emulator::command_line::{exit_on_error, next_value, parse_args};
+use chip_8_emulator::detection::Detection;
+use chip_8_emulator::recompiler::recompile;
+use chip_8_emulator::EmulatorType;
+
+/// The usage text printed when the arguments can't be parsed
+const USAGE: &str = "\
+usage: chip-8-recompile [OPTIONS] <ROM> <DIRECTORY>
+
+Writes a crate to the directory with a function per basic block of the ROM. Its binary runs the
+recompiled code alongside the interpreter, checks their frame buffers match and times them:
+
+    cargo run --release -- [--display-wait] [FRAMES] [INSTRUCTIONS PER FRAME|vip] [SEED]
+
+options:
+    --type <vip|chip48|auto>
+                           the emulator type to recompile for. auto picks one from the
+                           instructions the ROM uses (default: auto)
+    --name <NAME>          the name of the crate (default: recompiled)";
+
+/// Options for the tool parsed from the command line
+struct Options {
+    rom_path: PathBuf,
+    directory: PathBuf,
+    emulator_type: Option<EmulatorType>,
+    name: String,
+}
+
+impl Options {
+    /// Parses options from command line arguments, not including the program name
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut paths = Vec::new();
+        let mut emulator_type = None;
+        let mut name = "recompiled".to_string();
+
+        while let Some(arg) = args.next() {
+            let mut value = || next_value(&mut args, &arg);
+            match arg.as_str() {
+                "--type" => emulator_type = match value()?.as_str() {
+                    "auto" => None,
+                    other => Some(other.parse()?),
+                },
+                "--name" => name = value()?,
+                _ if arg.starts_with("--") => bail!("unknown option {arg}"),
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [rom_path, directory]: [PathBuf; 2] = paths.try_into().map_err(|_| anyhow!("expected a ROM and a directory"))?;
+        Ok(Self { rom_path, directory, emulator_type, name })
+    }
+}
+
+fn main() {
+    let options = parse_args(USAGE, Options::parse);
+    exit_on_error(run(&options));
+}
+
+/// Recompiles the ROM and writes the crate
+fn run(options: &Options) -> Result<()> {
+    let program = std::fs::read(&options.rom_path)
+        .with_context(|| format!("failed to read {}", options.rom_path.display()))?;
+    let emulator_type = options.emulator_type.unwrap_or_else(|| Detection::detect(&program).emulator_type());
+
+    let dependency = format!("{{ path = {:?}, default-features = false, features = [\"rand\"] }}", env!("CARGO_MANIFEST_DIR"));
+    recompile(&program, &options.name, emulator_type, &dependency)?
+        .write_to(&options.directory)
+        .with_context(|| format!("failed to write {}", options.directory.display()))?;
+
+    println!("Recompiled {} for {emulator_type:?} into {}", options.rom_path.display(), options.directory.display());
+    Ok(())
+}
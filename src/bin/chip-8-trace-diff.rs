@@ -0,0 +1,117 @@
+//! Replays a ROM against an execution trace from another emulator and reports the first cycle
+//! where the two disagree.
+
+use std::path::PathBuf;
+use anyhow::{anyhow, bail, Context, Result};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use chip_8_emulator::command_line::{exit_on_error, next_value, parse_args, parse_value};
+use chip_8_emulator::trace::TraceTiming;
+use chip_8_emulator::trace_diff::{find_divergence, parse_csv, parse_json};
+use chip_8_emulator::{Chip8, EmulatorType};
+
+/// The usage text printed when the arguments can't be parsed
+const USAGE: &str = "\
+usage: chip-8-trace-diff [OPTIONS] <ROM> <TRACE>
+
+Traces are CSV files with a header row, or JSON arrays or lines of objects, with a pc field and
+optionally i, v0-vf (or an array v), dt, st, sp, keys and screen fields. The format is picked by
+the extension, .csv or .json.
+
+options:
+    --type <vip|chip48>    the emulator type to interpret instructions as (default: vip)
+    --speed <N>            instructions executed per 60 Hz frame (default: 12)
+    --seed <N>             seed the random number generator
+    --timing <WHEN>        whether the trace records state before or after each instruction
+                           (default: before)
+    --context <N>          the number of cycles to show before the divergence (default: 8)";
+
+/// The default number of instructions executed per frame
+const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 12;
+
+/// The default number of cycles of context shown before a divergence
+const DEFAULT_CONTEXT: usize = 8;
+
+/// Options for the tool parsed from the command line
+struct Options {
+    rom_path: PathBuf,
+    trace_path: PathBuf,
+    emulator_type: EmulatorType,
+    instructions_per_frame: usize,
+    seed: u64,
+    timing: TraceTiming,
+    context: usize,
+}
+
+impl Options {
+    /// Parses options from command line arguments, not including the program name
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut paths = Vec::new();
+        let mut emulator_type = EmulatorType::CosmacVip;
+        let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+        let mut seed = 0;
+        let mut timing = TraceTiming::Before;
+        let mut context = DEFAULT_CONTEXT;
+
+        while let Some(arg) = args.next() {
+            let mut value = || next_value(&mut args, &arg);
+            match arg.as_str() {
+                "--type" => emulator_type = value()?.parse()?,
+                "--speed" => instructions_per_frame = parse_value(&value()?, &arg)?,
+                "--seed" => seed = parse_value(&value()?, &arg)?,
+                "--timing" => timing = match value()?.as_str() {
+                    "before" => TraceTiming::Before,
+                    "after" => TraceTiming::After,
+                    other => bail!("unknown timing {other}"),
+                },
+                "--context" => context = parse_value(&value()?, &arg)?,
+                _ if arg.starts_with("--") => bail!("unknown option {arg}"),
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [rom_path, trace_path]: [PathBuf; 2] = paths.try_into().map_err(|_| anyhow!("expected a ROM and a trace"))?;
+        Ok(Self { rom_path, trace_path, emulator_type, instructions_per_frame, seed, timing, context })
+    }
+}
+
+fn main() {
+    let options = parse_args(USAGE, Options::parse);
+    if !exit_on_error(run(&options)) {
+        std::process::exit(1);
+    }
+}
+
+/// Replays the trace and prints the result. Returns whether the traces matched
+fn run(options: &Options) -> Result<bool> {
+    let program = std::fs::read(&options.rom_path)
+        .with_context(|| format!("failed to read {}", options.rom_path.display()))?;
+    let text = std::fs::read_to_string(&options.trace_path)
+        .with_context(|| format!("failed to read {}", options.trace_path.display()))?;
+
+    let is_json = options.trace_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let reference = if is_json { parse_json(&text) } else { parse_csv(&text) }
+        .with_context(|| format!("failed to parse {}", options.trace_path.display()))?;
+
+    let mut chip8 = Chip8::new(options.emulator_type, StdRng::seed_from_u64(options.seed));
+    chip8.load_program(&program);
+
+    let Some(divergence) = find_divergence(&mut chip8, &reference, options.timing, options.instructions_per_frame, options.context) else {
+        println!("Traces match for all {} cycles", reference.len());
+        return Ok(true);
+    };
+
+    println!("First divergence at cycle {}:", divergence.cycle);
+    for difference in &divergence.differences {
+        println!("    {difference}");
+    }
+
+    println!("\nOur state {} each instruction:", if options.timing == TraceTiming::Before { "before" } else { "after" });
+    let mut out = std::io::stdout();
+    for record in &divergence.context {
+        record.write_text(&mut out, record.cycle == divergence.cycle as u64)?;
+    }
+
+    Ok(false)
+}
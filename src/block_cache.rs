@@ -0,0 +1,521 @@
+use crate::instruction::Instruction;
+use crate::{Chip8, Chip8Rng, EmulatorType, KeyState, DISPLAY_HEIGHT, DISPLAY_WIDTH, FONT_START_ADDRESS, MEMORY_SIZE};
+
+/// The most instructions decoded into one block, so long runs of straight-line code or data that
+/// happens to decode as it aren't decoded all at once
+const MAX_BLOCK_LENGTH: usize = 64;
+
+/// An instruction decoded with its operands and the emulator type's quirks resolved. Micro-ops
+/// don't advance the program counter: a block sets it once, past its last instruction, before
+/// running that one, so only control flow at the end of a block sees it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum MicroOp {
+    ClearScreen,
+    Return,
+    Jump { target: u16 },
+    Call { target: u16 },
+    SkipIfEqual { x: usize, value: u8 },
+    SkipIfNotEqual { x: usize, value: u8 },
+    SkipIfRegistersEqual { x: usize, y: usize },
+    SkipIfRegistersNotEqual { x: usize, y: usize },
+    Set { x: usize, value: u8 },
+    Add { x: usize, value: u8 },
+    Copy { x: usize, y: usize },
+    Or { x: usize, y: usize },
+    And { x: usize, y: usize },
+    Xor { x: usize, y: usize },
+    AddRegisters { x: usize, y: usize },
+    Subtract { x: usize, y: usize },
+    SubtractReversed { x: usize, y: usize },
+    /// Shifts `source` right into VX, where `source` is VY on the COSMAC VIP and VX on the CHIP-48
+    ShiftRight { x: usize, source: usize },
+    /// Shifts `source` left into VX, where `source` is VY on the COSMAC VIP and VX on the CHIP-48
+    ShiftLeft { x: usize, source: usize },
+    SetIndex { address: u16 },
+    /// Jumps to `base` plus the register, V0 on the COSMAC VIP and VX on the CHIP-48
+    JumpWithOffset { base: u16, register: usize },
+    Random { x: usize, mask: u8 },
+    Draw { x: usize, y: usize, rows: usize },
+    SkipIfKeyDown { x: usize },
+    SkipIfKeyUp { x: usize },
+    ReadDelayTimer { x: usize },
+    WaitForKey { x: usize },
+    SetDelayTimer { x: usize },
+    SetSoundTimer { x: usize },
+    AddToIndex { x: usize },
+    SetIndexToFontCharacter { x: usize },
+    StoreDecimalDigits { x: usize },
+    /// Stores V0 to VX, advancing the index register past them on the COSMAC VIP
+    StoreRegisters { count: usize, increment_index: bool },
+    /// Loads V0 to VX, advancing the index register past them on the COSMAC VIP
+    LoadRegisters { count: usize, increment_index: bool },
+    Invalid { opcode: u16 },
+}
+
+impl MicroOp {
+    /// Resolves an instruction's operands and quirks for an emulator type
+    fn new(instruction: Instruction, emulator_type: EmulatorType) -> Self {
+        let vip = emulator_type == EmulatorType::CosmacVip;
+        match instruction {
+            Instruction::ClearScreen => MicroOp::ClearScreen,
+            Instruction::Return => MicroOp::Return,
+            Instruction::Jump { nnn } => MicroOp::Jump { target: nnn },
+            Instruction::Call { nnn } => MicroOp::Call { target: nnn },
+            Instruction::SkipIfVxEqualsNn { x, nn } => MicroOp::SkipIfEqual { x: x as usize, value: nn },
+            Instruction::SkipIfVxNotEqualsNn { x, nn } => MicroOp::SkipIfNotEqual { x: x as usize, value: nn },
+            Instruction::SkipIfVxEqualsVy { x, y } => MicroOp::SkipIfRegistersEqual { x: x as usize, y: y as usize },
+            Instruction::SetVx { x, nn } => MicroOp::Set { x: x as usize, value: nn },
+            Instruction::AddToVx { x, nn } => MicroOp::Add { x: x as usize, value: nn },
+            Instruction::SetVxToVy { x, y } => MicroOp::Copy { x: x as usize, y: y as usize },
+            Instruction::OrVxWithVy { x, y } => MicroOp::Or { x: x as usize, y: y as usize },
+            Instruction::AndVxWithVy { x, y } => MicroOp::And { x: x as usize, y: y as usize },
+            Instruction::XorVxWithVy { x, y } => MicroOp::Xor { x: x as usize, y: y as usize },
+            Instruction::AddVyToVx { x, y } => MicroOp::AddRegisters { x: x as usize, y: y as usize },
+            Instruction::SubtractVyFromVx { x, y } => MicroOp::Subtract { x: x as usize, y: y as usize },
+            Instruction::ShiftVxRight { x, y } => MicroOp::ShiftRight { x: x as usize, source: if vip { y } else { x } as usize },
+            Instruction::SubtractVxFromVyIntoVx { x, y } => MicroOp::SubtractReversed { x: x as usize, y: y as usize },
+            Instruction::ShiftVxLeft { x, y } => MicroOp::ShiftLeft { x: x as usize, source: if vip { y } else { x } as usize },
+            Instruction::SkipIfVxNotEqualsVy { x, y } => MicroOp::SkipIfRegistersNotEqual { x: x as usize, y: y as usize },
+            Instruction::SetIndex { nnn } => MicroOp::SetIndex { address: nnn },
+            Instruction::JumpWithOffset { nnn } => MicroOp::JumpWithOffset { base: nnn, register: if vip { 0 } else { (nnn >> 8) as usize & 0xF } },
+            Instruction::Random { x, nn } => MicroOp::Random { x: x as usize, mask: nn },
+            Instruction::Draw { x, y, n } => MicroOp::Draw { x: x as usize, y: y as usize, rows: n as usize },
+            Instruction::SkipIfKeyDown { x } => MicroOp::SkipIfKeyDown { x: x as usize },
+            Instruction::SkipIfKeyUp { x } => MicroOp::SkipIfKeyUp { x: x as usize },
+            Instruction::SetVxToDelayTimer { x } => MicroOp::ReadDelayTimer { x: x as usize },
+            Instruction::WaitForKey { x } => MicroOp::WaitForKey { x: x as usize },
+            Instruction::SetDelayTimer { x } => MicroOp::SetDelayTimer { x: x as usize },
+            Instruction::SetSoundTimer { x } => MicroOp::SetSoundTimer { x: x as usize },
+            Instruction::AddVxToIndex { x } => MicroOp::AddToIndex { x: x as usize },
+            Instruction::SetIndexToFontCharacter { x } => MicroOp::SetIndexToFontCharacter { x: x as usize },
+            Instruction::StoreDecimalDigits { x } => MicroOp::StoreDecimalDigits { x: x as usize },
+            Instruction::StoreRegisters { x } => MicroOp::StoreRegisters { count: x as usize + 1, increment_index: vip },
+            Instruction::LoadRegisters { x } => MicroOp::LoadRegisters { count: x as usize + 1, increment_index: vip },
+            Instruction::Invalid { opcode } => MicroOp::Invalid { opcode },
+        }
+    }
+
+    /// Returns whether the micro-op ends a block, because execution may not continue with the next
+    /// instruction or because it writes to memory that may hold code. Draws end blocks as they
+    /// stall with [`Chip8::set_display_wait`]
+    fn ends_block(&self) -> bool {
+        !matches!(self,
+            MicroOp::ClearScreen
+            | MicroOp::Set { .. }
+            | MicroOp::Add { .. }
+            | MicroOp::Copy { .. }
+            | MicroOp::Or { .. }
+            | MicroOp::And { .. }
+            | MicroOp::Xor { .. }
+            | MicroOp::AddRegisters { .. }
+            | MicroOp::Subtract { .. }
+            | MicroOp::SubtractReversed { .. }
+            | MicroOp::ShiftRight { .. }
+            | MicroOp::ShiftLeft { .. }
+            | MicroOp::SetIndex { .. }
+            | MicroOp::Random { .. }
+            | MicroOp::ReadDelayTimer { .. }
+            | MicroOp::SetDelayTimer { .. }
+            | MicroOp::SetSoundTimer { .. }
+            | MicroOp::AddToIndex { .. }
+            | MicroOp::SetIndexToFontCharacter { .. }
+            | MicroOp::LoadRegisters { .. })
+    }
+
+    /// Gets the memory the micro-op writes as a start address and length, if it writes any
+    fn written_memory<R: Chip8Rng>(&self, chip8: &Chip8<R>) -> Option<(u16, u16)> {
+        match *self {
+            MicroOp::StoreRegisters { count, .. } => Some((chip8.index_register, count as u16)),
+            MicroOp::StoreDecimalDigits { .. } => Some((chip8.index_register, 3)),
+            _ => None,
+        }
+    }
+
+    /// Carries out the micro-op, with the program counter already past it if it's control flow
+    #[inline(always)]
+    fn execute<R: Chip8Rng>(self, chip8: &mut Chip8<R>) {
+        let v = &mut chip8.variable_registers;
+        match self {
+            MicroOp::ClearScreen => chip8.frame_buffer.fill(0),
+            MicroOp::Return => chip8.program_counter = chip8.pop_stack(),
+            MicroOp::Jump { target } => chip8.program_counter = target,
+            MicroOp::Call { target } => {
+                chip8.push_stack(chip8.program_counter);
+                chip8.program_counter = target;
+            }
+            MicroOp::SkipIfEqual { x, value } => chip8.program_counter += 2 * (v[x] == value) as u16,
+            MicroOp::SkipIfNotEqual { x, value } => chip8.program_counter += 2 * (v[x] != value) as u16,
+            MicroOp::SkipIfRegistersEqual { x, y } => chip8.program_counter += 2 * (v[x] == v[y]) as u16,
+            MicroOp::SkipIfRegistersNotEqual { x, y } => chip8.program_counter += 2 * (v[x] != v[y]) as u16,
+            MicroOp::Set { x, value } => v[x] = value,
+            MicroOp::Add { x, value } => v[x] = v[x].wrapping_add(value),
+            MicroOp::Copy { x, y } => v[x] = v[y],
+            MicroOp::Or { x, y } => v[x] |= v[y],
+            MicroOp::And { x, y } => v[x] &= v[y],
+            MicroOp::Xor { x, y } => v[x] ^= v[y],
+            MicroOp::AddRegisters { x, y } => {
+                let (sum, carry) = v[x].overflowing_add(v[y]);
+                v[0xF] = carry as u8;
+                v[x] = sum;
+            }
+            MicroOp::Subtract { x, y } => {
+                let (difference, borrow) = v[x].overflowing_sub(v[y]);
+                v[0xF] = !borrow as u8;
+                v[x] = difference;
+            }
+            MicroOp::SubtractReversed { x, y } => {
+                let (difference, borrow) = v[y].overflowing_sub(v[x]);
+                v[0xF] = !borrow as u8;
+                v[x] = difference;
+            }
+            // VF is set before VX is shifted, so shifting VF itself shifts the flag
+            MicroOp::ShiftRight { x, source } => {
+                v[x] = v[source];
+                v[0xF] = v[x] & 0x01;
+                v[x] >>= 1;
+            }
+            MicroOp::ShiftLeft { x, source } => {
+                v[x] = v[source];
+                v[0xF] = v[x] >> 7;
+                v[x] <<= 1;
+            }
+            MicroOp::SetIndex { address } => chip8.index_register = address,
+            MicroOp::JumpWithOffset { base, register } => chip8.program_counter = base.wrapping_add(v[register] as u16),
+            MicroOp::Random { x, mask } => v[x] = chip8.rng.random_byte() & mask,
+            MicroOp::Draw { x, y, rows } => draw(chip8, x, y, rows),
+            MicroOp::SkipIfKeyDown { x } => chip8.program_counter += 2 * (chip8.keypad_state[v[x] as usize] == KeyState::Down) as u16,
+            MicroOp::SkipIfKeyUp { x } => chip8.program_counter += 2 * (chip8.keypad_state[v[x] as usize] == KeyState::Up) as u16,
+            MicroOp::ReadDelayTimer { x } => v[x] = chip8.delay_timer,
+            MicroOp::WaitForKey { x } => match chip8.poll_key_release() {
+                Some(key) => chip8.variable_registers[x] = key,
+                None => chip8.program_counter -= 2,
+            },
+            MicroOp::SetDelayTimer { x } => chip8.delay_timer = v[x],
+            MicroOp::SetSoundTimer { x } => chip8.sound_timer = v[x],
+            MicroOp::AddToIndex { x } => chip8.index_register = chip8.index_register.wrapping_add(v[x] as u16),
+            MicroOp::SetIndexToFontCharacter { x } => chip8.index_register = FONT_START_ADDRESS as u16 + (v[x] & 0x0F) as u16 * 5,
+            MicroOp::StoreDecimalDigits { x } => {
+                let value = v[x];
+                let start = chip8.index_register as usize;
+                chip8.ram[start..start + 3].copy_from_slice(&[value / 100, value / 10 % 10, value % 10]);
+            }
+            MicroOp::StoreRegisters { count, increment_index } => {
+                let start = chip8.index_register as usize;
+                chip8.ram[start..start + count].copy_from_slice(&v[..count]);
+                if increment_index {
+                    chip8.index_register += count as u16;
+                }
+            }
+            MicroOp::LoadRegisters { count, increment_index } => {
+                let start = chip8.index_register as usize;
+                v[..count].copy_from_slice(&chip8.ram[start..start + count]);
+                if increment_index {
+                    chip8.index_register += count as u16;
+                }
+            }
+            MicroOp::Invalid { opcode } => panic!("Encountered invalid opcode {opcode:X}"),
+        }
+    }
+}
+
+/// Draws a sprite as [`Chip8::draw_sprite`] does, visiting only the pixels it sets
+fn draw<R: Chip8Rng>(chip8: &mut Chip8<R>, x: usize, y: usize, rows: usize) {
+    chip8.drawn_since_tick = true;
+    let x_offset = chip8.variable_registers[x] as usize % DISPLAY_WIDTH;
+    let y_offset = chip8.variable_registers[y] as usize % DISPLAY_HEIGHT;
+    let start = chip8.index_register as usize;
+    let sprite = &chip8.ram[start..start + rows];
+    // Pixels past the right edge are clipped
+    let visible = if x_offset + 8 > DISPLAY_WIDTH { 0xFFu8 << (x_offset + 8 - DISPLAY_WIDTH) } else { 0xFF };
+
+    let mut collision = 0;
+    for (row, &byte) in sprite.iter().enumerate().take(DISPLAY_HEIGHT - y_offset) {
+        let line = &mut chip8.frame_buffer[(y_offset + row) * DISPLAY_WIDTH + x_offset..];
+        let mut bits = byte & visible;
+        while bits != 0 {
+            let bit = bits.leading_zeros() as usize;
+            collision |= line[bit];
+            line[bit] ^= 1;
+            bits &= !(0x80 >> bit);
+        }
+    }
+
+    chip8.variable_registers[0xF] = collision;
+}
+
+/// A run of micro-ops decoded once and executed together
+#[derive(Debug)]
+struct CachedBlock {
+    /// The micro-ops in order
+    ops: Box<[MicroOp]>,
+    /// The address just past the last instruction
+    end: u16,
+}
+
+/// An alternative execution engine that decodes straight-line runs of instructions from the
+/// program counter into blocks of micro-ops once and caches them by address, rather than fetching
+/// and decoding every instruction as it runs. It gives identical results to
+/// [`Chip8::run_frame`]. Blocks end at anything that may not continue with the next instruction
+/// and at writes to memory, and any block whose bytes are written by FX55 or FX33 is dropped so
+/// it's decoded again. Writes to memory from outside, e.g. with [`Chip8::write_memory`] or
+/// [`Chip8::load_program`], and running a different interpreter need [`BlockCache::clear`].
+#[derive(Debug)]
+pub struct BlockCache {
+    /// The block starting at each address, if one has been decoded
+    blocks: Vec<Option<CachedBlock>>,
+    /// The number of cached blocks
+    block_count: usize,
+    /// The number of cached blocks that include each byte of memory
+    code_references: Vec<u8>,
+}
+
+impl BlockCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self {
+            blocks: (0..MEMORY_SIZE).map(|_| None).collect(),
+            block_count: 0,
+            code_references: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    /// Gets the number of blocks cached
+    pub fn len(&self) -> usize {
+        self.block_count
+    }
+
+    /// Returns whether no blocks are cached
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+
+    /// Drops every cached block
+    pub fn clear(&mut self) {
+        self.blocks.fill_with(|| None);
+        self.block_count = 0;
+        self.code_references.fill(0);
+    }
+
+    /// Runs one 60 Hz frame like [`Chip8::run_frame`] using cached blocks
+    pub fn run_frame<R: Chip8Rng>(&mut self, chip8: &mut Chip8<R>, instructions_per_frame: usize) {
+        let mut executed = 0;
+        while executed < instructions_per_frame && !chip8.is_waiting_for_display() {
+            executed += self.run_block(chip8, instructions_per_frame - executed);
+        }
+
+        chip8.decrement_timers();
+    }
+
+    /// Runs the block at the program counter, decoding it first if it isn't cached, stopping
+    /// after at most `budget` instructions or before a draw that has to wait for the display.
+    /// Returns the number of instructions executed
+    pub fn run_block<R: Chip8Rng>(&mut self, chip8: &mut Chip8<R>, budget: usize) -> usize {
+        let start = chip8.program_counter;
+        let Self { blocks, block_count, code_references } = self;
+        let block = blocks[start as usize].get_or_insert_with(|| {
+            *block_count += 1;
+            decode(&chip8.ram, start, chip8.emulator_type, code_references)
+        });
+
+        let count = block.ops.len().min(budget);
+        let (&last, rest) = block.ops[..count].split_last().unwrap();
+        for op in rest {
+            op.execute(chip8);
+        }
+
+        // Only the last micro-op can be control flow, draw or write memory
+        chip8.program_counter = start + 2 * count as u16;
+        if matches!(last, MicroOp::Draw { .. }) && chip8.draw_would_wait() {
+            chip8.program_counter -= 2;
+            return count - 1;
+        }
+
+        let written = last.written_memory(chip8);
+        last.execute(chip8);
+        if let Some((address, length)) = written {
+            self.invalidate(address, length);
+        }
+
+        count
+    }
+
+    /// Drops every cached block that includes any of the bytes written
+    fn invalidate(&mut self, start: u16, length: u16) {
+        let written = start as usize..(start as usize + length as usize).min(MEMORY_SIZE);
+        if self.code_references[written.clone()].iter().all(|references| *references == 0) {
+            return;
+        }
+
+        // Blocks are at most MAX_BLOCK_LENGTH instructions long, so only ones starting this close
+        // before the write can include it
+        for block_start in written.start.saturating_sub(2 * MAX_BLOCK_LENGTH)..written.end {
+            let overlaps = self.blocks[block_start].as_ref().is_some_and(|block| written.start < block.end as usize);
+            if overlaps {
+                let block = self.blocks[block_start].take().unwrap();
+                for reference in &mut self.code_references[block_start..block.end as usize] {
+                    *reference -= 1;
+                }
+                self.block_count -= 1;
+            }
+        }
+    }
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decodes the block starting at an address into micro-ops for an emulator type, counting the
+/// bytes it covers as code
+fn decode(memory: &[u8], start: u16, emulator_type: EmulatorType, code_references: &mut [u8]) -> CachedBlock {
+    let mut ops = Vec::new();
+    let mut address = start;
+
+    while ops.len() < MAX_BLOCK_LENGTH && (address as usize) + 1 < memory.len() {
+        let opcode = (memory[address as usize] as u16) << 8 | memory[address as usize + 1] as u16;
+        let op = MicroOp::new(Instruction::decode(opcode), emulator_type);
+        ops.push(op);
+        address += 2;
+
+        if op.ends_block() {
+            break;
+        }
+    }
+
+    // An instruction straddling the end of memory is fetched as the interpreter would, as a
+    // single instruction block
+    if ops.is_empty() {
+        ops.push(MicroOp::Invalid { opcode: (memory[start as usize] as u16) << 8 });
+        address += 2;
+    }
+
+    for reference in code_references.iter_mut().take(address as usize).skip(start as usize) {
+        *reference += 1;
+    }
+    CachedBlock { ops: ops.into_boxed_slice(), end: address }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::{Chip8Key, EmulatorType, Register};
+    use super::*;
+
+    /// Draws random sprites and digits, calling a subroutine that stores V2 + 1 into the operand of
+    /// its own `ADD V2` at 0x228, so the add changes every call
+    const PROGRAM: [u8; 50] = [
+        0xA2, 0x2C, 0xC0, 0x3F, 0xC1, 0x1F, 0xD0, 0x15,
+        0x22, 0x20, 0xF2, 0x29, 0xD0, 0x15, 0x32, 0x40,
+        0x12, 0x02, 0x00, 0xE0, 0x62, 0x00, 0x12, 0x02,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x80, 0x20, 0x70, 0x01, 0xA2, 0x29, 0xF0, 0x55,
+        0x72, 0x01, 0x00, 0xEE, 0xF0, 0x90, 0xF0, 0x90,
+        0x90, 0x00,
+    ];
+
+    fn assert_same_state(expected: &Chip8<TestRng>, actual: &Chip8<TestRng>) {
+        assert_eq!(expected.ram(), actual.ram());
+        assert_eq!(expected.frame_buffer(), actual.frame_buffer());
+        assert_eq!(expected.variable_registers(), actual.variable_registers());
+        for register in [Register::Index, Register::ProgramCounter, Register::StackPointer, Register::DelayTimer, Register::SoundTimer] {
+            assert_eq!(expected.register(register), actual.register(register), "{register:?}");
+        }
+        assert_eq!(expected.stack(), actual.stack());
+    }
+
+    #[test]
+    fn gives_identical_results_to_the_interpreter() {
+        for emulator_type in [EmulatorType::CosmacVip, EmulatorType::Chip48] {
+            let mut interpreted = Chip8::new(emulator_type, TestRng::new(3));
+            let mut cached = Chip8::new(emulator_type, TestRng::new(3));
+            interpreted.load_program(&PROGRAM);
+            cached.load_program(&PROGRAM);
+            let mut cache = BlockCache::new();
+
+            for _ in 0..300 {
+                interpreted.run_frame(7);
+                cache.run_frame(&mut cached, 7);
+                assert_same_state(&interpreted, &cached);
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_every_kind_of_instruction_like_the_interpreter() {
+        // Shifts VF, does arithmetic on random numbers, draws a random digit at a random position
+        // so it's clipped at the edges, stores, loads and adds to I, then jumps with an offset
+        // that depends on a key and the emulator type into a subroutine that may clear the screen
+        let program = [
+            0x6F, 0x85, 0x8F, 0xF6, 0x6E, 0xC3, 0x8F, 0xEE, 0xC0, 0xFF, 0xC1, 0xFF, 0x82, 0x00,
+            0x82, 0x15, 0x83, 0x17, 0x84, 0x14, 0x85, 0x02, 0x85, 0x13, 0x85, 0x21, 0xF0, 0x29,
+            0xD0, 0x15, 0xA3, 0x00, 0xF4, 0x55, 0xF4, 0x65, 0xF1, 0x1E, 0xF5, 0x33, 0xF0, 0x15,
+            0xF6, 0x07, 0x62, 0x04, 0x60, 0x00, 0xE2, 0x9E, 0x70, 0x02, 0xB2, 0x38, 0x00, 0x00,
+            0x63, 0x01, 0x64, 0x01, 0x22, 0x40, 0x12, 0x00, 0x3F, 0x01, 0x00, 0xE0, 0x00, 0xEE,
+        ];
+
+        for emulator_type in [EmulatorType::CosmacVip, EmulatorType::Chip48] {
+            let mut interpreted = Chip8::new(emulator_type, TestRng::new(5));
+            let mut cached = Chip8::new(emulator_type, TestRng::new(5));
+            interpreted.load_program(&program);
+            cached.load_program(&program);
+            let mut cache = BlockCache::new();
+
+            for frame in 0..200 {
+                for chip8 in [&mut interpreted, &mut cached] {
+                    if frame % 3 == 0 { chip8.key_down(Chip8Key::Four) } else { chip8.key_up(Chip8Key::Four) }
+                }
+                interpreted.run_frame(9);
+                cache.run_frame(&mut cached, 9);
+                assert_same_state(&interpreted, &cached);
+            }
+        }
+    }
+
+    #[test]
+    fn waits_for_the_display_like_the_interpreter() {
+        let mut interpreted = Chip8::new(EmulatorType::CosmacVip, TestRng::new(3));
+        let mut cached = Chip8::new(EmulatorType::CosmacVip, TestRng::new(3));
+        for chip8 in [&mut interpreted, &mut cached] {
+            chip8.load_program(&PROGRAM);
+            chip8.set_display_wait(true);
+        }
+        let mut cache = BlockCache::new();
+
+        for _ in 0..20 {
+            interpreted.run_frame(50);
+            cache.run_frame(&mut cached, 50);
+            assert_same_state(&interpreted, &cached);
+        }
+    }
+
+    #[test]
+    fn redecodes_blocks_whose_code_is_written() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(3));
+        chip8.load_program(&PROGRAM);
+        let mut cache = BlockCache::new();
+
+        // Runs the subroutine twice, the second time after its add was cached as ADD V2, 1
+        for _ in 0..11 {
+            cache.run_block(&mut chip8, usize::MAX);
+        }
+
+        assert_eq!(2, chip8.ram()[0x229]);
+        assert_eq!(3, chip8.register(Register::Variable(2)));
+    }
+
+    #[test]
+    fn stops_blocks_at_the_budget() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(3));
+        chip8.load_program(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xF0, 0x0A]);
+        chip8.key_down(Chip8Key::Five);
+        let mut cache = BlockCache::new();
+
+        assert_eq!(2, cache.run_block(&mut chip8, 2));
+        assert_eq!(0x204, chip8.program_counter());
+        assert_eq!(2, cache.run_block(&mut chip8, 5));
+        assert_eq!(0x206, chip8.program_counter());
+        assert_eq!(2, cache.len());
+    }
+}
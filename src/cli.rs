@@ -0,0 +1,376 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use anyhow::{anyhow, bail, Context, Result};
+use chip_8_emulator::command_line::{next_value, parse_value};
+use chip_8_emulator::coverage::Coverage;
+use chip_8_emulator::palette::Palette;
+use chip_8_emulator::persistence::PersistenceMode;
+use chip_8_emulator::profiler::Profiler;
+use chip_8_emulator::trace::{TraceFilter, TraceFormat, TraceTiming, Tracer};
+use chip_8_emulator::EmulatorType;
+
+/// The usage text printed when the arguments can't be parsed
+pub const USAGE: &str = "\
+usage: chip-8-emulator [OPTIONS] <ROM>
+
+options:
+    --type <vip|chip48|auto>
+                           the emulator type to interpret instructions as. auto picks one from
+                           the instructions the ROM uses (default: auto)
+    --speed <N|vip>        instructions executed per 60 Hz frame, or vip to give each instruction
+                           the time it took on the COSMAC VIP and make drawing wait for the next
+                           frame. vip can't be combined with --gdb or --lockstep (default: 12)
+    --display-wait         make drawing wait for the next frame after a sprite has been drawn in
+                           this one, as on the COSMAC VIP
+    --palette <NAME>       the palette to draw with: classic, amber, lcd, high-contrast or colorblind
+    --colors <RGB,...>     a custom palette of hex colors, background first, e.g. 000000,33ff66
+    --persistence <MODE>   how pixels fade when turned off: off, blend or decay:<FRAMES> (default: off)
+    --headless             run without opening a window
+    --frames <N>           the number of frames to run in headless mode (default: 600)
+    --seed <N>             seed the random number generator for reproducible runs
+    --wav <PATH>           write the buzzer output to a WAV file
+    --record <PATH>        record the session to a .gif, .y4m or .ppm file
+    --record-scale <N>     the number of output pixels per Chip-8 pixel when recording (default: 4)
+    --gdb <ADDR>           wait for gdb to attach on a TCP address, e.g. 127.0.0.1:1234, or a Unix
+                           socket given as unix:<PATH>, instead of opening a window
+    --lockstep <TYPE>      run a second interpreter of another type alongside in lockstep and
+                           report the first instruction where they diverge, showing both screens
+    --trace <PATH>         log every executed instruction to a file
+    --trace-format <FMT>   the trace format: text or binary (default: text)
+    --trace-timing <WHEN>  log register state before or after each instruction (default: before)
+    --trace-range <A-B>    only trace instructions at hex addresses A to B, can be repeated
+    --trace-opcodes <N,..> only trace opcodes whose first hex digit is one of these, e.g. 8,D
+    --tracepoint <ADDR>    always trace the instruction at a hex address, can be repeated. With no
+                           range or opcode filter, only tracepoints are traced
+    --profile <PATH>       write executions per subroutine call stack as folded stacks for flame
+                           graph tools such as flamegraph.pl or inferno
+    --profile-report <PATH>
+                           write the most executed instructions and opcode classes to a file
+    --coverage <PATH>      write a disassembly of the ROM marked with which bytes were executed,
+                           read and written
+    --coverage-html <PATH> write the coverage listing as an HTML page
+    --coverage-summary <PATH>
+                           write the coverage percentages as JSON
+    --coverage-min <PCT>   fail a headless run if less of the ROM than this percentage was executed";
+
+/// The default number of instructions executed per frame
+const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 12;
+
+/// The default number of frames a headless session runs for
+const DEFAULT_HEADLESS_FRAMES: u64 = 600;
+
+/// The default number of output pixels per Chip-8 pixel in recordings
+const DEFAULT_RECORD_SCALE: usize = 4;
+
+/// The number of hot spots listed in profile reports
+const PROFILE_REPORT_LENGTH: usize = 50;
+
+/// Options for running the emulator parsed from the command line
+#[derive(Debug)]
+pub struct Options {
+    /// The path of the ROM to load
+    pub rom_path: PathBuf,
+    /// The emulator type to interpret instructions as
+    pub emulator_type: EmulatorType,
+    /// Whether to detect the emulator type from the ROM since none was given
+    pub detect_emulator_type: bool,
+    /// The number of instructions executed per frame
+    pub instructions_per_frame: usize,
+    /// Whether to time instructions like the COSMAC VIP instead of running a fixed number per frame
+    pub vip_timing: bool,
+    /// Whether a draw waits for the next frame if a sprite was already drawn in this one
+    pub display_wait: bool,
+    /// The palette to draw with
+    pub palette: Palette,
+    /// How pixels fade when they're turned off
+    pub persistence: PersistenceMode,
+    /// Whether to run without opening a window
+    pub headless: bool,
+    /// The number of frames to run in headless mode
+    pub frames: u64,
+    /// The seed for the random number generator, if a reproducible run was requested
+    pub seed: Option<u64>,
+    /// Where to write the buzzer output as a WAV file, if anywhere
+    pub wav_path: Option<PathBuf>,
+    /// Where to record video of the session to, if anywhere
+    pub record_path: Option<PathBuf>,
+    /// The number of output pixels per Chip-8 pixel in recordings
+    pub record_scale: usize,
+    /// The address to serve the GDB remote serial protocol on, if gdb should attach
+    pub gdb_address: Option<String>,
+    /// The emulator type to run alongside in lockstep, if any
+    pub lockstep_type: Option<EmulatorType>,
+    /// Where to write an execution trace, if anywhere
+    pub trace_path: Option<PathBuf>,
+    /// The format to write the trace in
+    pub trace_format: TraceFormat,
+    /// Whether to trace register state before or after each instruction
+    pub trace_timing: TraceTiming,
+    /// The address ranges to trace, or empty to trace every address
+    pub trace_ranges: Vec<RangeInclusive<u16>>,
+    /// The opcode classes to trace, or empty to trace every class
+    pub trace_opcode_classes: Vec<u8>,
+    /// Addresses that are always traced
+    pub tracepoints: Vec<u16>,
+    /// Where to write folded stacks from profiling, if anywhere
+    pub profile_path: Option<PathBuf>,
+    /// Where to write a hot spot report from profiling, if anywhere
+    pub profile_report_path: Option<PathBuf>,
+    /// Where to write an annotated coverage listing, if anywhere
+    pub coverage_path: Option<PathBuf>,
+    /// Where to write the coverage listing as HTML, if anywhere
+    pub coverage_html_path: Option<PathBuf>,
+    /// Where to write a JSON coverage summary, if anywhere
+    pub coverage_summary_path: Option<PathBuf>,
+    /// The lowest percentage of the ROM a headless run must execute, if there's a minimum
+    pub coverage_minimum: Option<f64>,
+}
+
+impl Options {
+    /// Parses options from command line arguments, not including the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut rom_path = None;
+        let mut emulator_type = EmulatorType::CosmacVip;
+        let mut detect_emulator_type = true;
+        let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+        let mut vip_timing = false;
+        let mut display_wait = false;
+        let mut palette = Palette::default();
+        let mut persistence = PersistenceMode::Off;
+        let mut headless = false;
+        let mut frames = DEFAULT_HEADLESS_FRAMES;
+        let mut seed = None;
+        let mut wav_path = None;
+        let mut record_path = None;
+        let mut record_scale = DEFAULT_RECORD_SCALE;
+        let mut gdb_address = None;
+        let mut lockstep_type = None;
+        let mut trace_path = None;
+        let mut trace_format = TraceFormat::Text;
+        let mut trace_timing = TraceTiming::Before;
+        let mut trace_ranges = Vec::new();
+        let mut trace_opcode_classes = Vec::new();
+        let mut tracepoints = Vec::new();
+        let mut profile_path = None;
+        let mut profile_report_path = None;
+        let mut coverage_path = None;
+        let mut coverage_html_path = None;
+        let mut coverage_summary_path = None;
+        let mut coverage_minimum = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--type" => match next_value(&mut args, &arg)?.as_str() {
+                    "auto" => detect_emulator_type = true,
+                    value => {
+                        emulator_type = value.parse()?;
+                        detect_emulator_type = false;
+                    }
+                },
+                "--speed" => match next_value(&mut args, &arg)?.as_str() {
+                    "vip" => vip_timing = true,
+                    value => {
+                        instructions_per_frame = parse_value(value, &arg)?;
+                        vip_timing = false;
+                    }
+                },
+                "--display-wait" => display_wait = true,
+                "--palette" => palette = Palette::named(&next_value(&mut args, &arg)?)?,
+                "--colors" => palette = Palette::parse_custom(&next_value(&mut args, &arg)?)?,
+                "--persistence" => persistence = parse_persistence_mode(&next_value(&mut args, &arg)?)?,
+                "--headless" => headless = true,
+                "--frames" => frames = parse_value(&next_value(&mut args, &arg)?, &arg)?,
+                "--seed" => seed = Some(parse_value(&next_value(&mut args, &arg)?, &arg)?),
+                "--wav" => wav_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--record" => record_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--record-scale" => record_scale = parse_value(&next_value(&mut args, &arg)?, &arg)?,
+                "--gdb" => gdb_address = Some(next_value(&mut args, &arg)?),
+                "--lockstep" => lockstep_type = Some(next_value(&mut args, &arg)?.parse()?),
+                "--trace" => trace_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--trace-format" => trace_format = match next_value(&mut args, &arg)?.as_str() {
+                    "text" => TraceFormat::Text,
+                    "binary" => TraceFormat::Binary,
+                    value => bail!("unknown trace format {value}"),
+                },
+                "--trace-timing" => trace_timing = match next_value(&mut args, &arg)?.as_str() {
+                    "before" => TraceTiming::Before,
+                    "after" => TraceTiming::After,
+                    value => bail!("unknown trace timing {value}"),
+                },
+                "--trace-range" => trace_ranges.push(parse_address_range(&next_value(&mut args, &arg)?)?),
+                "--trace-opcodes" => {
+                    for class in next_value(&mut args, &arg)?.split(',') {
+                        trace_opcode_classes.push(parse_hex(class, &arg)? as u8 & 0xF);
+                    }
+                }
+                "--tracepoint" => tracepoints.push(parse_hex(&next_value(&mut args, &arg)?, &arg)?),
+                "--profile" => profile_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--profile-report" => profile_report_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--coverage" => coverage_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--coverage-html" => coverage_html_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--coverage-summary" => coverage_summary_path = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--coverage-min" => coverage_minimum = Some(parse_value(&next_value(&mut args, &arg)?, &arg)?),
+                _ if arg.starts_with("--") => bail!("unknown option {arg}"),
+                _ if rom_path.is_none() => rom_path = Some(PathBuf::from(arg)),
+                _ => bail!("unexpected argument {arg}"),
+            }
+        }
+
+        if vip_timing && gdb_address.is_some() {
+            bail!("--speed vip can't be used with --gdb, which runs a fixed number of instructions per frame");
+        }
+        if vip_timing && lockstep_type.is_some() {
+            bail!("--speed vip can't be used with --lockstep, which runs a fixed number of instructions per frame");
+        }
+
+        Ok(Self {
+            rom_path: rom_path.ok_or_else(|| anyhow!("no ROM given"))?,
+            emulator_type,
+            detect_emulator_type,
+            instructions_per_frame,
+            vip_timing,
+            display_wait,
+            palette,
+            persistence,
+            headless,
+            frames,
+            seed,
+            wav_path,
+            record_path,
+            record_scale,
+            gdb_address,
+            lockstep_type,
+            trace_path,
+            trace_format,
+            trace_timing,
+            trace_ranges,
+            trace_opcode_classes,
+            tracepoints,
+            profile_path,
+            profile_report_path,
+            coverage_path,
+            coverage_html_path,
+            coverage_summary_path,
+            coverage_minimum,
+        })
+    }
+
+    /// Creates the tracer requested by the trace options, if any
+    pub fn create_tracer(&self) -> Result<Option<Tracer<BufWriter<File>>>> {
+        let Some(path) = &self.trace_path else { return Ok(None) };
+
+        let filtered = !self.trace_ranges.is_empty() || !self.trace_opcode_classes.is_empty();
+        let mut filter = if filtered || self.tracepoints.is_empty() { TraceFilter::everything() } else { TraceFilter::nothing() };
+        for range in &self.trace_ranges {
+            filter = filter.with_address_range(range.clone());
+        }
+        if !self.trace_opcode_classes.is_empty() {
+            filter = filter.with_opcode_classes(&self.trace_opcode_classes);
+        }
+
+        let mut tracer = Tracer::create(path, self.trace_format, self.trace_timing)
+            .with_context(|| format!("failed to create {}", path.display()))?
+            .with_filter(filter);
+        for address in &self.tracepoints {
+            tracer.add_tracepoint(*address);
+        }
+
+        Ok(Some(tracer))
+    }
+
+    /// Creates a profiler if any profiling output was requested
+    pub fn create_profiler(&self) -> Option<Profiler> {
+        (self.profile_path.is_some() || self.profile_report_path.is_some()).then(Profiler::new)
+    }
+
+    /// Writes the profiling outputs requested by the options, disassembling hot spots from memory
+    pub fn write_profile(&self, profiler: &Profiler, memory: &[u8]) -> Result<()> {
+        if let Some(path) = &self.profile_path {
+            let mut writer = BufWriter::new(File::create(path).with_context(|| format!("failed to create {}", path.display()))?);
+            profiler.write_folded(&mut writer)?;
+            writer.flush()?;
+        }
+
+        if let Some(path) = &self.profile_report_path {
+            let mut writer = BufWriter::new(File::create(path).with_context(|| format!("failed to create {}", path.display()))?);
+            profiler.write_report(&mut writer, memory, PROFILE_REPORT_LENGTH)?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates coverage recording if any coverage output or minimum was requested
+    pub fn create_coverage(&self) -> Option<Coverage> {
+        let requested = self.coverage_path.is_some()
+            || self.coverage_html_path.is_some()
+            || self.coverage_summary_path.is_some()
+            || self.coverage_minimum.is_some();
+        requested.then(Coverage::new)
+    }
+
+    /// Writes the coverage outputs requested by the options for a program of a given length
+    pub fn write_coverage(&self, coverage: &Coverage, memory: &[u8], program_length: usize) -> Result<()> {
+        if let Some(path) = &self.coverage_path {
+            let mut writer = BufWriter::new(File::create(path).with_context(|| format!("failed to create {}", path.display()))?);
+            coverage.write_listing(&mut writer, memory, program_length)?;
+            writer.flush()?;
+        }
+
+        if let Some(path) = &self.coverage_html_path {
+            let mut writer = BufWriter::new(File::create(path).with_context(|| format!("failed to create {}", path.display()))?);
+            coverage.write_html(&mut writer, memory, program_length)?;
+            writer.flush()?;
+        }
+
+        if let Some(path) = &self.coverage_summary_path {
+            let mut writer = BufWriter::new(File::create(path).with_context(|| format!("failed to create {}", path.display()))?);
+            coverage.summary(program_length).write_json(&mut writer)?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a hex number, with or without a 0x prefix
+fn parse_hex(value: &str, option: &str) -> Result<u16> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    u16::from_str_radix(digits, 16).with_context(|| format!("invalid value {value} for {option}"))
+}
+
+/// Parses an inclusive range of hex addresses, e.g. `200-2FF`
+fn parse_address_range(value: &str) -> Result<RangeInclusive<u16>> {
+    let (start, end) = value.split_once('-').ok_or_else(|| anyhow!("invalid address range {value}"))?;
+    Ok(parse_hex(start, "--trace-range")?..=parse_hex(end, "--trace-range")?)
+}
+
+/// Parses a persistence mode, e.g. `off`, `blend` or `decay:4`
+fn parse_persistence_mode(value: &str) -> Result<PersistenceMode> {
+    match value.split_once(':') {
+        None if value == "off" => Ok(PersistenceMode::Off),
+        None if value == "blend" => Ok(PersistenceMode::Blend),
+        Some(("decay", frames)) => Ok(PersistenceMode::Decay { frames: parse_value(frames, "--persistence")? }),
+        _ => bail!("unknown persistence mode {value}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn rejects_vip_timing_in_modes_that_run_a_fixed_speed() {
+        assert!(parse(&["--speed", "vip", "rom.ch8"]).unwrap().vip_timing);
+        assert!(parse(&["--speed", "vip", "--gdb", "127.0.0.1:1234", "rom.ch8"]).is_err());
+        assert!(parse(&["--lockstep", "chip48", "--speed", "vip", "rom.ch8"]).is_err());
+        assert!(parse(&["--speed", "vip", "--speed", "20", "--lockstep", "chip48", "rom.ch8"]).is_ok());
+    }
+}
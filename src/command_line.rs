@@ -0,0 +1,76 @@
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "rand")]
+use rand::rngs::StdRng;
+#[cfg(feature = "rand")]
+use rand::SeedableRng;
+
+/// The status the command line tools exit with when their arguments are invalid or they fail
+pub const ERROR_STATUS: i32 = 2;
+
+/// An error in a tool's command line arguments
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ArgumentError(pub String);
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+/// Parses the process's arguments, not including the program name. If they're invalid, prints
+/// the error and the usage text and exits with [`ERROR_STATUS`]
+pub fn parse_args<T, E: fmt::Display>(usage: &str, parse: impl FnOnce(std::iter::Skip<std::env::Args>) -> Result<T, E>) -> T {
+    parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("error: {error:#}\n\n{usage}");
+        std::process::exit(ERROR_STATUS);
+    })
+}
+
+/// Unwraps the result of running a tool, or prints the error and exits with [`ERROR_STATUS`]
+pub fn exit_on_error<T, E: fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("error: {error:#}");
+        std::process::exit(ERROR_STATUS);
+    })
+}
+
+/// Takes the value following an option, failing if there isn't one
+pub fn next_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, ArgumentError> {
+    args.next().ok_or_else(|| ArgumentError(format!("{option} requires a value")))
+}
+
+/// Parses an option's value, e.g. a number
+pub fn parse_value<T: FromStr>(value: &str, option: &str) -> Result<T, ArgumentError>
+where
+    T::Err: fmt::Display,
+{
+    value.parse().map_err(|error| ArgumentError(format!("invalid value {value} for {option}: {error}")))
+}
+
+/// Creates the random number generator for a `--seed` option: seeded from it if given, so runs
+/// with the same seed produce identical output, or from the thread's generator if not
+#[cfg(feature = "rand")]
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_rng(&mut rand::rng()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_option_values() {
+        let mut args = ["20".to_string()].into_iter();
+
+        assert_eq!(Ok("20".to_string()), next_value(&mut args, "--speed"));
+        assert_eq!(Err(ArgumentError("--speed requires a value".to_string())), next_value(&mut args, "--speed"));
+        assert_eq!(Ok(20), parse_value::<usize>("20", "--speed"));
+        assert!(parse_value::<usize>("fast", "--speed").unwrap_err().0.starts_with("invalid value fast for --speed"));
+    }
+}
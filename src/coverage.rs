@@ -0,0 +1,347 @@
+use std::io::{self, Write};
+use std::ops::Range;
+use serde_json::json;
+use crate::instruction::{disassemble, DisassembledInstruction, Instruction};
+use crate::{Chip8, Chip8Rng, ExecutionObserver, MEMORY_SIZE, PROGRAM_START_ADDRESS};
+
+/// How a byte of memory was accessed while running
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct ByteAccess {
+    /// Whether the byte was fetched as part of an instruction
+    pub executed: bool,
+    /// Whether an instruction read the byte as data
+    pub read: bool,
+    /// Whether an instruction wrote to the byte
+    pub written: bool,
+}
+
+impl ByteAccess {
+    /// Gets the kind of region the byte belongs to
+    pub fn region(&self) -> Region {
+        if self.executed {
+            Region::Executed
+        } else if self.read || self.written {
+            Region::Data
+        } else {
+            Region::Untouched
+        }
+    }
+
+    /// Gets the access flags as three characters, e.g. `x--` or `-rw`
+    fn flags(&self) -> String {
+        [(self.executed, 'x'), (self.read, 'r'), (self.written, 'w')].iter()
+            .map(|(set, flag)| if *set { *flag } else { '-' })
+            .collect()
+    }
+}
+
+/// The kind of region a byte of memory belongs to after a run
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Region {
+    /// Executed as an instruction, whether or not it was also accessed as data
+    Executed,
+    /// Read or written as data but never executed
+    Data,
+    /// Never accessed
+    Untouched,
+}
+
+impl Region {
+    /// Gets the name used for the region in reports
+    fn name(&self) -> &'static str {
+        match self {
+            Region::Executed => "executed",
+            Region::Data => "data",
+            Region::Untouched => "untouched",
+        }
+    }
+}
+
+/// The number of program bytes in each region after a run
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct CoverageSummary {
+    /// The number of bytes the program occupies
+    pub program_bytes: usize,
+    /// The number of program bytes that were executed
+    pub executed_bytes: usize,
+    /// The number of program bytes that were only accessed as data
+    pub data_bytes: usize,
+    /// The number of program bytes that were never accessed
+    pub untouched_bytes: usize,
+}
+
+impl CoverageSummary {
+    /// Gets the percentage of program bytes that were executed
+    pub fn executed_percentage(&self) -> f64 {
+        self.executed_bytes as f64 * 100.0 / self.program_bytes.max(1) as f64
+    }
+
+    /// Gets the percentage of program bytes that were executed or accessed as data
+    pub fn touched_percentage(&self) -> f64 {
+        (self.executed_bytes + self.data_bytes) as f64 * 100.0 / self.program_bytes.max(1) as f64
+    }
+
+    /// Writes the summary as a JSON object for checking coverage thresholds in CI
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let summary = json!({
+            "program_bytes": self.program_bytes,
+            "executed_bytes": self.executed_bytes,
+            "data_bytes": self.data_bytes,
+            "untouched_bytes": self.untouched_bytes,
+            "executed_percentage": self.executed_percentage(),
+            "touched_percentage": self.touched_percentage(),
+        });
+        writeln!(writer, "{summary:#}")
+    }
+}
+
+/// One line of an annotated listing, either an instruction or a single byte of data
+struct ListingLine {
+    address: u16,
+    access: ByteAccess,
+    bytes: String,
+    text: String,
+}
+
+/// Records which bytes of memory are executed, read and written while running, as an
+/// [`ExecutionObserver`], and reports on the program's coverage
+pub struct Coverage {
+    /// The accesses to each byte of memory
+    accesses: Vec<ByteAccess>,
+}
+
+impl Coverage {
+    /// Creates coverage with no bytes accessed
+    pub fn new() -> Self {
+        Self { accesses: vec![ByteAccess::default(); MEMORY_SIZE] }
+    }
+
+    /// Gets how the byte at an address was accessed
+    pub fn access(&self, address: u16) -> ByteAccess {
+        self.accesses.get(address as usize).copied().unwrap_or_default()
+    }
+
+    /// Summarizes the coverage of a program of a given length loaded at the usual start address
+    pub fn summary(&self, program_length: usize) -> CoverageSummary {
+        let mut summary = CoverageSummary {
+            program_bytes: 0,
+            executed_bytes: 0,
+            data_bytes: 0,
+            untouched_bytes: 0,
+        };
+
+        for access in &self.accesses[program_range(program_length)] {
+            summary.program_bytes += 1;
+            match access.region() {
+                Region::Executed => summary.executed_bytes += 1,
+                Region::Data => summary.data_bytes += 1,
+                Region::Untouched => summary.untouched_bytes += 1,
+            }
+        }
+
+        summary
+    }
+
+    /// Writes a disassembly of the program annotated with how each line was accessed. Executed
+    /// bytes are disassembled as instructions, data bytes are listed one per line and untouched
+    /// bytes are disassembled in aligned pairs in case they're code that was never reached
+    pub fn write_listing<W: Write>(&self, writer: &mut W, memory: &[u8], program_length: usize) -> io::Result<()> {
+        let summary = self.summary(program_length);
+        writeln!(writer, "; {} of {} bytes executed ({:.2}%), {} data only, {} untouched",
+                 summary.executed_bytes,
+                 summary.program_bytes,
+                 summary.executed_percentage(),
+                 summary.data_bytes,
+                 summary.untouched_bytes)?;
+        writeln!(writer, "; flags: x executed, r read, w written")?;
+
+        for line in self.listing(memory, program_length) {
+            writeln!(writer, "{} {:03X}  {:<4}  {}", line.access.flags(), line.address, line.bytes, line.text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the annotated listing as a static HTML page with each region highlighted
+    pub fn write_html<W: Write>(&self, writer: &mut W, memory: &[u8], program_length: usize) -> io::Result<()> {
+        let summary = self.summary(program_length);
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(writer, "<html>")?;
+        writeln!(writer, "<head>")?;
+        writeln!(writer, "<meta charset=\"utf-8\">")?;
+        writeln!(writer, "<title>Chip-8 coverage</title>")?;
+        writeln!(writer, "<style>")?;
+        writeln!(writer, "body {{ font-family: monospace; background: #fff; color: #222; }}")?;
+        writeln!(writer, ".executed {{ background: #c8f0c8; }}")?;
+        writeln!(writer, ".data {{ background: #f0e0a0; }}")?;
+        writeln!(writer, ".untouched {{ background: #f0c8c8; }}")?;
+        writeln!(writer, "</style>")?;
+        writeln!(writer, "</head>")?;
+        writeln!(writer, "<body>")?;
+        writeln!(writer, "<h1>Chip-8 coverage</h1>")?;
+        writeln!(writer, "<p>{} of {} bytes executed ({:.2}%), <span class=\"data\">{} data only</span>, \
+                          <span class=\"untouched\">{} untouched</span></p>",
+                 summary.executed_bytes,
+                 summary.program_bytes,
+                 summary.executed_percentage(),
+                 summary.data_bytes,
+                 summary.untouched_bytes)?;
+        writeln!(writer, "<pre>")?;
+
+        for line in self.listing(memory, program_length) {
+            writeln!(writer, "<span class=\"{}\">{} {:03X}  {:<4}  {}</span>",
+                     line.access.region().name(),
+                     line.access.flags(),
+                     line.address,
+                     line.bytes,
+                     escape_html(&line.text))?;
+        }
+
+        writeln!(writer, "</pre>")?;
+        writeln!(writer, "</body>")?;
+        writeln!(writer, "</html>")
+    }
+
+    /// Splits the program into annotated listing lines
+    fn listing(&self, memory: &[u8], program_length: usize) -> Vec<ListingLine> {
+        let range = program_range(program_length);
+        let mut lines = Vec::new();
+        let mut address = range.start;
+
+        while address < range.end {
+            let access = self.accesses[address];
+            let next = self.accesses.get(address + 1).copied().unwrap_or_default();
+            let is_aligned = (address - range.start).is_multiple_of(2);
+            let is_untouched_word = is_aligned && access.region() == Region::Untouched && next.region() == Region::Untouched;
+            let is_word = address + 1 < range.end && (access.executed || is_untouched_word);
+
+            if is_word {
+                let instruction = disassemble(memory, address as u16, 1)[0];
+                lines.push(ListingLine {
+                    address: address as u16,
+                    access,
+                    bytes: format!("{:04X}", instruction.opcode),
+                    text: instruction.instruction.to_string(),
+                });
+                address += 2;
+            } else {
+                lines.push(ListingLine {
+                    address: address as u16,
+                    access,
+                    bytes: format!("{:02X}", memory[address]),
+                    text: format!("DB 0x{:02X}", memory[address]),
+                });
+                address += 1;
+            }
+        }
+
+        lines
+    }
+
+    /// Marks a run of bytes starting at an address, stopping at the end of memory
+    fn mark(&mut self, start: u16, length: usize, mark: impl Fn(&mut ByteAccess)) {
+        for access in self.accesses.iter_mut().skip(start as usize).take(length) {
+            mark(access);
+        }
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExecutionObserver for Coverage {
+    fn before_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        self.mark(instruction.address, 2, |access| access.executed = true);
+
+        let index = chip8.index_register();
+        match instruction.instruction {
+            Instruction::Draw { n, .. } => self.mark(index, n as usize, |access| access.read = true),
+            Instruction::LoadRegisters { x } => self.mark(index, x as usize + 1, |access| access.read = true),
+            Instruction::StoreRegisters { x } => self.mark(index, x as usize + 1, |access| access.written = true),
+            Instruction::StoreDecimalDigits { .. } => self.mark(index, 3, |access| access.written = true),
+            _ => {}
+        }
+    }
+}
+
+/// Gets the range of memory a program of a given length occupies
+fn program_range(program_length: usize) -> Range<usize> {
+    let start = PROGRAM_START_ADDRESS as usize;
+    start..(start + program_length).min(MEMORY_SIZE)
+}
+
+/// Escapes the characters that are special in HTML text
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::EmulatorType;
+    use super::*;
+
+    /// Draws the sprite at 0x20A, stores V0 at 0x20C, then loops forever, leaving 0x20E untouched
+    const PROGRAM: [u8; 16] = [
+        0xA2, 0x0A, 0xD0, 0x01, 0xA2, 0x0C, 0xF0, 0x55,
+        0x12, 0x08, 0xF0, 0x00, 0x00, 0x00, 0x12, 0x34,
+    ];
+
+    fn cover() -> (Coverage, Chip8<TestRng>) {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&PROGRAM);
+        let mut coverage = Coverage::new();
+        for _ in 0..6 {
+            chip8.execute_next_instruction_with(&mut coverage);
+        }
+        (coverage, chip8)
+    }
+
+    #[test]
+    fn records_execution_reads_and_writes() {
+        let (coverage, _) = cover();
+
+        assert_eq!(ByteAccess { executed: true, read: false, written: false }, coverage.access(0x201));
+        assert_eq!(ByteAccess { executed: false, read: true, written: false }, coverage.access(0x20A));
+        assert_eq!(ByteAccess { executed: false, read: false, written: true }, coverage.access(0x20C));
+        assert_eq!(Region::Untouched, coverage.access(0x20B).region());
+        assert_eq!(CoverageSummary { program_bytes: 16, executed_bytes: 10, data_bytes: 2, untouched_bytes: 4 },
+                   coverage.summary(PROGRAM.len()));
+    }
+
+    #[test]
+    fn writes_annotated_listings() {
+        let (coverage, chip8) = cover();
+        let mut listing = Vec::new();
+
+        coverage.write_listing(&mut listing, chip8.ram(), PROGRAM.len()).unwrap();
+
+        let listing = String::from_utf8(listing).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!("; 10 of 16 bytes executed (62.50%), 2 data only, 4 untouched", lines[0]);
+        assert_eq!("x-- 200  A20A  LD I, 0x20A", lines[2]);
+        assert_eq!("-r- 20A  F0    DB 0xF0", lines[7]);
+        assert_eq!("--- 20B  00    DB 0x00", lines[8]);
+        assert_eq!("--w 20C  00    DB 0x00", lines[9]);
+        assert_eq!("--- 20E  1234  JP 0x234", lines[11]);
+    }
+
+    #[test]
+    fn writes_html_and_json_summaries() {
+        let (coverage, chip8) = cover();
+        let mut html = Vec::new();
+        let mut json = Vec::new();
+
+        coverage.write_html(&mut html, chip8.ram(), PROGRAM.len()).unwrap();
+        coverage.summary(PROGRAM.len()).write_json(&mut json).unwrap();
+
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("<span class=\"data\">-r- 20A  F0    DB 0xF0</span>"));
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(10, json["executed_bytes"]);
+        assert_eq!(62.5, json["executed_percentage"]);
+    }
+}
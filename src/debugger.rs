@@ -0,0 +1,302 @@
+use std::collections::BTreeSet;
+use crate::instruction::{disassemble, Instruction};
+use crate::timing::VipTiming;
+use crate::{Chip8, Chip8Rng, ExecutionObserver};
+
+/// The result of running the emulator under the debugger
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RunOutcome {
+    /// A single instruction was executed without completing the frame
+    Stepped,
+    /// The frame ran to completion and the timers were decremented
+    FrameCompleted,
+    /// Execution stopped before running the instruction at a breakpoint
+    BreakpointHit {
+        /// The address of the breakpoint
+        address: u16,
+    },
+    /// Execution stopped because the program counter points at something that isn't an
+    /// instruction, which the interpreter would panic on
+    InvalidOpcode {
+        /// The address of the opcode
+        address: u16,
+        /// The opcode
+        opcode: u16,
+    },
+}
+
+/// Runs a Chip-8 interpreter with breakpoints and single stepping.
+/// The debugger keeps track of how far into the current 60 Hz frame execution is, so stopping in
+/// the middle of a frame and resuming, or stepping one instruction at a time, still decrements
+/// the timers once every `instructions_per_frame` instructions, or when the frame's time is up
+/// with VIP timing.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    /// Addresses to stop at before executing
+    breakpoints: BTreeSet<u16>,
+    /// The number of instructions executed since the timers were last decremented
+    instructions_into_frame: usize,
+    /// Set after stopping at a breakpoint so that resuming executes the instruction there instead
+    /// of stopping at the same breakpoint again
+    skip_next_breakpoint: bool,
+    /// Times frames like the COSMAC VIP instead of by instruction count, if set
+    timing: Option<VipTiming>,
+}
+
+impl Debugger {
+    /// Creates a debugger with no breakpoints
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a breakpoint. Returns false if there already was one at the address
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Removes a breakpoint. Returns false if there was no breakpoint at the address
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Adds a breakpoint if there isn't one at the address, otherwise removes it. Returns whether
+    /// there is a breakpoint at the address afterwards
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.remove_breakpoint(address) {
+            false
+        } else {
+            self.add_breakpoint(address)
+        }
+    }
+
+    /// Returns whether there is a breakpoint at the address
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    /// Gets the breakpoint addresses in ascending order
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Times frames like the COSMAC VIP with [`VipTiming`], ignoring `instructions_per_frame`, or
+    /// goes back to counting instructions
+    pub fn set_vip_timing(&mut self, enabled: bool) {
+        self.timing = enabled.then(VipTiming::new);
+    }
+
+    /// Returns whether frames are timed like the COSMAC VIP
+    pub fn has_vip_timing(&self) -> bool {
+        self.timing.is_some()
+    }
+
+    /// Gets the number of instructions executed in the current frame
+    pub fn instructions_into_frame(&self) -> usize {
+        self.instructions_into_frame
+    }
+
+    /// Executes a single instruction, ignoring breakpoints. Decrements the timers if the
+    /// instruction completes a frame. Nothing is executed if the program counter doesn't point at
+    /// a valid instruction
+    pub fn step<R: Chip8Rng>(&mut self, chip8: &mut Chip8<R>, instructions_per_frame: usize) -> RunOutcome {
+        self.step_with(chip8, instructions_per_frame, &mut ())
+    }
+
+    /// Executes a single instruction like [`Debugger::step`], passing it to an observer
+    pub fn step_with<R: Chip8Rng, O: ExecutionObserver>(&mut self,
+                                                   chip8: &mut Chip8<R>,
+                                                   instructions_per_frame: usize,
+                                                   observer: &mut O) -> RunOutcome {
+        let address = chip8.program_counter();
+        match disassemble(chip8.ram(), address, 1).first() {
+            Some(line) if line.instruction != Instruction::Invalid { opcode: line.opcode } => {}
+            line => return RunOutcome::InvalidOpcode { address, opcode: line.map_or(0, |line| line.opcode) },
+        }
+
+        self.skip_next_breakpoint = false;
+        self.instructions_into_frame += 1;
+
+        if let Some(timing) = &mut self.timing {
+            timing.execute_next_instruction_with(chip8, observer);
+            if timing.is_frame_over(chip8) {
+                timing.end_frame(chip8);
+                self.instructions_into_frame = 0;
+                return RunOutcome::FrameCompleted;
+            }
+        } else {
+            chip8.execute_next_instruction_with(observer);
+            if self.instructions_into_frame >= instructions_per_frame {
+                chip8.decrement_timers();
+                self.instructions_into_frame = 0;
+                return RunOutcome::FrameCompleted;
+            }
+        }
+
+        RunOutcome::Stepped
+    }
+
+    /// Runs the rest of the current frame, stopping early if a breakpoint or an invalid opcode is
+    /// reached
+    pub fn run_frame<R: Chip8Rng>(&mut self, chip8: &mut Chip8<R>, instructions_per_frame: usize) -> RunOutcome {
+        self.run_frame_with(chip8, instructions_per_frame, &mut ())
+    }
+
+    /// Runs the rest of the current frame like [`Debugger::run_frame`], passing each instruction
+    /// to an observer
+    pub fn run_frame_with<R: Chip8Rng, O: ExecutionObserver>(&mut self,
+                                                        chip8: &mut Chip8<R>,
+                                                        instructions_per_frame: usize,
+                                                        observer: &mut O) -> RunOutcome {
+        self.run_for_with(chip8, instructions_per_frame, usize::MAX, observer)
+    }
+
+    /// Runs like [`Debugger::run_frame`], but stops after at most `max_instructions`, returning
+    /// [`RunOutcome::Stepped`] if that leaves the frame unfinished. This lets a caller check for
+    /// input regularly when frames are long
+    pub fn run_for<R: Chip8Rng>(&mut self,
+                                chip8: &mut Chip8<R>,
+                                instructions_per_frame: usize,
+                                max_instructions: usize) -> RunOutcome {
+        self.run_for_with(chip8, instructions_per_frame, max_instructions, &mut ())
+    }
+
+    /// Runs like [`Debugger::run_for`], passing each instruction to an observer
+    fn run_for_with<R: Chip8Rng, O: ExecutionObserver>(&mut self,
+                                                  chip8: &mut Chip8<R>,
+                                                  instructions_per_frame: usize,
+                                                  max_instructions: usize,
+                                                  observer: &mut O) -> RunOutcome {
+        for _ in 0..max_instructions {
+            let address = chip8.program_counter();
+            if self.has_breakpoint(address) && !self.skip_next_breakpoint {
+                self.skip_next_breakpoint = true;
+                return RunOutcome::BreakpointHit { address };
+            }
+
+            match self.step_with(chip8, instructions_per_frame, observer) {
+                RunOutcome::Stepped => {}
+                outcome => return outcome,
+            }
+        }
+
+        RunOutcome::Stepped
+    }
+
+    /// Forgets how far into the frame execution is, e.g. after the interpreter was reset
+    pub fn reset_frame(&mut self) {
+        self.instructions_into_frame = 0;
+        self.skip_next_breakpoint = false;
+        if let Some(timing) = &mut self.timing {
+            *timing = VipTiming::new();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::EmulatorType;
+    use super::*;
+
+    /// A program that counts up in V0 forever
+    const COUNTING_PROGRAM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn counting_chip8() -> Chip8<TestRng> {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&COUNTING_PROGRAM);
+        chip8
+    }
+
+    #[test]
+    fn can_add_remove_and_toggle_breakpoints() {
+        let mut debugger = Debugger::new();
+
+        assert!(debugger.add_breakpoint(0x300));
+        assert!(!debugger.add_breakpoint(0x300));
+        assert!(debugger.toggle_breakpoint(0x200));
+        assert_eq!(vec![0x200, 0x300], debugger.breakpoints().collect::<Vec<_>>());
+
+        assert!(!debugger.toggle_breakpoint(0x200));
+        assert!(debugger.remove_breakpoint(0x300));
+        assert!(!debugger.remove_breakpoint(0x300));
+        assert_eq!(0, debugger.breakpoints().count());
+    }
+
+    #[test]
+    fn run_frame_without_breakpoints_completes_frame() {
+        let mut chip8 = counting_chip8();
+        let mut debugger = Debugger::new();
+
+        assert_eq!(RunOutcome::FrameCompleted, debugger.run_frame(&mut chip8, 10));
+        assert_eq!(5, chip8.variable_registers()[0]);
+        assert_eq!(0, debugger.instructions_into_frame());
+    }
+
+    #[test]
+    fn run_frame_stops_at_breakpoint_and_resumes_past_it() {
+        let mut chip8 = counting_chip8();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x202);
+
+        assert_eq!(RunOutcome::BreakpointHit { address: 0x202 }, debugger.run_frame(&mut chip8, 10));
+        assert_eq!(0x202, chip8.program_counter());
+        assert_eq!(1, chip8.variable_registers()[0]);
+
+        assert_eq!(RunOutcome::BreakpointHit { address: 0x202 }, debugger.run_frame(&mut chip8, 10));
+        assert_eq!(2, chip8.variable_registers()[0]);
+        assert_eq!(3, debugger.instructions_into_frame());
+    }
+
+    #[test]
+    fn run_for_stops_after_the_instruction_limit_or_the_frame() {
+        let mut chip8 = counting_chip8();
+        let mut debugger = Debugger::new();
+
+        assert_eq!(RunOutcome::Stepped, debugger.run_for(&mut chip8, 10, 4));
+        assert_eq!(4, debugger.instructions_into_frame());
+        assert_eq!(RunOutcome::FrameCompleted, debugger.run_for(&mut chip8, 10, 100));
+        assert_eq!(0, debugger.instructions_into_frame());
+        assert_eq!(5, chip8.variable_registers()[0]);
+    }
+
+    #[test]
+    fn stepping_decrements_timers_once_per_frame() {
+        let mut chip8 = counting_chip8();
+        chip8.load_program(&[0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+        let mut debugger = Debugger::new();
+
+        assert_eq!(RunOutcome::Stepped, debugger.step(&mut chip8, 3));
+        assert_eq!(RunOutcome::Stepped, debugger.step(&mut chip8, 3));
+        assert_eq!(5, chip8.delay_timer());
+
+        assert_eq!(RunOutcome::FrameCompleted, debugger.step(&mut chip8, 3));
+        assert_eq!(4, chip8.delay_timer());
+    }
+
+    #[test]
+    fn vip_timing_runs_frames_like_the_timing_model() {
+        let mut chip8 = counting_chip8();
+        let mut expected = counting_chip8();
+        let mut timing = VipTiming::new();
+        let mut debugger = Debugger::new();
+        debugger.set_vip_timing(true);
+
+        for _ in 0..3 {
+            assert_eq!(RunOutcome::FrameCompleted, debugger.run_frame(&mut chip8, 1));
+            timing.run_frame(&mut expected);
+            assert_eq!(expected.variable_registers(), chip8.variable_registers());
+        }
+        assert!(chip8.variable_registers()[0] > 3);
+    }
+
+    #[test]
+    fn stops_at_invalid_opcodes_instead_of_executing_them() {
+        let mut chip8 = counting_chip8();
+        chip8.load_program(&[0x60, 0x05, 0xFF, 0xFF]);
+        let mut debugger = Debugger::new();
+
+        assert_eq!(RunOutcome::InvalidOpcode { address: 0x202, opcode: 0xFFFF }, debugger.run_frame(&mut chip8, 10));
+        assert_eq!(RunOutcome::InvalidOpcode { address: 0x202, opcode: 0xFFFF }, debugger.step(&mut chip8, 10));
+        assert_eq!(0x202, chip8.program_counter());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::analysis::{Analysis, BasicBlock, EdgeKind, Unknown};
+use crate::instruction::Instruction;
+use crate::{EmulatorType, DISPLAY_HEIGHT, DISPLAY_WIDTH, VARIABLE_REGISTER_COUNT};
+
+/// The width of every sprite in pixels
+const SPRITE_WIDTH: usize = 8;
+
+/// A platform a ROM may have been written for
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Platform {
+    /// The original interpreter on the COSMAC VIP
+    CosmacVip,
+    /// The HP-48 calculator interpreter
+    Chip48,
+    /// SUPER-CHIP, which adds high resolution, scrolling and 16x16 sprites to CHIP-48
+    SuperChip,
+    /// XO-CHIP, which extends SUPER-CHIP with more memory, colors and audio, keeping VIP quirks
+    XoChip,
+}
+
+impl Platform {
+    /// Every platform, in the order ties are broken in
+    pub const ALL: [Platform; 4] = [Platform::CosmacVip, Platform::Chip48, Platform::SuperChip, Platform::XoChip];
+
+    /// Gets the emulator type whose quirks are closest to the platform's
+    pub fn emulator_type(&self) -> EmulatorType {
+        match self {
+            Platform::CosmacVip | Platform::XoChip => EmulatorType::CosmacVip,
+            Platform::Chip48 | Platform::SuperChip => EmulatorType::Chip48,
+        }
+    }
+
+    /// Gets the quirks programs for the platform expect
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks { shift_uses_vy: true, load_store_increments_index: true, jump_uses_vx: false, sprites_wrap: false },
+            Platform::Chip48 | Platform::SuperChip => Quirks { shift_uses_vy: false, load_store_increments_index: false, jump_uses_vx: true, sprites_wrap: false },
+            Platform::XoChip => Quirks { shift_uses_vy: true, load_store_increments_index: true, jump_uses_vx: false, sprites_wrap: true },
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::CosmacVip => write!(f, "COSMAC VIP"),
+            Platform::Chip48 => write!(f, "CHIP-48"),
+            Platform::SuperChip => write!(f, "SUPER-CHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+/// The behaviors that differ between platforms
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Quirks {
+    /// Whether 8XY6 and 8XYE shift VY into VX rather than shifting VX in place
+    pub shift_uses_vy: bool,
+    /// Whether FX55 and FX65 leave the index register pointing past the last register
+    pub load_store_increments_index: bool,
+    /// Whether BNNN jumps to NNN plus VX rather than plus V0
+    pub jump_uses_vx: bool,
+    /// Whether sprites drawn past an edge wrap around to the other side rather than being clipped
+    pub sprites_wrap: bool,
+}
+
+/// Something about a ROM's reachable code that hints at the platform it was written for
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Evidence {
+    /// An instruction only SUPER-CHIP and XO-CHIP have
+    SuperChipOpcode { address: u16, opcode: u16 },
+    /// An instruction only XO-CHIP has
+    XoChipOpcode { address: u16, opcode: u16 },
+    /// A shift from one register into another, which only works if VY is shifted
+    ShiftBetweenRegisters { address: u16 },
+    /// FX55 or FX65 run repeatedly without resetting the index register, so it relies on the
+    /// index register being incremented
+    ReliesOnIndexIncrement { address: u16 },
+    /// FX55 or FX65 run in a loop that advances the index register itself with FX1E
+    AdvancesIndexManually { address: u16 },
+    /// A BNNN jump, which is usually a V0 jump table from the VIP
+    JumpWithOffset { address: u16 },
+    /// A sprite drawn at constant coordinates where it crosses the edge of the screen
+    SpriteOffEdge { address: u16 },
+}
+
+impl Evidence {
+    /// Gets the address of the instruction the evidence was found at
+    pub fn address(&self) -> u16 {
+        match *self {
+            Evidence::SuperChipOpcode { address, .. }
+            | Evidence::XoChipOpcode { address, .. }
+            | Evidence::ShiftBetweenRegisters { address }
+            | Evidence::ReliesOnIndexIncrement { address }
+            | Evidence::AdvancesIndexManually { address }
+            | Evidence::JumpWithOffset { address }
+            | Evidence::SpriteOffEdge { address } => address,
+        }
+    }
+
+    /// Gets how strongly the evidence points at a platform
+    fn weight(&self, platform: Platform) -> u32 {
+        use Platform::*;
+        match (self, platform) {
+            (Evidence::XoChipOpcode { .. }, XoChip) => 10,
+            (Evidence::SuperChipOpcode { .. }, SuperChip) => 10,
+            (Evidence::SuperChipOpcode { .. }, XoChip) => 5,
+            (Evidence::ShiftBetweenRegisters { .. }, CosmacVip | XoChip) => 2,
+            (Evidence::ReliesOnIndexIncrement { .. }, CosmacVip | XoChip) => 3,
+            (Evidence::AdvancesIndexManually { .. }, Chip48 | SuperChip) => 2,
+            (Evidence::JumpWithOffset { .. }, CosmacVip | XoChip) => 1,
+            (Evidence::SpriteOffEdge { .. }, CosmacVip | Chip48 | SuperChip) => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:03X}: ", self.address())?;
+        match *self {
+            Evidence::SuperChipOpcode { opcode, .. } => write!(f, "SUPER-CHIP instruction {opcode:04X}"),
+            Evidence::XoChipOpcode { opcode, .. } => write!(f, "XO-CHIP instruction {opcode:04X}"),
+            Evidence::ShiftBetweenRegisters { .. } => write!(f, "shift between different registers"),
+            Evidence::ReliesOnIndexIncrement { .. } => write!(f, "relies on FX55/FX65 incrementing I"),
+            Evidence::AdvancesIndexManually { .. } => write!(f, "advances I itself around FX55/FX65"),
+            Evidence::JumpWithOffset { .. } => write!(f, "BNNN jump"),
+            Evidence::SpriteOffEdge { .. } => write!(f, "sprite drawn across the edge of the screen"),
+        }
+    }
+}
+
+/// The platform a ROM was most likely written for, judged from its reachable code
+#[derive(Debug, PartialEq, Clone)]
+pub struct Detection {
+    /// The most likely platform. The COSMAC VIP if there's no evidence either way
+    pub platform: Platform,
+    /// How sure the detection is, from 0 with no evidence to 1 when nothing points elsewhere
+    pub confidence: f64,
+    /// What the detection was based on, in address order
+    pub evidence: Vec<Evidence>,
+}
+
+impl Detection {
+    /// Detects the platform of a program as it would be loaded at the usual start address
+    pub fn detect(program: &[u8]) -> Self {
+        Self::from_analysis(&Analysis::analyze(program))
+    }
+
+    /// Detects the platform from an analysis of the program
+    pub fn from_analysis(analysis: &Analysis) -> Self {
+        let mut evidence = Vec::new();
+        find_extension_opcodes(analysis, &mut evidence);
+        find_quirk_uses(analysis, &mut evidence);
+        find_index_loops(analysis, &mut evidence);
+        evidence.sort_by_key(Evidence::address);
+        evidence.dedup();
+
+        let mut scores: Vec<(Platform, u32)> = Platform::ALL.iter()
+            .map(|platform| (*platform, evidence.iter().map(|evidence| evidence.weight(*platform)).sum()))
+            .collect();
+        // Stable, so ties keep the platform order
+        scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+        let (platform, best) = scores[0];
+        let second = scores[1].1;
+        let confidence = if best == 0 { 0.0 } else { best as f64 / (best + second) as f64 };
+
+        Self { platform, confidence, evidence }
+    }
+
+    /// Gets the emulator type to run the ROM as
+    pub fn emulator_type(&self) -> EmulatorType {
+        self.platform.emulator_type()
+    }
+
+    /// Gets the quirks the ROM most likely expects
+    pub fn quirks(&self) -> Quirks {
+        self.platform.quirks()
+    }
+}
+
+/// Gets the platform an opcode this interpreter can't run belongs to, if it's a known extension
+fn extension_platform(opcode: u16) -> Option<Platform> {
+    match opcode {
+        0x00C1..=0x00CF | 0x00FB..=0x00FF => Some(Platform::SuperChip),
+        0x00D1..=0x00DF | 0xF000 | 0xF002 => Some(Platform::XoChip),
+        _ => match opcode & 0xF0FF {
+            0xF030 | 0xF075 | 0xF085 => Some(Platform::SuperChip),
+            0xF001 | 0xF03A => Some(Platform::XoChip),
+            _ if opcode & 0xF00F == 0x5002 || opcode & 0xF00F == 0x5003 => Some(Platform::XoChip),
+            _ => None,
+        },
+    }
+}
+
+/// Finds extension instructions where the analysis stopped at an invalid opcode, and SUPER-CHIP's
+/// DXY0 16x16 sprites
+fn find_extension_opcodes(analysis: &Analysis, evidence: &mut Vec<Evidence>) {
+    for unknown in analysis.unknowns() {
+        if let Unknown::InvalidOpcode { address, opcode } = *unknown {
+            match extension_platform(opcode) {
+                Some(Platform::SuperChip) => evidence.push(Evidence::SuperChipOpcode { address, opcode }),
+                Some(Platform::XoChip) => evidence.push(Evidence::XoChipOpcode { address, opcode }),
+                _ => {}
+            }
+        }
+    }
+
+    for instruction in analysis.blocks().flat_map(|block| &block.instructions) {
+        if let Instruction::Draw { n: 0, .. } = instruction.instruction {
+            evidence.push(Evidence::SuperChipOpcode { address: instruction.address, opcode: instruction.opcode });
+        }
+    }
+}
+
+/// Finds shifts between registers, BNNN jumps and sprites drawn across an edge at coordinates
+/// set by constants earlier in the same block
+fn find_quirk_uses(analysis: &Analysis, evidence: &mut Vec<Evidence>) {
+    for block in analysis.blocks() {
+        let mut registers: [Option<u8>; VARIABLE_REGISTER_COUNT] = [None; VARIABLE_REGISTER_COUNT];
+
+        for instruction in &block.instructions {
+            let address = instruction.address;
+            match instruction.instruction {
+                Instruction::ShiftVxRight { x, y } | Instruction::ShiftVxLeft { x, y } if x != y => {
+                    evidence.push(Evidence::ShiftBetweenRegisters { address });
+                }
+                Instruction::JumpWithOffset { .. } => evidence.push(Evidence::JumpWithOffset { address }),
+                Instruction::Draw { x, y, n } if n > 0 => {
+                    let crosses_right = registers[x as usize]
+                        .is_some_and(|vx| vx as usize % DISPLAY_WIDTH + SPRITE_WIDTH > DISPLAY_WIDTH);
+                    let crosses_bottom = registers[y as usize]
+                        .is_some_and(|vy| vy as usize % DISPLAY_HEIGHT + n as usize > DISPLAY_HEIGHT);
+                    if crosses_right || crosses_bottom {
+                        evidence.push(Evidence::SpriteOffEdge { address });
+                    }
+                }
+                _ => {}
+            }
+
+            track_constants(&instruction.instruction, &mut registers);
+        }
+    }
+}
+
+/// Updates which registers hold known constants after an instruction
+fn track_constants(instruction: &Instruction, registers: &mut [Option<u8>; VARIABLE_REGISTER_COUNT]) {
+    match *instruction {
+        Instruction::SetVx { x, nn } => registers[x as usize] = Some(nn),
+        Instruction::AddToVx { x, nn } => registers[x as usize] = registers[x as usize].map(|vx| vx.wrapping_add(nn)),
+        Instruction::SetVxToVy { x, .. }
+        | Instruction::OrVxWithVy { x, .. }
+        | Instruction::AndVxWithVy { x, .. }
+        | Instruction::XorVxWithVy { x, .. }
+        | Instruction::AddVyToVx { x, .. }
+        | Instruction::SubtractVyFromVx { x, .. }
+        | Instruction::ShiftVxRight { x, .. }
+        | Instruction::SubtractVxFromVyIntoVx { x, .. }
+        | Instruction::ShiftVxLeft { x, .. } => {
+            registers[x as usize] = None;
+            registers[0xF] = None;
+        }
+        Instruction::Random { x, .. } | Instruction::SetVxToDelayTimer { x } | Instruction::WaitForKey { x } => {
+            registers[x as usize] = None;
+        }
+        Instruction::LoadRegisters { x } => registers[..=x as usize].fill(None),
+        Instruction::Draw { .. } => registers[0xF] = None,
+        _ => {}
+    }
+}
+
+/// Finds FX55 and FX65 that run repeatedly without the index register being reset, either twice
+/// in a row or in a loop, and loops that advance the index register between them with FX1E
+fn find_index_loops(analysis: &Analysis, evidence: &mut Vec<Evidence>) {
+    let blocks: BTreeMap<u16, &BasicBlock> = analysis.blocks().map(|block| (block.start(), block)).collect();
+    let mut successors: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+    for edge in analysis.edges().iter().filter(|edge| edge.kind != EdgeKind::Call) {
+        successors.entry(edge.from).or_default().push(edge.to);
+    }
+
+    for block in blocks.values() {
+        let mut previous_transfer = None;
+        for instruction in &block.instructions {
+            match instruction.instruction {
+                Instruction::StoreRegisters { .. } | Instruction::LoadRegisters { .. } => {
+                    if previous_transfer.is_some() {
+                        evidence.push(Evidence::ReliesOnIndexIncrement { address: instruction.address });
+                    }
+                    previous_transfer = Some(instruction.address);
+                }
+                Instruction::SetIndex { .. } | Instruction::AddVxToIndex { .. } | Instruction::SetIndexToFontCharacter { .. } => {
+                    previous_transfer = None;
+                }
+                _ => {}
+            }
+        }
+
+        let Some(transfer) = block.instructions.iter()
+            .find(|instruction| matches!(instruction.instruction, Instruction::StoreRegisters { .. } | Instruction::LoadRegisters { .. }))
+        else { continue };
+
+        let body = loop_body(block.start(), &successors);
+        if body.is_empty() {
+            continue;
+        }
+
+        let loop_instructions = || body.iter().flat_map(|start| &blocks[start].instructions);
+        if loop_instructions().any(|instruction| matches!(instruction.instruction, Instruction::SetIndex { .. })) {
+            continue;
+        }
+
+        let advances_index = loop_instructions().any(|instruction| matches!(instruction.instruction, Instruction::AddVxToIndex { .. }));
+        evidence.push(if advances_index {
+            Evidence::AdvancesIndexManually { address: transfer.address }
+        } else {
+            Evidence::ReliesOnIndexIncrement { address: transfer.address }
+        });
+    }
+}
+
+/// Gets the starts of the blocks in a loop through a block, or nothing if it isn't in a loop
+fn loop_body(start: u16, successors: &BTreeMap<u16, Vec<u16>>) -> BTreeSet<u16> {
+    let reachable_from = |from: u16| {
+        let mut reached = BTreeSet::new();
+        let mut worklist: Vec<u16> = successors.get(&from).cloned().unwrap_or_default();
+        while let Some(block) = worklist.pop() {
+            if reached.insert(block) {
+                worklist.extend(successors.get(&block).into_iter().flatten());
+            }
+        }
+        reached
+    };
+
+    let forward = reachable_from(start);
+    if !forward.contains(&start) {
+        return BTreeSet::new();
+    }
+
+    forward.into_iter()
+        .filter(|block| reachable_from(*block).contains(&start))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_extension_instructions() {
+        // Switches to SUPER-CHIP's high resolution mode, then loops
+        let detection = Detection::detect(&[0x00, 0xFF, 0x12, 0x02]);
+        assert_eq!(Platform::SuperChip, detection.platform);
+        assert_eq!(EmulatorType::Chip48, detection.emulator_type());
+        assert_eq!(vec![Evidence::SuperChipOpcode { address: 0x200, opcode: 0x00FF }], detection.evidence);
+        assert!((detection.confidence - 10.0 / 15.0).abs() < 1e-9);
+
+        // Sets the audio pitch, which only XO-CHIP has
+        let detection = Detection::detect(&[0x60, 0x40, 0xF0, 0x3A, 0x12, 0x04]);
+        assert_eq!(Platform::XoChip, detection.platform);
+        assert!(detection.quirks().sprites_wrap);
+    }
+
+    #[test]
+    fn detects_loops_relying_on_the_index_register_incrementing() {
+        // Stores V0 in successive bytes without touching I until V1 counts down
+        let detection = Detection::detect(&[
+            0xA3, 0x00, 0x61, 0x04, 0xF0, 0x55, 0x71, 0xFF,
+            0x31, 0x00, 0x12, 0x04, 0x12, 0x0C,
+        ]);
+        assert_eq!(Platform::CosmacVip, detection.platform);
+        assert_eq!(vec![Evidence::ReliesOnIndexIncrement { address: 0x204 }], detection.evidence);
+        assert_eq!(0.5, detection.confidence);
+    }
+
+    #[test]
+    fn detects_loops_advancing_the_index_register_themselves() {
+        let detection = Detection::detect(&[
+            0xA3, 0x00, 0x62, 0x01, 0xF0, 0x55, 0xF2, 0x1E,
+            0x12, 0x04,
+        ]);
+        assert_eq!(Platform::Chip48, detection.platform);
+        assert_eq!(EmulatorType::Chip48, detection.emulator_type());
+        assert_eq!(vec![Evidence::AdvancesIndexManually { address: 0x204 }], detection.evidence);
+    }
+
+    #[test]
+    fn detects_quirked_shifts_jumps_and_sprites() {
+        let detection = Detection::detect(&[
+            0x80, 0x16, 0x60, 0x3C, 0xD0, 0x15, 0xB2, 0x00,
+        ]);
+        assert_eq!(vec![
+            Evidence::ShiftBetweenRegisters { address: 0x200 },
+            Evidence::SpriteOffEdge { address: 0x204 },
+            Evidence::JumpWithOffset { address: 0x206 },
+        ], detection.evidence);
+        assert_eq!(Platform::CosmacVip, detection.platform);
+    }
+
+    #[test]
+    fn defaults_to_the_vip_without_evidence() {
+        let detection = Detection::detect(&[0x60, 0x01, 0x12, 0x02]);
+        assert_eq!(Platform::CosmacVip, detection.platform);
+        assert_eq!(0.0, detection.confidence);
+        assert!(detection.evidence.is_empty());
+    }
+}
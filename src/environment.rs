@@ -0,0 +1,429 @@
+use std::fmt;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde_json::Value;
+use crate::{Chip8, Chip8Key, EmulatorType, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// An observation, the frame buffer after a step
+pub type Observation = [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+
+/// A value a ROM keeps in memory, e.g. its score or number of lives
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MemoryWatch {
+    /// A single byte
+    Byte { address: u16 },
+    /// Two bytes, most significant first
+    Word { address: u16 },
+    /// Decimal digits one per byte, most significant first, as FX33 stores them. At most
+    /// [`MemoryWatch::MAX_BCD_DIGITS`] so the value fits a `u32`
+    Bcd { address: u16, digits: u8 },
+}
+
+impl MemoryWatch {
+    /// The most digits a BCD watch can read without overflowing
+    pub const MAX_BCD_DIGITS: u8 = 9;
+
+    /// Parses a watch written as `byte:ADDRESS`, `word:ADDRESS` or `bcd:ADDRESS:DIGITS`, e.g.
+    /// `bcd:0x3F0:3`. Numbers are decimal, or hex with a 0x prefix
+    pub fn parse(text: &str) -> Result<Self, ParseSpecError> {
+        let error = || ParseSpecError(format!("invalid memory watch {text}, expected byte:ADDRESS, word:ADDRESS or bcd:ADDRESS:DIGITS"));
+        let parts: Vec<&str> = text.trim().split(':').collect();
+        match parts.as_slice() {
+            ["byte", address] => Ok(MemoryWatch::Byte { address: parse_number(address)? }),
+            ["word", address] => Ok(MemoryWatch::Word { address: parse_number(address)? }),
+            ["bcd", address, digits] => {
+                let digits = parse_number(digits)?;
+                if digits > Self::MAX_BCD_DIGITS {
+                    return Err(ParseSpecError(format!("invalid memory watch {text}, a bcd watch reads at most {} digits", Self::MAX_BCD_DIGITS)));
+                }
+                Ok(MemoryWatch::Bcd { address: parse_number(address)?, digits })
+            }
+            _ => Err(error()),
+        }
+    }
+
+    /// Reads the value from memory. Bytes past the end of memory read as zero
+    pub fn read(&self, memory: &[u8]) -> u32 {
+        let byte = |address: u16| memory.get(address as usize).copied().unwrap_or(0) as u32;
+        match *self {
+            MemoryWatch::Byte { address } => byte(address),
+            MemoryWatch::Word { address } => byte(address) << 8 | byte(address.wrapping_add(1)),
+            MemoryWatch::Bcd { address, digits } => (0..digits as u16)
+                .fold(0, |value, digit| value * 10 + byte(address.wrapping_add(digit)) % 10),
+        }
+    }
+}
+
+/// How a watched value is compared to decide if an episode is over
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Comparison {
+    /// `==`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// The operators comparisons are written with, longest first so they parse unambiguously
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    /// Applies the comparison
+    pub fn compare(&self, left: u32, right: u32) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// Ends an episode when a watched value compares true against a constant
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DoneCondition {
+    /// The value to watch
+    pub watch: MemoryWatch,
+    /// How to compare it
+    pub comparison: Comparison,
+    /// What to compare it to
+    pub value: u32,
+}
+
+impl DoneCondition {
+    /// Parses a condition written as `WATCH OPERATOR VALUE`, e.g. `byte:0x3F4 == 0`. The operator
+    /// is one of `==`, `!=`, `<`, `<=`, `>` and `>=`
+    pub fn parse(text: &str) -> Result<Self, ParseSpecError> {
+        let (index, operator, comparison) = Comparison::OPERATORS.iter()
+            .find_map(|(operator, comparison)| text.find(operator).map(|index| (index, *operator, *comparison)))
+            .ok_or_else(|| ParseSpecError(format!("invalid condition {text}, expected WATCH OPERATOR VALUE")))?;
+
+        Ok(Self {
+            watch: MemoryWatch::parse(&text[..index])?,
+            comparison,
+            value: parse_number(&text[index + operator.len()..])?,
+        })
+    }
+
+    /// Returns whether the condition holds for the memory
+    pub fn is_met(&self, memory: &[u8]) -> bool {
+        self.comparison.compare(self.watch.read(memory), self.value)
+    }
+}
+
+/// An error parsing an environment spec
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseSpecError(pub String);
+
+impl fmt::Display for ParseSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseSpecError {}
+
+/// Describes how to play a ROM as an environment: what the agent's actions are and how to tell
+/// its reward and when an episode is over from memory
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EnvironmentSpec {
+    /// The emulator type to run the ROM as
+    pub emulator_type: EmulatorType,
+    /// The number of instructions executed per frame
+    pub instructions_per_frame: usize,
+    /// The number of frames each action is held for, at least one
+    pub frame_skip: usize,
+    /// The keys held down for each action, indexed by action. An empty set does nothing
+    pub actions: Vec<Vec<Chip8Key>>,
+    /// The value whose increase is the reward, e.g. the score
+    pub reward: Option<MemoryWatch>,
+    /// Conditions any of which ends an episode
+    pub done: Vec<DoneCondition>,
+    /// The number of frames after which an episode is cut off even if it isn't over
+    pub max_frames: Option<u64>,
+}
+
+impl Default for EnvironmentSpec {
+    /// Runs as the COSMAC VIP with an action for no keys and one for each key alone, no reward and
+    /// no end
+    fn default() -> Self {
+        Self {
+            emulator_type: EmulatorType::CosmacVip,
+            instructions_per_frame: 12,
+            frame_skip: 1,
+            actions: std::iter::once(Vec::new()).chain(Chip8Key::ALL.map(|key| vec![key])).collect(),
+            reward: None,
+            done: Vec::new(),
+            max_frames: None,
+        }
+    }
+}
+
+impl EnvironmentSpec {
+    /// Parses a spec from a JSON object. Every key is optional and defaults as in
+    /// [`EnvironmentSpec::default`]:
+    ///
+    /// ```json
+    /// {
+    ///     "type": "vip",
+    ///     "instructions_per_frame": 12,
+    ///     "frame_skip": 4,
+    ///     "actions": ["", "4", "6", "4 6"],
+    ///     "reward": "bcd:0x3F0:3",
+    ///     "done": ["byte:0x3F4 == 0"],
+    ///     "max_frames": 18000
+    /// }
+    /// ```
+    ///
+    /// Actions are the hex digits of the keys they hold, separated by spaces. `type` is `vip` or
+    /// `chip48`
+    pub fn from_json(text: &str) -> Result<Self, ParseSpecError> {
+        let json: Value = serde_json::from_str(text).map_err(|error| ParseSpecError(error.to_string()))?;
+        let Value::Object(object) = json else { return Err(ParseSpecError("expected an object".to_string())) };
+        let mut spec = Self::default();
+
+        for (key, value) in &object {
+            let error = || ParseSpecError(format!("invalid value for {key}"));
+            let string = || value.as_str().ok_or_else(error);
+            let number = || value.as_u64().ok_or_else(error);
+            let strings = || value.as_array()
+                .ok_or_else(error)?
+                .iter()
+                .map(|value| value.as_str().ok_or_else(error))
+                .collect::<Result<Vec<_>, _>>();
+
+            match key.as_str() {
+                "type" => spec.emulator_type = string()?.parse::<EmulatorType>().map_err(|error| ParseSpecError(error.to_string()))?,
+                "instructions_per_frame" => spec.instructions_per_frame = number()? as usize,
+                "frame_skip" => spec.frame_skip = (number()? as usize).max(1),
+                "actions" => spec.actions = strings()?.into_iter().map(parse_keys).collect::<Result<_, _>>()?,
+                "reward" => spec.reward = Some(MemoryWatch::parse(string()?)?),
+                "done" => spec.done = strings()?.into_iter().map(DoneCondition::parse).collect::<Result<_, _>>()?,
+                "max_frames" => spec.max_frames = Some(number()?),
+                _ => return Err(ParseSpecError(format!("unknown key {key}"))),
+            }
+        }
+
+        if spec.actions.is_empty() {
+            return Err(ParseSpecError("expected at least one action".to_string()));
+        }
+        Ok(spec)
+    }
+}
+
+/// A reinforcement learning environment that plays a ROM. Everything including the random number
+/// generator is seeded on reset, so the same seed and actions always give the same episode
+#[derive(Debug)]
+pub struct Environment {
+    program: Vec<u8>,
+    spec: EnvironmentSpec,
+    chip8: Chip8<StdRng>,
+    /// The watched reward value as of the last step
+    reward_value: u32,
+    /// The number of frames run this episode
+    frames: u64,
+    done: bool,
+}
+
+impl Environment {
+    /// Creates an environment that plays a ROM, reset with seed 0
+    pub fn new(program: &[u8], spec: EnvironmentSpec) -> Self {
+        let chip8 = Chip8::new(spec.emulator_type, StdRng::seed_from_u64(0));
+        let mut environment = Self {
+            program: program.to_vec(),
+            spec,
+            chip8,
+            reward_value: 0,
+            frames: 0,
+            done: false,
+        };
+        environment.reset(0);
+        environment
+    }
+
+    /// Gets the spec the environment was created with
+    pub fn spec(&self) -> &EnvironmentSpec {
+        &self.spec
+    }
+
+    /// Gets the number of actions
+    pub fn action_count(&self) -> usize {
+        self.spec.actions.len()
+    }
+
+    /// Gets the interpreter running the ROM
+    pub fn chip8(&self) -> &Chip8<StdRng> {
+        &self.chip8
+    }
+
+    /// Gets the number of frames run this episode
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns whether the episode is over
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Starts a new episode, reloading the ROM with a random number generator from the seed, and
+    /// returns the first observation
+    pub fn reset(&mut self, seed: u64) -> &Observation {
+        self.chip8 = Chip8::new(self.spec.emulator_type, StdRng::seed_from_u64(seed));
+        self.chip8.load_program(&self.program);
+        self.reward_value = self.spec.reward.map_or(0, |watch| watch.read(self.chip8.ram()));
+        self.frames = 0;
+        self.done = false;
+        self.chip8.frame_buffer()
+    }
+
+    /// Holds the action's keys down for [`EnvironmentSpec::frame_skip`] frames, or until the
+    /// episode is over, and returns the observation afterwards, the reward earned and whether the
+    /// episode is over. Panics if the action is out of range. Stepping once the episode is over
+    /// does nothing until it's reset
+    pub fn step(&mut self, action: usize) -> (&Observation, i64, bool) {
+        if self.done {
+            return (self.chip8.frame_buffer(), 0, true);
+        }
+
+        let keys = &self.spec.actions[action];
+        for key in Chip8Key::ALL {
+            if keys.contains(&key) { self.chip8.key_down(key) } else { self.chip8.key_up(key) }
+        }
+
+        for _ in 0..self.spec.frame_skip {
+            self.chip8.run_frame(self.spec.instructions_per_frame);
+            self.frames += 1;
+
+            let memory = self.chip8.ram();
+            self.done = self.spec.done.iter().any(|condition| condition.is_met(memory))
+                || self.spec.max_frames.is_some_and(|max_frames| self.frames >= max_frames);
+            if self.done {
+                break;
+            }
+        }
+
+        let reward_value = self.spec.reward.map_or(0, |watch| watch.read(self.chip8.ram()));
+        let reward = reward_value as i64 - self.reward_value as i64;
+        self.reward_value = reward_value;
+        (self.chip8.frame_buffer(), reward, self.done)
+    }
+}
+
+/// Parses a decimal number, or a hex one with a 0x prefix
+fn parse_number<T: TryFrom<u64>>(text: &str) -> Result<T, ParseSpecError> {
+    let text = text.trim();
+    let number = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    number.ok()
+        .and_then(|number| T::try_from(number).ok())
+        .ok_or_else(|| ParseSpecError(format!("invalid number {text}")))
+}
+
+/// Parses the keys an action holds, as hex digits separated by spaces
+fn parse_keys(text: &str) -> Result<Vec<Chip8Key>, ParseSpecError> {
+    text.split_whitespace()
+        .map(|key| u8::from_str_radix(key, 16).ok()
+            .and_then(Chip8Key::from_value)
+            .ok_or_else(|| ParseSpecError(format!("invalid key {key}"))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adds 7 to V0 while key 6 is held, storing it as BCD at 0x300, until it reaches 56, then
+    /// stores it at 0x310 and stops
+    const PROGRAM: [u8; 20] = [
+        0x61, 0x06, 0xE1, 0xA1, 0x70, 0x07, 0xA3, 0x00,
+        0xF0, 0x33, 0x30, 0x38, 0x12, 0x02, 0xA3, 0x10,
+        0xF0, 0x55, 0x12, 0x12,
+    ];
+
+    #[test]
+    fn parses_specs() {
+        let spec = EnvironmentSpec::from_json(r#"{
+            "type": "chip48",
+            "frame_skip": 4,
+            "actions": ["", "4", "4 6"],
+            "reward": "bcd:0x3F0:3",
+            "done": ["byte:0x3F4 == 0", "word:0x3F6>=0x100"],
+            "max_frames": 100
+        }"#).unwrap();
+
+        assert_eq!(EmulatorType::Chip48, spec.emulator_type);
+        assert_eq!(12, spec.instructions_per_frame);
+        assert_eq!(vec![vec![], vec![Chip8Key::Four], vec![Chip8Key::Four, Chip8Key::Six]], spec.actions);
+        assert_eq!(Some(MemoryWatch::Bcd { address: 0x3F0, digits: 3 }), spec.reward);
+        assert_eq!(DoneCondition { watch: MemoryWatch::Word { address: 0x3F6 }, comparison: Comparison::GreaterOrEqual, value: 0x100 }, spec.done[1]);
+        assert!(EnvironmentSpec::from_json(r#"{"actions": ["G"]}"#).is_err());
+        assert!(EnvironmentSpec::from_json(r#"{"reward": "bcd:0x300"}"#).is_err());
+    }
+
+    #[test]
+    fn reads_memory_watches() {
+        let memory = [0x01, 0x02, 0x09];
+
+        assert_eq!(2, MemoryWatch::Byte { address: 1 }.read(&memory));
+        assert_eq!(0x0102, MemoryWatch::Word { address: 0 }.read(&memory));
+        assert_eq!(129, MemoryWatch::Bcd { address: 0, digits: 3 }.read(&memory));
+        assert_eq!(0, MemoryWatch::Byte { address: 3 }.read(&memory));
+    }
+
+    #[test]
+    fn rejects_bcd_watches_too_long_for_a_u32() {
+        let watch = MemoryWatch::parse("bcd:0:9").unwrap();
+        assert_eq!(999_999_999, watch.read(&[9; 9]));
+        assert!(MemoryWatch::parse("bcd:0:10").is_err());
+        assert!(MemoryWatch::parse("bcd:0:255").is_err());
+    }
+
+    #[test]
+    fn rewards_and_ends_episodes_from_memory() {
+        let spec = EnvironmentSpec {
+            instructions_per_frame: 5,
+            frame_skip: 2,
+            actions: vec![vec![], vec![Chip8Key::Six]],
+            reward: Some(MemoryWatch::Bcd { address: 0x300, digits: 3 }),
+            done: vec![DoneCondition::parse("byte:0x310 != 0").unwrap()],
+            ..EnvironmentSpec::default()
+        };
+        let mut environment = Environment::new(&PROGRAM, spec);
+
+        let (_, reward, done) = environment.step(0);
+        assert_eq!((0, false), (reward, done));
+
+        let (_, reward, done) = environment.step(1);
+        assert_eq!((7, false), (reward, done));
+
+        let mut total = 7;
+        while !environment.is_done() {
+            total += environment.step(1).1;
+        }
+        assert_eq!(56, total);
+        assert_eq!((0, true), (environment.step(1).1, environment.is_done()));
+
+        environment.reset(0);
+        assert_eq!((0, 0), (environment.frames(), environment.chip8().ram()[0x300]));
+    }
+}
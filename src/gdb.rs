@@ -0,0 +1,635 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use crate::debugger::{Debugger, RunOutcome};
+use crate::{Chip8, Chip8Rng, Register};
+
+/// The registers in the order gdb numbers them: V0 to VF, then I, PC, SP, DT and ST
+const REGISTERS: [Register; 21] = [
+    Register::Variable(0x0), Register::Variable(0x1), Register::Variable(0x2), Register::Variable(0x3),
+    Register::Variable(0x4), Register::Variable(0x5), Register::Variable(0x6), Register::Variable(0x7),
+    Register::Variable(0x8), Register::Variable(0x9), Register::Variable(0xA), Register::Variable(0xB),
+    Register::Variable(0xC), Register::Variable(0xD), Register::Variable(0xE), Register::Variable(0xF),
+    Register::Index, Register::ProgramCounter, Register::StackPointer, Register::DelayTimer, Register::SoundTimer,
+];
+
+/// The target description sent to gdb, listing the registers and their sizes
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8"/><reg name="v1" bitsize="8"/><reg name="v2" bitsize="8"/><reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/><reg name="v5" bitsize="8"/><reg name="v6" bitsize="8"/><reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/><reg name="v9" bitsize="8"/><reg name="va" bitsize="8"/><reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/><reg name="vd" bitsize="8"/><reg name="ve" bitsize="8"/><reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// The byte gdb sends outside of a packet to interrupt a running target
+const INTERRUPT: u8 = 0x03;
+
+/// The signal reported when execution stopped at a breakpoint or after a step
+const SIGTRAP: u8 = 5;
+
+/// The signal reported when execution stopped at an invalid opcode
+const SIGILL: u8 = 4;
+
+/// The signal reported when execution was interrupted by gdb
+const SIGINT: u8 = 2;
+
+/// The most instructions to run between checks for an interrupt from gdb, so a continue stays
+/// responsive when frames run many instructions
+const INTERRUPT_POLL_INSTRUCTIONS: usize = 1000;
+
+/// A stream gdb is connected over
+pub trait GdbConnection: Read + Write {
+    /// Checks without blocking whether gdb sent an interrupt while the target was running. Any
+    /// other bytes received are discarded, since gdb doesn't send packets to a running target
+    fn interrupt_requested(&mut self) -> io::Result<bool>;
+}
+
+impl GdbConnection for TcpStream {
+    fn interrupt_requested(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let result = read_interrupt(self);
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+#[cfg(unix)]
+impl GdbConnection for std::os::unix::net::UnixStream {
+    fn interrupt_requested(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let result = read_interrupt(self);
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+/// Reads whatever is available from a nonblocking stream, returning whether it contained an
+/// interrupt
+fn read_interrupt<S: Read>(stream: &mut S) -> io::Result<bool> {
+    let mut buffer = [0; 64];
+    let mut interrupted = false;
+
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(count) => interrupted |= buffer[..count].contains(&INTERRUPT),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(interrupted),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// How a gdb session ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SessionEnd {
+    /// Gdb detached, leaving the program to keep running
+    Detached,
+    /// Gdb asked for the program to be killed
+    Killed,
+    /// The connection was closed
+    Disconnected,
+}
+
+/// What to do after handling a packet
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    /// Send a reply and wait for the next packet
+    Reply(String),
+    /// Execute a single instruction, then report why execution stopped
+    Step,
+    /// Run until a breakpoint or an interrupt, then report why execution stopped
+    Continue,
+    /// Reply OK and end the session, leaving the interpreter as it is
+    Detach,
+    /// End the session without replying
+    Kill,
+}
+
+/// Serves the GDB remote serial protocol for a Chip-8 interpreter, so gdb, lldb and IDEs built on
+/// them can attach to a running program.
+/// Registers are numbered V0 to VF (0 to 15), I (16), PC (17), SP (18), DT (19) and ST (20). I and
+/// PC are 16 bits, the rest 8 bits, all little endian. Software breakpoints, single stepping,
+/// continuing, interrupting and reading and writing registers and memory are supported.
+pub struct GdbStub<R: Chip8Rng> {
+    chip8: Chip8<R>,
+    debugger: Debugger,
+    instructions_per_frame: usize,
+}
+
+impl<R: Chip8Rng> GdbStub<R> {
+    /// Creates a stub for an interpreter that's stopped before its next instruction
+    pub fn new(chip8: Chip8<R>, instructions_per_frame: usize) -> Self {
+        Self {
+            chip8,
+            debugger: Debugger::new(),
+            instructions_per_frame,
+        }
+    }
+
+    /// Gets the interpreter
+    pub fn chip8(&self) -> &Chip8<R> {
+        &self.chip8
+    }
+
+    /// Gets the breakpoints and frame position
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Gives back the interpreter, e.g. to keep running it after gdb detached
+    pub fn into_inner(self) -> Chip8<R> {
+        self.chip8
+    }
+
+    /// Serves a single gdb session until it detaches, kills the target or disconnects.
+    /// Breakpoints are kept between sessions
+    pub fn serve<C: GdbConnection>(&mut self, connection: &mut C) -> io::Result<SessionEnd> {
+        while let Some(packet) = read_packet(connection)? {
+            let reply = match self.handle_packet(&packet) {
+                Action::Reply(reply) => reply,
+                Action::Step => self.step(),
+                Action::Continue => self.resume(connection)?,
+                Action::Detach => {
+                    write_packet(connection, "OK")?;
+                    return Ok(SessionEnd::Detached);
+                }
+                Action::Kill => return Ok(SessionEnd::Killed),
+            };
+
+            write_packet(connection, &reply)?;
+        }
+
+        Ok(SessionEnd::Disconnected)
+    }
+
+    /// Executes a single instruction and reports the stop
+    fn step(&mut self) -> String {
+        match self.debugger.step(&mut self.chip8, self.instructions_per_frame) {
+            RunOutcome::InvalidOpcode { .. } => stop_reply(SIGILL),
+            _ => stop_reply(SIGTRAP),
+        }
+    }
+
+    /// Runs until a breakpoint, an invalid opcode or an interrupt from gdb and reports the stop.
+    /// Gdb is checked for interrupts at the end of each frame and every
+    /// [`INTERRUPT_POLL_INSTRUCTIONS`] instructions
+    fn resume<C: GdbConnection>(&mut self, connection: &mut C) -> io::Result<String> {
+        loop {
+            match self.debugger.run_for(&mut self.chip8, self.instructions_per_frame, INTERRUPT_POLL_INSTRUCTIONS) {
+                RunOutcome::BreakpointHit { .. } => return Ok("T05swbreak:;".to_string()),
+                RunOutcome::InvalidOpcode { .. } => return Ok(stop_reply(SIGILL)),
+                RunOutcome::FrameCompleted | RunOutcome::Stepped => {
+                    if connection.interrupt_requested()? {
+                        return Ok(stop_reply(SIGINT));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Decides what to do with the contents of a packet
+    fn handle_packet(&mut self, packet: &str) -> Action {
+        let reply = |reply: &str| Action::Reply(reply.to_string());
+        let error = || reply("E01");
+
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        match command {
+            "?" => Action::Reply(stop_reply(SIGTRAP)),
+            "g" => Action::Reply(REGISTERS.iter().map(|register| self.register_hex(*register)).collect()),
+            "G" => self.write_registers(arguments).map_or_else(error, |_| reply("OK")),
+            "p" => parse_hex(arguments)
+                .and_then(|number| REGISTERS.get(number as usize))
+                .map_or_else(error, |register| Action::Reply(self.register_hex(*register))),
+            "P" => self.write_register(arguments).map_or_else(error, |_| reply("OK")),
+            "m" => self.read_memory(arguments).map_or_else(error, Action::Reply),
+            "M" => self.write_memory(arguments).map_or_else(error, |_| reply("OK")),
+            "s" => Action::Step,
+            "c" => Action::Continue,
+            "Z" | "z" => match arguments.strip_prefix("0,").and_then(parse_breakpoint) {
+                Some(address) if command == "Z" => {
+                    self.debugger.add_breakpoint(address);
+                    reply("OK")
+                }
+                Some(address) => {
+                    self.debugger.remove_breakpoint(address);
+                    reply("OK")
+                }
+                None => reply(""),
+            },
+            "D" => Action::Detach,
+            "k" => Action::Kill,
+            "H" => reply("OK"),
+            _ => self.handle_named_packet(packet),
+        }
+    }
+
+    /// Handles the query and v packets, whose commands are words rather than single characters
+    fn handle_named_packet(&self, packet: &str) -> Action {
+        let reply = |reply: &str| Action::Reply(reply.to_string());
+
+        match packet {
+            _ if packet.starts_with("qSupported") => reply("PacketSize=1000;qXfer:features:read+;swbreak+;vContSupported+"),
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                let range = &packet["qXfer:features:read:target.xml:".len()..];
+                xfer_chunk(TARGET_XML, range).map_or_else(|| reply("E01"), Action::Reply)
+            }
+            "qAttached" => reply("1"),
+            "qC" => reply("QC1"),
+            "qfThreadInfo" => reply("m1"),
+            "qsThreadInfo" => reply("l"),
+            "vCont?" => reply("vCont;c;C;s;S"),
+            _ if packet.starts_with("vCont;c") || packet.starts_with("vCont;C") => Action::Continue,
+            _ if packet.starts_with("vCont;s") || packet.starts_with("vCont;S") => Action::Step,
+            _ => reply(""),
+        }
+    }
+
+    /// Formats a register as little endian hex bytes
+    fn register_hex(&self, register: Register) -> String {
+        let value = self.chip8.register(register);
+        to_hex(&value.to_le_bytes()[..register_size(register)])
+    }
+
+    /// Handles `G`, which writes every register from little endian hex
+    fn write_registers(&mut self, arguments: &str) -> Option<()> {
+        let bytes = from_hex(arguments)?;
+        let total_size: usize = REGISTERS.iter().map(|register| register_size(*register)).sum();
+        if bytes.len() != total_size {
+            return None;
+        }
+
+        let mut bytes = bytes.as_slice();
+        for register in REGISTERS {
+            let (value, rest) = bytes.split_at(register_size(register));
+            self.chip8.set_register(register, from_le_bytes(value));
+            bytes = rest;
+        }
+
+        Some(())
+    }
+
+    /// Handles `P n=value`, which writes one register from little endian hex
+    fn write_register(&mut self, arguments: &str) -> Option<()> {
+        let (number, value) = arguments.split_once('=')?;
+        let register = *REGISTERS.get(parse_hex(number)? as usize)?;
+        let value = from_hex(value)?;
+        if value.len() != register_size(register) {
+            return None;
+        }
+
+        self.chip8.set_register(register, from_le_bytes(&value));
+        Some(())
+    }
+
+    /// Handles `m addr,length`. Reads that run past the end of memory are cut short
+    fn read_memory(&self, arguments: &str) -> Option<String> {
+        let (address, length) = arguments.split_once(',')?;
+        let start = parse_hex(address)? as usize;
+        let end = start.checked_add(parse_hex(length)? as usize)?.min(self.chip8.ram().len());
+
+        self.chip8.ram().get(start..end).filter(|bytes| !bytes.is_empty()).map(to_hex)
+    }
+
+    /// Handles `M addr,length:bytes`
+    fn write_memory(&mut self, arguments: &str) -> Option<()> {
+        let (location, data) = arguments.split_once(':')?;
+        let (address, length) = location.split_once(',')?;
+        let bytes = from_hex(data)?;
+        if bytes.len() != parse_hex(length)? as usize {
+            return None;
+        }
+
+        let address = u16::try_from(parse_hex(address)?).ok()?;
+        self.chip8.write_memory(address, &bytes).then_some(())
+    }
+}
+
+/// Gets the number of bytes gdb transfers for a register
+fn register_size(register: Register) -> usize {
+    match register {
+        Register::Index | Register::ProgramCounter => 2,
+        _ => 1,
+    }
+}
+
+/// Formats a stop reply for a signal
+fn stop_reply(signal: u8) -> String {
+    format!("S{signal:02x}")
+}
+
+/// Parses the address of a `Z0` or `z0` packet, e.g. `200,2`
+fn parse_breakpoint(arguments: &str) -> Option<u16> {
+    let (address, _kind) = arguments.split_once(',')?;
+    u16::try_from(parse_hex(address)?).ok()
+}
+
+/// Answers a qXfer read of `offset,length` from a document
+fn xfer_chunk(document: &str, range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let offset = (parse_hex(offset)? as usize).min(document.len());
+    let end = offset.saturating_add(parse_hex(length)? as usize).min(document.len());
+    let prefix = if end == document.len() { 'l' } else { 'm' };
+
+    Some(format!("{prefix}{}", &document[offset..end]))
+}
+
+/// Parses a hex number
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// Formats bytes as pairs of lowercase hex digits
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Parses pairs of hex digits into bytes
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
+
+/// Combines up to two little endian bytes into a register value
+fn from_le_bytes(bytes: &[u8]) -> u16 {
+    bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as u16)
+}
+
+/// Computes the checksum of a packet's contents
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Reads a single byte, returning None at the end of the stream
+fn read_byte<S: Read>(stream: &mut S) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match stream.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Reads the next `$data#checksum` packet, acknowledging it. Packets with a bad checksum are
+/// rejected so gdb resends them, and acknowledgements and stray interrupts between packets are
+/// skipped. Returns None when gdb disconnects
+fn read_packet<S: Read + Write>(stream: &mut S) -> io::Result<Option<String>> {
+    loop {
+        match read_byte(stream)? {
+            None => return Ok(None),
+            Some(b'$') => {}
+            Some(_) => continue,
+        }
+
+        let mut data = Vec::new();
+        loop {
+            match read_byte(stream)? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(byte) => data.push(byte),
+            }
+        }
+
+        let mut expected = [0; 2];
+        stream.read_exact(&mut expected)?;
+        let valid = std::str::from_utf8(&expected).ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .is_some_and(|expected| expected == checksum(&data));
+
+        if !valid {
+            stream.write_all(b"-")?;
+            continue;
+        }
+
+        stream.write_all(b"+")?;
+        return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+    }
+}
+
+/// Sends a packet, resending it until gdb acknowledges it
+fn write_packet<S: Read + Write>(stream: &mut S, data: &str) -> io::Result<()> {
+    let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+
+    loop {
+        stream.write_all(packet.as_bytes())?;
+        stream.flush()?;
+
+        loop {
+            match read_byte(stream)? {
+                Some(b'+') | None => return Ok(()),
+                Some(b'-') => break,
+                Some(_) => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use std::io::Cursor;
+    use std::net::TcpListener;
+    use crate::EmulatorType;
+    use super::*;
+
+    /// A program that counts up in V0 forever
+    const COUNTING_PROGRAM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn counting_stub() -> GdbStub<TestRng> {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&COUNTING_PROGRAM);
+        GdbStub::new(chip8, 10)
+    }
+
+    fn reply(reply: &str) -> Action {
+        Action::Reply(reply.to_string())
+    }
+
+    /// Frames a packet the way gdb sends it
+    fn packet(data: &str) -> String {
+        format!("${data}#{:02x}", checksum(data.as_bytes()))
+    }
+
+    /// A connection that reads from a script and is interrupted whenever checked if `interrupt`
+    /// is set
+    struct ScriptedConnection {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+        interrupt: bool,
+    }
+
+    impl Read for ScriptedConnection {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buffer)
+        }
+    }
+
+    impl Write for ScriptedConnection {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.output.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl GdbConnection for ScriptedConnection {
+        fn interrupt_requested(&mut self) -> io::Result<bool> {
+            Ok(self.interrupt)
+        }
+    }
+
+    #[test]
+    fn can_read_and_write_registers() {
+        let mut stub = counting_stub();
+
+        assert_eq!(reply("OK"), stub.handle_packet("P3=2a"));
+        assert_eq!(reply("OK"), stub.handle_packet("P10=3402"));
+        assert_eq!(reply("2a"), stub.handle_packet("p3"));
+        assert_eq!(reply("3402"), stub.handle_packet("p10"));
+        assert_eq!(reply("0002"), stub.handle_packet("p11"));
+        assert_eq!(reply("E01"), stub.handle_packet("p15"));
+        assert_eq!(reply("E01"), stub.handle_packet("P3=2a2a"));
+
+        let Action::Reply(registers) = stub.handle_packet("g") else { panic!("expected a reply") };
+        assert_eq!(format!("0000002a{}34020002000000", "00".repeat(12)), registers);
+
+        let mut written = registers.clone();
+        written.replace_range(0..2, "07");
+        assert_eq!(reply("OK"), stub.handle_packet(&format!("G{written}")));
+        assert_eq!(7, stub.chip8().variable_registers()[0]);
+        assert_eq!(reply("E01"), stub.handle_packet("G00"));
+    }
+
+    #[test]
+    fn can_read_and_write_memory() {
+        let mut stub = counting_stub();
+
+        assert_eq!(reply("70011200"), stub.handle_packet("m200,4"));
+        assert_eq!(reply("OK"), stub.handle_packet("M202,2:1202"));
+        assert_eq!(reply("70011202"), stub.handle_packet("m200,4"));
+        assert_eq!(reply("00"), stub.handle_packet("mfff,10"));
+        assert_eq!(reply("E01"), stub.handle_packet("m1000,1"));
+        assert_eq!(reply("E01"), stub.handle_packet("Mfff,2:0000"));
+        assert_eq!(reply("E01"), stub.handle_packet("M200,2:00"));
+    }
+
+    #[test]
+    fn can_set_breakpoints_and_answer_queries() {
+        let mut stub = counting_stub();
+
+        assert_eq!(reply("OK"), stub.handle_packet("Z0,202,2"));
+        assert!(stub.debugger().has_breakpoint(0x202));
+        assert_eq!(reply("OK"), stub.handle_packet("z0,202,2"));
+        assert!(!stub.debugger().has_breakpoint(0x202));
+        assert_eq!(reply(""), stub.handle_packet("Z2,202,2"));
+
+        assert_eq!(reply("S05"), stub.handle_packet("?"));
+        assert_eq!(reply("1"), stub.handle_packet("qAttached"));
+        assert_eq!(reply(""), stub.handle_packet("qUnknownQuery"));
+        assert_eq!(Action::Continue, stub.handle_packet("vCont;c"));
+        assert_eq!(Action::Step, stub.handle_packet("vCont;s:1"));
+
+        let Action::Reply(start) = stub.handle_packet("qXfer:features:read:target.xml:0,10") else { panic!() };
+        assert_eq!("m<?xml version=\"1", start);
+        let Action::Reply(end) = stub.handle_packet("qXfer:features:read:target.xml:10,1000") else { panic!() };
+        assert!(end.starts_with('l') && end.ends_with("</target>\n"));
+    }
+
+    #[test]
+    fn serves_a_scripted_session() {
+        let mut stub = counting_stub();
+        // Each reply is acknowledged, except the register read which is rejected once and resent
+        let script = [&packet("Z0,202,2"), "+", &packet("c"), "+", &packet("p0"), "-+", &packet("s"), "+", &packet("D"), "+"];
+        let mut connection = ScriptedConnection {
+            input: Cursor::new(script.concat().into_bytes()),
+            output: Vec::new(),
+            interrupt: false,
+        };
+
+        assert_eq!(SessionEnd::Detached, stub.serve(&mut connection).unwrap());
+
+        let output = String::from_utf8(connection.output).unwrap();
+        let expected = ["+", &packet("OK"), "+", &packet("T05swbreak:;"), "+", &packet("01"), &packet("01"),
+                        "+", &packet("S05"), "+", &packet("OK")].concat();
+        assert_eq!(expected, output);
+        assert_eq!(0x200, stub.chip8().program_counter());
+    }
+
+    #[test]
+    fn rejects_packets_with_bad_checksums() {
+        let mut connection = ScriptedConnection {
+            input: Cursor::new(b"$g#00$g#67".to_vec()),
+            output: Vec::new(),
+            interrupt: false,
+        };
+
+        assert_eq!(Some("g".to_string()), read_packet(&mut connection).unwrap());
+        assert_eq!(b"-+".to_vec(), connection.output);
+    }
+
+    #[test]
+    fn continue_stops_at_invalid_opcodes() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&[0x60, 0x05, 0xFF, 0xFF]);
+        let mut stub = GdbStub::new(chip8, 10);
+        let mut connection = ScriptedConnection { input: Cursor::new(Vec::new()), output: Vec::new(), interrupt: false };
+
+        assert_eq!("S04", stub.resume(&mut connection).unwrap());
+        assert_eq!(0x202, stub.chip8().program_counter());
+    }
+
+    #[test]
+    fn continue_checks_for_interrupts_within_long_frames() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&COUNTING_PROGRAM);
+        let mut stub = GdbStub::new(chip8, 1_000_000);
+        let mut connection = ScriptedConnection { input: Cursor::new(Vec::new()), output: Vec::new(), interrupt: true };
+
+        assert_eq!("S02", stub.resume(&mut connection).unwrap());
+        assert_eq!(INTERRUPT_POLL_INSTRUCTIONS, stub.debugger().instructions_into_frame());
+    }
+
+    #[test]
+    fn can_interrupt_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(packet("c").as_bytes()).unwrap();
+
+            let mut ack = [0];
+            stream.read_exact(&mut ack).unwrap();
+            assert_eq!(b'+', ack[0]);
+            stream.write_all(&[INTERRUPT]).unwrap();
+
+            let mut stop = [0; 7];
+            stream.read_exact(&mut stop).unwrap();
+            stream.write_all(format!("+{}", packet("k")).as_bytes()).unwrap();
+            String::from_utf8(stop.to_vec()).unwrap()
+        });
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut stub = counting_stub();
+        assert_eq!(SessionEnd::Killed, stub.serve(&mut stream).unwrap());
+
+        assert_eq!(packet("S02"), client.join().unwrap());
+        assert!(stub.chip8().variable_registers()[0] > 0);
+    }
+}
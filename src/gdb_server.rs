@@ -0,0 +1,55 @@
+use std::net::TcpListener;
+use anyhow::{Context, Result};
+use chip_8_emulator::command_line::seeded_rng;
+use chip_8_emulator::gdb::{GdbConnection, GdbStub, SessionEnd};
+use chip_8_emulator::{Chip8, Chip8Rng};
+use crate::cli::Options;
+
+/// Serves the GDB remote serial protocol on a TCP address, or on a Unix socket if the address
+/// starts with `unix:`. Sessions are served one after another until gdb kills the program
+pub fn run(options: &Options, program: &[u8], address: &str) -> Result<()> {
+    let mut chip8 = Chip8::new(options.emulator_type, seeded_rng(options.seed));
+    chip8.load_program(program);
+    chip8.set_display_wait(options.display_wait);
+    let mut stub = GdbStub::new(chip8, options.instructions_per_frame);
+
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        let _ = std::fs::remove_file(path);
+        let listener = std::os::unix::net::UnixListener::bind(path)
+            .with_context(|| format!("failed to listen on {path}"))?;
+        println!("Waiting for gdb on {path}");
+
+        loop {
+            let (mut stream, _) = listener.accept()?;
+            if serve_session(&mut stub, &mut stream) == Some(SessionEnd::Killed) {
+                return Ok(());
+            }
+        }
+    }
+
+    let listener = TcpListener::bind(address).with_context(|| format!("failed to listen on {address}"))?;
+    println!("Waiting for gdb on {}", listener.local_addr()?);
+
+    loop {
+        let (mut stream, _) = listener.accept()?;
+        if let Err(error) = stream.set_nodelay(true) {
+            eprintln!("warning: failed to disable Nagle's algorithm: {error}");
+        }
+        if serve_session(&mut stub, &mut stream) == Some(SessionEnd::Killed) {
+            return Ok(());
+        }
+    }
+}
+
+/// Serves one gdb session, returning how it ended. An I/O error, e.g. gdb going away without
+/// detaching, is reported and ends just that session so the server can wait for the next one
+fn serve_session<R: Chip8Rng, C: GdbConnection>(stub: &mut GdbStub<R>, connection: &mut C) -> Option<SessionEnd> {
+    match stub.serve(connection) {
+        Ok(end) => Some(end),
+        Err(error) => {
+            eprintln!("error: gdb session failed: {error}");
+            None
+        }
+    }
+}
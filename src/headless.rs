@@ -0,0 +1,182 @@
+use anyhow::{bail, Result};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use chip_8_emulator::audio::BuzzerWavWriter;
+use chip_8_emulator::command_line::seeded_rng;
+use chip_8_emulator::lockstep::{Lockstep, LockstepDivergence};
+use chip_8_emulator::recorder::FrameRecorder;
+use chip_8_emulator::timing::VipTiming;
+use chip_8_emulator::{Chip8, Chip8Rng, EmulatorType, ExecutionObserver, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::cli::Options;
+use crate::{TONE_AMPLITUDE, TONE_FREQUENCY};
+
+/// Creates an interpreter of each type with identically seeded random number generators and runs
+/// them in lockstep
+pub fn create_lockstep(options: &Options, program: &[u8], right_type: EmulatorType) -> Lockstep<StdRng> {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let mut left = Chip8::new(options.emulator_type, StdRng::seed_from_u64(seed));
+    let mut right = Chip8::new(right_type, StdRng::seed_from_u64(seed));
+    for chip8 in [&mut left, &mut right] {
+        chip8.load_program(program);
+        chip8.set_display_wait(options.display_wait);
+    }
+
+    Lockstep::new(left, right, options.instructions_per_frame)
+}
+
+/// Describes where two interpreters diverged, with the left interpreter's values as expected
+pub fn describe_divergence(divergence: &LockstepDivergence, left_type: EmulatorType, right_type: EmulatorType) -> String {
+    let instruction = divergence.instruction;
+    let mut description = format!("{left_type:?} and {right_type:?} diverged at cycle {} after {:03X}  {:04X}  {}",
+                                  divergence.cycle,
+                                  instruction.address,
+                                  instruction.opcode,
+                                  instruction.instruction);
+    if divergence.is_quirk_dependent() {
+        description.push_str(" (quirk dependent)");
+    }
+
+    for difference in &divergence.differences {
+        description.push_str(&format!("\n    {difference}"));
+    }
+
+    description
+}
+
+/// Runs a program on two interpreter types in lockstep without a window for the number of frames
+/// given in the options, stopping at the first divergence
+pub fn run_lockstep(options: &Options, program: &[u8], right_type: EmulatorType) -> Result<()> {
+    let mut lockstep = create_lockstep(options, program, right_type);
+
+    for _ in 0..options.frames {
+        if let Some(divergence) = lockstep.run_frame() {
+            println!("{}", describe_divergence(divergence, options.emulator_type, right_type));
+            return Ok(());
+        }
+    }
+
+    println!("No divergence after {} instructions", lockstep.cycle());
+    Ok(())
+}
+
+/// Runs a program without a window for the number of frames given in the options, writing any
+/// requested outputs as it goes
+pub fn run(options: &Options, program: &[u8]) -> Result<()> {
+    let mut chip8 = Chip8::new(options.emulator_type, seeded_rng(options.seed));
+    chip8.load_program(program);
+    chip8.set_display_wait(options.display_wait);
+
+    let mut wav_writer = match &options.wav_path {
+        Some(path) => Some(BuzzerWavWriter::create(path, TONE_FREQUENCY, TONE_AMPLITUDE)?),
+        None => None,
+    };
+
+    let mut recorder = match &options.record_path {
+        Some(path) => Some(FrameRecorder::create(path,
+                                                 DISPLAY_WIDTH,
+                                                 DISPLAY_HEIGHT,
+                                                 options.record_scale,
+                                                 options.palette.colors())?),
+        None => None,
+    };
+
+    let mut tracer = options.create_tracer()?;
+    let mut profiler = options.create_profiler();
+    let mut coverage = options.create_coverage();
+
+    let mut timing = options.vip_timing.then(VipTiming::new);
+    for _ in 0..options.frames {
+        let mut observer = (&mut tracer, (&mut profiler, &mut coverage));
+        let playing_sound = run_frame(&mut chip8, timing.as_mut(), options.instructions_per_frame, &mut observer);
+
+        if let Some(wav_writer) = wav_writer.as_mut() {
+            wav_writer.write_frame(playing_sound)?;
+        }
+
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record_frame(chip8.frame_buffer())?;
+        }
+    }
+
+    if let Some(wav_writer) = wav_writer {
+        wav_writer.finalize()?;
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+
+    if let Some(tracer) = tracer {
+        tracer.finish()?;
+    }
+
+    if let Some(profiler) = profiler {
+        options.write_profile(&profiler, chip8.ram())?;
+    }
+
+    if let Some(coverage) = coverage {
+        options.write_coverage(&coverage, chip8.ram(), program.len())?;
+
+        let executed_percentage = coverage.summary(program.len()).executed_percentage();
+        if let Some(minimum) = options.coverage_minimum.filter(|minimum| executed_percentage < *minimum) {
+            bail!("only {executed_percentage:.2}% of the ROM was executed, below the minimum of {minimum}%");
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs one frame, with VIP timing if given, and returns whether the buzzer sounded during it. The
+/// buzzer is read before the timers are decremented, so a sound timer of 1 sounds for one frame
+fn run_frame<R: Chip8Rng, O: ExecutionObserver>(chip8: &mut Chip8<R>,
+                                                timing: Option<&mut VipTiming>,
+                                                instructions_per_frame: usize,
+                                                observer: &mut O) -> bool {
+    match timing {
+        Some(timing) => {
+            timing.execute_frame_with(chip8, observer);
+            let playing_sound = chip8.is_playing_sound();
+            timing.end_frame(chip8);
+            playing_sound
+        }
+        None => {
+            chip8.execute_frame_with(instructions_per_frame, observer);
+            let playing_sound = chip8.is_playing_sound();
+            chip8.decrement_timers();
+            playing_sound
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use chip_8_emulator::audio::SAMPLES_PER_FRAME;
+    use super::*;
+
+    #[test]
+    fn a_sound_timer_of_one_writes_one_frame_of_tone() {
+        // 6001 F018 1204, setting the sound timer to 1 and looping
+        let program = [0x60, 0x01, 0xF0, 0x18, 0x12, 0x04];
+
+        for vip_timing in [false, true] {
+            let mut chip8 = Chip8::new(EmulatorType::CosmacVip, StdRng::seed_from_u64(1));
+            chip8.load_program(&program);
+            let mut timing = vip_timing.then(VipTiming::new);
+            let mut buffer = Cursor::new(Vec::new());
+            let mut writer = BuzzerWavWriter::new(&mut buffer, TONE_FREQUENCY, TONE_AMPLITUDE).unwrap();
+
+            for _ in 0..3 {
+                writer.write_frame(run_frame(&mut chip8, timing.as_mut(), 12, &mut ())).unwrap();
+            }
+            writer.finalize().unwrap();
+
+            let samples: Vec<i16> = hound::WavReader::new(Cursor::new(buffer.into_inner())).unwrap()
+                .into_samples::<i16>()
+                .map(|sample| sample.unwrap())
+                .collect();
+            assert!(samples[..SAMPLES_PER_FRAME].iter().any(|sample| *sample != 0), "VIP timing {vip_timing}");
+            assert!(samples[SAMPLES_PER_FRAME..].iter().all(|sample| *sample == 0), "VIP timing {vip_timing}");
+        }
+    }
+}
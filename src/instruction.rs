@@ -0,0 +1,279 @@
+use alloc::vec::Vec;
+use core::fmt;
+use crate::OpCode;
+
+/// A decoded Chip-8 instruction. Register operands are variable register indexes, e.g. `x` for VX
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    /// 00E0: clears the screen
+    ClearScreen,
+    /// 00EE: returns from a subroutine
+    Return,
+    /// 1NNN: jumps to NNN
+    Jump { nnn: u16 },
+    /// 2NNN: calls the subroutine at NNN
+    Call { nnn: u16 },
+    /// 3XNN: skips the next instruction if VX == NN
+    SkipIfVxEqualsNn { x: u8, nn: u8 },
+    /// 4XNN: skips the next instruction if VX != NN
+    SkipIfVxNotEqualsNn { x: u8, nn: u8 },
+    /// 5XY0: skips the next instruction if VX == VY
+    SkipIfVxEqualsVy { x: u8, y: u8 },
+    /// 6XNN: sets VX to NN
+    SetVx { x: u8, nn: u8 },
+    /// 7XNN: adds NN to VX without setting the carry flag
+    AddToVx { x: u8, nn: u8 },
+    /// 8XY0: sets VX to VY
+    SetVxToVy { x: u8, y: u8 },
+    /// 8XY1: sets VX to VX | VY
+    OrVxWithVy { x: u8, y: u8 },
+    /// 8XY2: sets VX to VX & VY
+    AndVxWithVy { x: u8, y: u8 },
+    /// 8XY3: sets VX to VX ^ VY
+    XorVxWithVy { x: u8, y: u8 },
+    /// 8XY4: adds VY to VX, setting VF on carry
+    AddVyToVx { x: u8, y: u8 },
+    /// 8XY5: subtracts VY from VX, clearing VF on borrow
+    SubtractVyFromVx { x: u8, y: u8 },
+    /// 8XY6: shifts VX right by one. Ambiguous, see [`crate::EmulatorType`]
+    ShiftVxRight { x: u8, y: u8 },
+    /// 8XY7: sets VX to VY - VX, clearing VF on borrow
+    SubtractVxFromVyIntoVx { x: u8, y: u8 },
+    /// 8XYE: shifts VX left by one. Ambiguous, see [`crate::EmulatorType`]
+    ShiftVxLeft { x: u8, y: u8 },
+    /// 9XY0: skips the next instruction if VX != VY
+    SkipIfVxNotEqualsVy { x: u8, y: u8 },
+    /// ANNN: sets the index register to NNN
+    SetIndex { nnn: u16 },
+    /// BNNN: jumps to NNN plus an offset. Ambiguous, see [`crate::EmulatorType`]
+    JumpWithOffset { nnn: u16 },
+    /// CXNN: sets VX to a random number ANDed with NN
+    Random { x: u8, nn: u8 },
+    /// DXYN: draws an N pixel tall sprite at (VX, VY)
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E: skips the next instruction if the key in VX is down
+    SkipIfKeyDown { x: u8 },
+    /// EXA1: skips the next instruction if the key in VX is up
+    SkipIfKeyUp { x: u8 },
+    /// FX07: sets VX to the delay timer
+    SetVxToDelayTimer { x: u8 },
+    /// FX0A: waits for a key press and puts the key into VX
+    WaitForKey { x: u8 },
+    /// FX15: sets the delay timer to VX
+    SetDelayTimer { x: u8 },
+    /// FX18: sets the sound timer to VX
+    SetSoundTimer { x: u8 },
+    /// FX1E: adds VX to the index register
+    AddVxToIndex { x: u8 },
+    /// FX29: points the index register at the font character in VX
+    SetIndexToFontCharacter { x: u8 },
+    /// FX33: stores the decimal digits of VX at the index register
+    StoreDecimalDigits { x: u8 },
+    /// FX55: stores V0 to VX at the index register. Ambiguous, see [`crate::EmulatorType`]
+    StoreRegisters { x: u8 },
+    /// FX65: loads V0 to VX from the index register. Ambiguous, see [`crate::EmulatorType`]
+    LoadRegisters { x: u8 },
+    /// Any opcode that isn't a Chip-8 instruction
+    Invalid { opcode: u16 },
+}
+
+impl Instruction {
+    /// Decodes a 16-bit opcode
+    pub fn decode(opcode: u16) -> Self {
+        let op = OpCode::new(opcode);
+        let (x, y, n, nn, nnn) = (op.x(), op.y(), op.n(), op.nn(), op.nnn());
+
+        match opcode {
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x1000..=0x1FFF => Instruction::Jump { nnn },
+            0x2000..=0x2FFF => Instruction::Call { nnn },
+            0x3000..=0x3FFF => Instruction::SkipIfVxEqualsNn { x, nn },
+            0x4000..=0x4FFF => Instruction::SkipIfVxNotEqualsNn { x, nn },
+            0x5000..=0x5FFF => Instruction::SkipIfVxEqualsVy { x, y },
+            0x6000..=0x6FFF => Instruction::SetVx { x, nn },
+            0x7000..=0x7FFF => Instruction::AddToVx { x, nn },
+            0x8000..=0x8FFF => match n {
+                0x0 => Instruction::SetVxToVy { x, y },
+                0x1 => Instruction::OrVxWithVy { x, y },
+                0x2 => Instruction::AndVxWithVy { x, y },
+                0x3 => Instruction::XorVxWithVy { x, y },
+                0x4 => Instruction::AddVyToVx { x, y },
+                0x5 => Instruction::SubtractVyFromVx { x, y },
+                0x6 => Instruction::ShiftVxRight { x, y },
+                0x7 => Instruction::SubtractVxFromVyIntoVx { x, y },
+                0xE => Instruction::ShiftVxLeft { x, y },
+                _ => Instruction::Invalid { opcode },
+            },
+            0x9000..=0x9FFF => Instruction::SkipIfVxNotEqualsVy { x, y },
+            0xA000..=0xAFFF => Instruction::SetIndex { nnn },
+            0xB000..=0xBFFF => Instruction::JumpWithOffset { nnn },
+            0xC000..=0xCFFF => Instruction::Random { x, nn },
+            0xD000..=0xDFFF => Instruction::Draw { x, y, n },
+            0xE000..=0xEFFF => match nn {
+                0x9E => Instruction::SkipIfKeyDown { x },
+                0xA1 => Instruction::SkipIfKeyUp { x },
+                _ => Instruction::Invalid { opcode },
+            },
+            0xF000..=0xFFFF => match nn {
+                0x07 => Instruction::SetVxToDelayTimer { x },
+                0x0A => Instruction::WaitForKey { x },
+                0x15 => Instruction::SetDelayTimer { x },
+                0x18 => Instruction::SetSoundTimer { x },
+                0x1E => Instruction::AddVxToIndex { x },
+                0x29 => Instruction::SetIndexToFontCharacter { x },
+                0x33 => Instruction::StoreDecimalDigits { x },
+                0x55 => Instruction::StoreRegisters { x },
+                0x65 => Instruction::LoadRegisters { x },
+                _ => Instruction::Invalid { opcode },
+            },
+            _ => Instruction::Invalid { opcode },
+        }
+    }
+
+    /// Returns whether the instruction behaves differently depending on the
+    /// [`crate::EmulatorType`]
+    pub fn is_quirk_dependent(&self) -> bool {
+        matches!(self,
+            Instruction::ShiftVxRight { .. }
+            | Instruction::ShiftVxLeft { .. }
+            | Instruction::JumpWithOffset { .. }
+            | Instruction::StoreRegisters { .. }
+            | Instruction::LoadRegisters { .. })
+    }
+
+    /// Returns whether the instruction conditionally skips the instruction after it
+    pub fn is_skip(&self) -> bool {
+        matches!(self,
+            Instruction::SkipIfVxEqualsNn { .. }
+            | Instruction::SkipIfVxNotEqualsNn { .. }
+            | Instruction::SkipIfVxEqualsVy { .. }
+            | Instruction::SkipIfVxNotEqualsVy { .. }
+            | Instruction::SkipIfKeyDown { .. }
+            | Instruction::SkipIfKeyUp { .. })
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction as an assembly mnemonic, e.g. `LD V3, 0x2A`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::Jump { nnn } => write!(f, "JP 0x{nnn:03X}"),
+            Instruction::Call { nnn } => write!(f, "CALL 0x{nnn:03X}"),
+            Instruction::SkipIfVxEqualsNn { x, nn } => write!(f, "SE V{x:X}, 0x{nn:02X}"),
+            Instruction::SkipIfVxNotEqualsNn { x, nn } => write!(f, "SNE V{x:X}, 0x{nn:02X}"),
+            Instruction::SkipIfVxEqualsVy { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            Instruction::SetVx { x, nn } => write!(f, "LD V{x:X}, 0x{nn:02X}"),
+            Instruction::AddToVx { x, nn } => write!(f, "ADD V{x:X}, 0x{nn:02X}"),
+            Instruction::SetVxToVy { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Instruction::OrVxWithVy { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            Instruction::AndVxWithVy { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Instruction::XorVxWithVy { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            Instruction::AddVyToVx { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            Instruction::SubtractVyFromVx { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            Instruction::ShiftVxRight { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            Instruction::SubtractVxFromVyIntoVx { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Instruction::ShiftVxLeft { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            Instruction::SkipIfVxNotEqualsVy { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            Instruction::SetIndex { nnn } => write!(f, "LD I, 0x{nnn:03X}"),
+            Instruction::JumpWithOffset { nnn } => write!(f, "JP V0, 0x{nnn:03X}"),
+            Instruction::Random { x, nn } => write!(f, "RND V{x:X}, 0x{nn:02X}"),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            Instruction::SkipIfKeyDown { x } => write!(f, "SKP V{x:X}"),
+            Instruction::SkipIfKeyUp { x } => write!(f, "SKNP V{x:X}"),
+            Instruction::SetVxToDelayTimer { x } => write!(f, "LD V{x:X}, DT"),
+            Instruction::WaitForKey { x } => write!(f, "LD V{x:X}, K"),
+            Instruction::SetDelayTimer { x } => write!(f, "LD DT, V{x:X}"),
+            Instruction::SetSoundTimer { x } => write!(f, "LD ST, V{x:X}"),
+            Instruction::AddVxToIndex { x } => write!(f, "ADD I, V{x:X}"),
+            Instruction::SetIndexToFontCharacter { x } => write!(f, "LD F, V{x:X}"),
+            Instruction::StoreDecimalDigits { x } => write!(f, "LD B, V{x:X}"),
+            Instruction::StoreRegisters { x } => write!(f, "LD [I], V{x:X}"),
+            Instruction::LoadRegisters { x } => write!(f, "LD V{x:X}, [I]"),
+            Instruction::Invalid { opcode } => write!(f, "DW 0x{opcode:04X}"),
+        }
+    }
+}
+
+/// A line of disassembly
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DisassembledInstruction {
+    /// The address of the instruction
+    pub address: u16,
+    /// The raw opcode
+    pub opcode: u16,
+    /// The decoded instruction
+    pub instruction: Instruction,
+}
+
+/// Disassembles count instructions from memory starting at address. Stops early at the end of
+/// memory
+pub fn disassemble(memory: &[u8], address: u16, count: usize) -> Vec<DisassembledInstruction> {
+    (0..count)
+        .map(|i| address as usize + i * 2)
+        .take_while(|address| address + 1 < memory.len())
+        .map(|address| {
+            let opcode = (memory[address] as u16) << 8 | memory[address + 1] as u16;
+            DisassembledInstruction { address: address as u16, opcode, instruction: Instruction::decode(opcode) }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use super::*;
+
+    #[test]
+    fn can_decode_instructions() {
+        assert_eq!(Instruction::ClearScreen, Instruction::decode(0x00E0));
+        assert_eq!(Instruction::Return, Instruction::decode(0x00EE));
+        assert_eq!(Instruction::Jump { nnn: 0x234 }, Instruction::decode(0x1234));
+        assert_eq!(Instruction::SkipIfVxEqualsVy { x: 0x2, y: 0x3 }, Instruction::decode(0x5230));
+        assert_eq!(Instruction::ShiftVxLeft { x: 0x2, y: 0x3 }, Instruction::decode(0x823E));
+        assert_eq!(Instruction::Draw { x: 0x1, y: 0x2, n: 0x5 }, Instruction::decode(0xD125));
+        assert_eq!(Instruction::SkipIfKeyUp { x: 0x4 }, Instruction::decode(0xE4A1));
+        assert_eq!(Instruction::LoadRegisters { x: 0xF }, Instruction::decode(0xFF65));
+    }
+
+    #[test]
+    fn decodes_unknown_opcodes_as_invalid() {
+        for opcode in [0x0123, 0x8238, 0xE49F, 0xF0FF] {
+            assert_eq!(Instruction::Invalid { opcode }, Instruction::decode(opcode));
+        }
+    }
+
+    #[test]
+    fn knows_which_instructions_are_quirk_dependent() {
+        assert!(Instruction::decode(0x8126).is_quirk_dependent());
+        assert!(Instruction::decode(0xB300).is_quirk_dependent());
+        assert!(Instruction::decode(0xF265).is_quirk_dependent());
+        assert!(!Instruction::decode(0x8124).is_quirk_dependent());
+        assert!(!Instruction::decode(0xF233).is_quirk_dependent());
+    }
+
+    #[test]
+    fn can_format_instructions() {
+        assert_eq!("CLS", Instruction::decode(0x00E0).to_string());
+        assert_eq!("CALL 0x2F0", Instruction::decode(0x22F0).to_string());
+        assert_eq!("LD V3, 0x2A", Instruction::decode(0x632A).to_string());
+        assert_eq!("SUBN VA, VB", Instruction::decode(0x8AB7).to_string());
+        assert_eq!("JP V0, 0x300", Instruction::decode(0xB300).to_string());
+        assert_eq!("DRW V0, V1, 15", Instruction::decode(0xD01F).to_string());
+        assert_eq!("LD [I], V5", Instruction::decode(0xF555).to_string());
+        assert_eq!("DW 0xFFFF", Instruction::decode(0xFFFF).to_string());
+    }
+
+    #[test]
+    fn can_disassemble_memory() {
+        let memory = [0x00, 0xE0, 0x12, 0x00, 0xAB];
+
+        let lines = disassemble(&memory, 0, 4);
+
+        assert_eq!(2, lines.len());
+        assert_eq!(DisassembledInstruction { address: 0, opcode: 0x00E0, instruction: Instruction::ClearScreen }, lines[0]);
+        assert_eq!(DisassembledInstruction { address: 2, opcode: 0x1200, instruction: Instruction::Jump { nnn: 0x200 } }, lines[1]);
+    }
+}
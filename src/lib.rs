@@ -1,4 +1,47 @@
-use rand::{Rng};
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use instruction::{DisassembledInstruction, Instruction};
+
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod audio;
+#[cfg(feature = "parallel")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod block_cache;
+#[cfg(feature = "std")]
+pub mod command_line;
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod detection;
+#[cfg(all(feature = "std", feature = "rand"))]
+pub mod environment;
+#[cfg(feature = "std")]
+pub mod gdb;
+pub mod instruction;
+#[cfg(feature = "std")]
+pub mod lockstep;
+pub mod palette;
+pub mod persistence;
+#[cfg(feature = "std")]
+pub mod profiler;
+#[cfg(feature = "std")]
+pub mod recompiler;
+#[cfg(feature = "export")]
+pub mod recorder;
+pub mod state;
+pub mod timing;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod trace_diff;
 
 /// The frame buffer's width in pixels
 pub const DISPLAY_WIDTH: usize = 64;
@@ -12,6 +55,9 @@ const PROGRAM_START_ADDRESS: u16 = 0x200;
 /// The number bytes of memory
 const MEMORY_SIZE: usize = 4096;
 
+/// The size in bytes of the largest program that fits in memory
+pub const MAX_PROGRAM_SIZE: usize = MEMORY_SIZE - PROGRAM_START_ADDRESS as usize;
+
 /// The number of bytes the stack can hold
 const STACK_SIZE: usize = 16;
 
@@ -55,6 +101,31 @@ pub enum EmulatorType {
     Chip48
 }
 
+impl core::str::FromStr for EmulatorType {
+    type Err = ParseEmulatorTypeError;
+
+    /// Parses an emulator type by name: `vip` or `cosmac-vip`, or `chip48` or `chip-48`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "vip" | "cosmac-vip" => Ok(EmulatorType::CosmacVip),
+            "chip48" | "chip-48" => Ok(EmulatorType::Chip48),
+            _ => Err(ParseEmulatorTypeError(name.into())),
+        }
+    }
+}
+
+/// The error returned when parsing an emulator type from an unknown name
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseEmulatorTypeError(pub alloc::string::String);
+
+impl core::fmt::Display for ParseEmulatorTypeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "unknown emulator type {}, expected vip or chip48", self.0)
+    }
+}
+
+impl core::error::Error for ParseEmulatorTypeError {}
+
 /// Represents a key on the Chip-8 keypad.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Chip8Key {
@@ -93,6 +164,24 @@ pub enum Chip8Key {
 }
 
 impl Chip8Key {
+    /// All keys in order of their value
+    pub const ALL: [Chip8Key; NUM_KEYS] = [
+        Chip8Key::Zero, Chip8Key::One, Chip8Key::Two, Chip8Key::Three,
+        Chip8Key::Four, Chip8Key::Five, Chip8Key::Six, Chip8Key::Seven,
+        Chip8Key::Eight, Chip8Key::Nine, Chip8Key::A, Chip8Key::B,
+        Chip8Key::C, Chip8Key::D, Chip8Key::E, Chip8Key::F,
+    ];
+
+    /// Gets the key with the given hexadecimal value, if the value is 0-F
+    pub fn from_value(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    /// Gets the hexadecimal value printed on the key
+    pub fn value(&self) -> u8 {
+        self.key_index() as u8
+    }
+
     /// Gets the key index in the chip8 keypad for the given key
     fn key_index(&self) -> usize {
         match self {
@@ -125,6 +214,91 @@ pub enum KeyState {
     Down
 }
 
+/// Identifies a register for reading and writing it from outside the interpreter, e.g. from a
+/// debugger
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Register {
+    /// One of the variable registers V0 to VF
+    Variable(u8),
+    /// The index register I
+    Index,
+    /// The program counter
+    ProgramCounter,
+    /// The stack pointer
+    StackPointer,
+    /// The delay timer
+    DelayTimer,
+    /// The sound timer
+    SoundTimer,
+}
+
+/// A source of random numbers for the interpreter. With the `rand` feature, every `rand` random
+/// number generator is one
+pub trait Chip8Rng {
+    /// Generates a random byte
+    fn random_byte(&mut self) -> u8;
+}
+
+#[cfg(feature = "rand")]
+impl<R: rand::RngCore> Chip8Rng for R {
+    fn random_byte(&mut self) -> u8 {
+        rand::Rng::random(self)
+    }
+}
+
+/// Observes instructions as the interpreter executes them, e.g. to trace execution. Observers are
+/// passed generically, so running without one through `()` compiles to the same code as not
+/// observing at all. Both methods do nothing by default
+pub trait ExecutionObserver {
+    /// Called after an instruction is fetched and before it's executed
+    fn before_instruction<R: Chip8Rng>(&mut self, _chip8: &Chip8<R>, _instruction: &DisassembledInstruction) {}
+
+    /// Called after an instruction is executed
+    fn after_instruction<R: Chip8Rng>(&mut self, _chip8: &Chip8<R>, _instruction: &DisassembledInstruction) {}
+}
+
+/// Observes nothing
+impl ExecutionObserver for () {}
+
+/// Observes through a borrowed observer
+impl<O: ExecutionObserver> ExecutionObserver for &mut O {
+    fn before_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        (**self).before_instruction(chip8, instruction);
+    }
+
+    fn after_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        (**self).after_instruction(chip8, instruction);
+    }
+}
+
+/// Observes through both observers, the first one first
+impl<A: ExecutionObserver, B: ExecutionObserver> ExecutionObserver for (A, B) {
+    fn before_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        self.0.before_instruction(chip8, instruction);
+        self.1.before_instruction(chip8, instruction);
+    }
+
+    fn after_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        self.0.after_instruction(chip8, instruction);
+        self.1.after_instruction(chip8, instruction);
+    }
+}
+
+/// Observes through the observer if there is one
+impl<O: ExecutionObserver> ExecutionObserver for Option<O> {
+    fn before_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        if let Some(observer) = self {
+            observer.before_instruction(chip8, instruction);
+        }
+    }
+
+    fn after_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        if let Some(observer) = self {
+            observer.after_instruction(chip8, instruction);
+        }
+    }
+}
+
 /// Represents a 16-bit opcode
 #[derive(Debug)]
 struct OpCode {
@@ -168,7 +342,7 @@ impl OpCode {
 
 /// Represents a Chip8 interpreter
 #[derive(Debug)]
-pub struct Chip8<R: Rng> {
+pub struct Chip8<R: Chip8Rng> {
     /// [`MEMORY_SIZE`] bytes of memory
     ram: [u8; MEMORY_SIZE],
     /// frame buffer for drawing screen
@@ -195,9 +369,14 @@ pub struct Chip8<R: Rng> {
     keypad_state: [KeyState; NUM_KEYS],
     /// The state of the keypad on the previous loop through of the "put key into VX" instruction
     previous_keypad_state: [KeyState; NUM_KEYS],
+    /// Whether a draw waits for the next 60 Hz tick if there has already been one since the last
+    /// tick, as on the COSMAC VIP
+    display_wait: bool,
+    /// Whether a sprite has been drawn since the timers were last decremented
+    drawn_since_tick: bool,
 }
 
-impl<R: Rng> Chip8<R> {
+impl<R: Chip8Rng> Chip8<R> {
     /// Creates a new Chip8 instance
     pub fn new(emulator_type: EmulatorType, rng: R) -> Self {
         let mut chip8 = Self {
@@ -214,6 +393,8 @@ impl<R: Rng> Chip8<R> {
             rng,
             keypad_state: [KeyState::Up; NUM_KEYS],
             previous_keypad_state: [KeyState::Up; NUM_KEYS],
+            display_wait: false,
+            drawn_since_tick: false,
         };
 
         chip8.ram[FONT_START_ADDRESS..FONT_START_ADDRESS + FONT.len()].copy_from_slice(&FONT);
@@ -226,6 +407,187 @@ impl<R: Rng> Chip8<R> {
         &self.frame_buffer
     }
 
+    /// Gets the Chip-8 instances memory
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Gets the value of the program counter register
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Gets the value of the index register
+    pub fn index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    /// Gets the variable registers V0 to VF
+    pub fn variable_registers(&self) -> &[u8; VARIABLE_REGISTER_COUNT] {
+        &self.variable_registers
+    }
+
+    /// Gets the addresses currently pushed onto the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    /// Gets the value of the stack pointer
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    /// Gets the value of the delay timer register
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Gets the value of the sound timer register
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Gets the emulator type instructions are interpreted as
+    pub fn emulator_type(&self) -> EmulatorType {
+        self.emulator_type
+    }
+
+    /// Makes a draw wait for the next 60 Hz tick, i.e. the next [`Chip8::decrement_timers`], if a
+    /// sprite has already been drawn since the last one. This limits games to 60 sprites a second
+    /// as on the COSMAC VIP however many instructions are run per frame
+    pub fn set_display_wait(&mut self, display_wait: bool) {
+        self.display_wait = display_wait;
+    }
+
+    /// Returns whether draws wait for the next 60 Hz tick
+    pub fn display_wait(&self) -> bool {
+        self.display_wait
+    }
+
+    /// Returns whether the next instruction is a draw stalled until the next 60 Hz tick. Executing
+    /// it does nothing until then
+    pub fn is_waiting_for_display(&self) -> bool {
+        self.draw_would_wait() && matches!(self.next_instruction(), Instruction::Draw { .. })
+    }
+
+    /// Returns whether a draw executed now would have to wait for the next 60 Hz tick, because
+    /// display wait is on and a sprite has already been drawn since the last one
+    pub fn draw_would_wait(&self) -> bool {
+        self.display_wait && self.drawn_since_tick
+    }
+
+    /// Decodes the instruction at the program counter without executing it
+    pub fn next_instruction(&self) -> Instruction {
+        let address = self.program_counter as usize;
+        let opcode = self.ram.get(address..address + 2).map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+
+        Instruction::decode(opcode)
+    }
+
+    /// Gets the random number generator
+    pub fn rng(&self) -> &R {
+        &self.rng
+    }
+
+    /// Gets the random number generator mutably, e.g. to restore its state
+    pub fn rng_mut(&mut self) -> &mut R {
+        &mut self.rng
+    }
+
+    /// Gets the value of a register
+    pub fn register(&self, register: Register) -> u16 {
+        match register {
+            Register::Variable(x) => self.variable_registers[x as usize & 0xF] as u16,
+            Register::Index => self.index_register,
+            Register::ProgramCounter => self.program_counter,
+            Register::StackPointer => self.stack_pointer as u16,
+            Register::DelayTimer => self.delay_timer as u16,
+            Register::SoundTimer => self.sound_timer as u16,
+        }
+    }
+
+    /// Sets the value of a register. Values are truncated to the register's width, the program
+    /// counter and index register to the address space and the stack pointer to the stack size
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        match register {
+            Register::Variable(x) => self.variable_registers[x as usize & 0xF] = value as u8,
+            Register::Index => self.index_register = value,
+            Register::ProgramCounter => self.program_counter = value % MEMORY_SIZE as u16,
+            Register::StackPointer => self.stack_pointer = value.min(STACK_SIZE as u16) as u8,
+            Register::DelayTimer => self.delay_timer = value as u8,
+            Register::SoundTimer => self.sound_timer = value as u8,
+        }
+    }
+
+    /// Writes bytes to memory starting at address. Returns false without writing anything if
+    /// the bytes don't fit in memory
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> bool {
+        let start = address as usize;
+        match self.ram.get_mut(start..start + bytes.len()) {
+            Some(memory) => {
+                memory.copy_from_slice(bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gets the memory mutably. This and the other `_mut` accessors and primitives below let
+    /// recompiled code carry out instructions directly, without decoding them
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    /// Gets the frame buffer mutably
+    pub fn frame_buffer_mut(&mut self) -> &mut [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT] {
+        &mut self.frame_buffer
+    }
+
+    /// Gets the program counter mutably. Unlike [`Chip8::set_register`] the address isn't wrapped
+    pub fn program_counter_mut(&mut self) -> &mut u16 {
+        &mut self.program_counter
+    }
+
+    /// Gets the index register mutably
+    pub fn index_register_mut(&mut self) -> &mut u16 {
+        &mut self.index_register
+    }
+
+    /// Gets the variable registers V0 to VF mutably
+    pub fn variable_registers_mut(&mut self) -> &mut [u8; VARIABLE_REGISTER_COUNT] {
+        &mut self.variable_registers
+    }
+
+    /// Pushes a return address onto the stack, as a call does. Panics if the stack is full
+    pub fn push_stack(&mut self, address: u16) {
+        self.stack[self.stack_pointer as usize] = address;
+        self.stack_pointer += 1;
+    }
+
+    /// Pops a return address off the stack, as a return does. Panics if the stack is empty
+    pub fn pop_stack(&mut self) -> u16 {
+        self.stack_pointer -= 1;
+        self.stack[self.stack_pointer as usize]
+    }
+
+    /// Draws an n row sprite from the memory the index register points at, at the coordinates in
+    /// VX and VY, setting VF if any pixel was turned off. This doesn't wait for the display, see
+    /// [`Chip8::draw_would_wait`]
+    pub fn draw_sprite(&mut self, x: u8, y: u8, n: u8) {
+        self.draw(x, y, n);
+    }
+
+    /// Checks for a key released since the last check, as FX0A waits for, and remembers the
+    /// keypad's state for the next check. Returns the key's value if there was one
+    pub fn poll_key_release(&mut self) -> Option<u8> {
+        let key_option = self.keypad_state.iter()
+            .enumerate()
+            .position(|(i, key_state)| *key_state == KeyState::Up && self.previous_keypad_state[i] == KeyState::Down);
+
+        self.previous_keypad_state = self.keypad_state;
+        key_option.map(|key_index| key_index as u8)
+    }
+
     /// Returns whether the emulator should be playing a sound
     pub fn is_playing_sound(&self) -> bool {
         self.sound_timer > 0
@@ -256,6 +618,32 @@ impl<R: Rng> Chip8<R> {
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+
+        self.drawn_since_tick = false;
+    }
+
+    /// Runs one 60 Hz frame by executing `instructions_per_frame` instructions and then
+    /// decrementing the delay and sound timers once. With [`Chip8::set_display_wait`], the frame
+    /// ends early at a draw that has to wait for the next one
+    pub fn run_frame(&mut self, instructions_per_frame: usize) {
+        self.run_frame_with(instructions_per_frame, &mut ());
+    }
+
+    /// Runs one 60 Hz frame like [`Chip8::run_frame`], passing each instruction to an observer
+    pub fn run_frame_with<O: ExecutionObserver>(&mut self, instructions_per_frame: usize, observer: &mut O) {
+        self.execute_frame_with(instructions_per_frame, observer);
+        self.decrement_timers();
+    }
+
+    /// Executes a frame's instructions like [`Chip8::run_frame_with`] without decrementing the
+    /// timers, for callers that need the state in between, e.g. whether the buzzer sounded
+    pub fn execute_frame_with<O: ExecutionObserver>(&mut self, instructions_per_frame: usize, observer: &mut O) {
+        for _ in 0..instructions_per_frame {
+            if self.is_waiting_for_display() {
+                break;
+            }
+            self.execute_next_instruction_with(observer);
+        }
     }
 
     /// gets the current KeyState for a key
@@ -267,61 +655,81 @@ impl<R: Rng> Chip8<R> {
     /// and increment the program counter register so the next instruction can be executed on the
     /// next call.
     pub fn execute_next_instruction(&mut self) {
-         let opcode = self.fetch_next_opcode();
+        self.execute_next_instruction_with(&mut ());
+    }
+
+    /// Executes the next instruction like [`Chip8::execute_next_instruction`], telling an observer
+    /// about it before and after. Does nothing if it's a draw waiting for the next 60 Hz tick
+    pub fn execute_next_instruction_with<O: ExecutionObserver>(&mut self, observer: &mut O) {
+        if self.is_waiting_for_display() {
+            return;
+        }
+
+        let address = self.program_counter;
+        let opcode = self.fetch_next_opcode();
+        let executed = DisassembledInstruction {
+            address,
+            opcode: opcode.opcode,
+            instruction: Instruction::decode(opcode.opcode),
+        };
+
+        observer.before_instruction(self, &executed);
 
         self.program_counter += 2;
+        self.execute_instruction(executed.instruction);
 
-        match opcode.opcode {
-            0x00E0 => self.clear_screen(),
-            0x00EE => self.return_from_subroutine(),
-            0x1000..=0x1FFF => self.jump(opcode.nnn()),
-            0x2000..=0x2FFF => self.call_subroutine(opcode.nnn()),
-            0x3000..=0x3FFF => self.skip_instruction_if_vx_equals_nn(opcode.x(), opcode.nn()),
-            0x4000..=0x4FFF => self.skip_instruction_if_vx_not_equals_nn(opcode.x(), opcode.nn()),
-            0x5000..=0x5FFF => self.skip_instruction_if_vx_equals_vy(opcode.x(), opcode.y()),
-            0x6000..=0x6FFF => self.set_variable_register(opcode.x(), opcode.nn()),
-            0x7000..=0x7FFF => self.add_to_variable_register(opcode.x(), opcode.nn()),
-            0x8000..=0x8FFF => {
-                match opcode.n() {
-                    0x0 => self.set_vx_to_vy(opcode.x(), opcode.y()),
-                    0x1 => self.binary_or_vx_with_vy(opcode.x(), opcode.y()),
-                    0x2 => self.binary_and_vx_with_vy(opcode.x(), opcode.y()),
-                    0x3 => self.binary_xor_vx_with_vy(opcode.x(), opcode.y()),
-                    0x4 => self.add_vy_to_vx(opcode.x(), opcode.y()),
-                    0x5 => self.subtract_vy_from_vx(opcode.x(), opcode.y()),
-                    0x6 => self.shift_vx_right(opcode.x(), opcode.y()),
-                    0x7 => self.subtract_vx_from_vy_into_vx(opcode.x(), opcode.y()),
-                    0xE => self.shift_vx_left(opcode.x(), opcode.y()),
-                    _ => self.panic_for_invalid_opcode(opcode) // TODO test this case
-                }
-            },
-            0x9000..=0x9FFF => self.skip_instruction_if_vx_not_equals_vy(opcode.x(), opcode.y()),
-            0xA000..=0xAFFF => self.set_index_register(opcode.nnn()),
-            0xB000..=0xBFFF => self.jump_with_offset(opcode.nnn()),
-            0xC000..=0xCFFF => self.randomize_vx(opcode.x(), opcode.nn()),
-            0xD000..=0xDFFF => self.draw(opcode.x(), opcode.y(), opcode.n()),
-            0xE000..=0xEFFF => {
-                match opcode.nn() {
-                    0x9E => self.skip_if_key_down(opcode.x()),
-                    0xA1 => self.skip_if_key_up(opcode.x()),
-                    _ => self.panic_for_invalid_opcode(opcode) // TODO test this case
-                }
-            }
-            0xF000..=0xFFFF => {
-                match opcode.nn() {
-                    0x07 => self.set_vx_to_delay_timer(opcode.x()),
-                    0x0A => self.put_key_into_vx(opcode.x()),
-                    0x15 => self.set_delay_timer_to_vx(opcode.x()),
-                    0x18 => self.set_sound_timer_to_vx(opcode.x()),
-                    0x1E => self.add_vx_to_index_register(opcode.x()),
-                    0x29 => self.point_index_register_at_font_character(opcode.x()),
-                    0x33 => self.put_vx_decimal_digits_into_memory(opcode.x()),
-                    0x55 => self.store_variable_registers_to_memory(opcode.x()),
-                    0x65 => self.load_variable_registers_from_memory(opcode.x()),
-                    _ => self.panic_for_invalid_opcode(opcode) // TODO test this case
-                }
-            }
-            _ => self.panic_for_invalid_opcode(opcode)
+        observer.after_instruction(self, &executed);
+    }
+
+    /// Executes an instruction that was decoded ahead of time, such as by a recompiler, as if it
+    /// had just been fetched from the program counter, which must point at it
+    pub fn execute_predecoded(&mut self, instruction: Instruction) {
+        if self.is_waiting_for_display() {
+            return;
+        }
+
+        self.program_counter += 2;
+        self.execute_instruction(instruction);
+    }
+
+    /// Executes a decoded instruction. The program counter must already point past it
+    fn execute_instruction(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::ClearScreen => self.clear_screen(),
+            Instruction::Return => self.return_from_subroutine(),
+            Instruction::Jump { nnn } => self.jump(nnn),
+            Instruction::Call { nnn } => self.call_subroutine(nnn),
+            Instruction::SkipIfVxEqualsNn { x, nn } => self.skip_instruction_if_vx_equals_nn(x, nn),
+            Instruction::SkipIfVxNotEqualsNn { x, nn } => self.skip_instruction_if_vx_not_equals_nn(x, nn),
+            Instruction::SkipIfVxEqualsVy { x, y } => self.skip_instruction_if_vx_equals_vy(x, y),
+            Instruction::SetVx { x, nn } => self.set_variable_register(x, nn),
+            Instruction::AddToVx { x, nn } => self.add_to_variable_register(x, nn),
+            Instruction::SetVxToVy { x, y } => self.set_vx_to_vy(x, y),
+            Instruction::OrVxWithVy { x, y } => self.binary_or_vx_with_vy(x, y),
+            Instruction::AndVxWithVy { x, y } => self.binary_and_vx_with_vy(x, y),
+            Instruction::XorVxWithVy { x, y } => self.binary_xor_vx_with_vy(x, y),
+            Instruction::AddVyToVx { x, y } => self.add_vy_to_vx(x, y),
+            Instruction::SubtractVyFromVx { x, y } => self.subtract_vy_from_vx(x, y),
+            Instruction::ShiftVxRight { x, y } => self.shift_vx_right(x, y),
+            Instruction::SubtractVxFromVyIntoVx { x, y } => self.subtract_vx_from_vy_into_vx(x, y),
+            Instruction::ShiftVxLeft { x, y } => self.shift_vx_left(x, y),
+            Instruction::SkipIfVxNotEqualsVy { x, y } => self.skip_instruction_if_vx_not_equals_vy(x, y),
+            Instruction::SetIndex { nnn } => self.set_index_register(nnn),
+            Instruction::JumpWithOffset { nnn } => self.jump_with_offset(nnn),
+            Instruction::Random { x, nn } => self.randomize_vx(x, nn),
+            Instruction::Draw { x, y, n } => self.draw(x, y, n),
+            Instruction::SkipIfKeyDown { x } => self.skip_if_key_down(x),
+            Instruction::SkipIfKeyUp { x } => self.skip_if_key_up(x),
+            Instruction::SetVxToDelayTimer { x } => self.set_vx_to_delay_timer(x),
+            Instruction::WaitForKey { x } => self.put_key_into_vx(x),
+            Instruction::SetDelayTimer { x } => self.set_delay_timer_to_vx(x),
+            Instruction::SetSoundTimer { x } => self.set_sound_timer_to_vx(x),
+            Instruction::AddVxToIndex { x } => self.add_vx_to_index_register(x),
+            Instruction::SetIndexToFontCharacter { x } => self.point_index_register_at_font_character(x),
+            Instruction::StoreDecimalDigits { x } => self.put_vx_decimal_digits_into_memory(x),
+            Instruction::StoreRegisters { x } => self.store_variable_registers_to_memory(x),
+            Instruction::LoadRegisters { x } => self.load_variable_registers_from_memory(x),
+            Instruction::Invalid { opcode } => self.panic_for_invalid_opcode(OpCode::new(opcode)), // TODO test this case
         }
     }
 
@@ -370,6 +778,7 @@ impl<R: Rng> Chip8<R> {
     /// the frame buffer, at horizontal X coordinate held in variable register at index x and the Y
     /// coordinate held in the variable register at index y
     fn draw(&mut self, x: u8, y: u8, n: u8) {
+        self.drawn_since_tick = true;
         let sprite_memory_address = self.index_register as usize;
         let sprite_bytes = &self.ram[sprite_memory_address..sprite_memory_address + n as usize];
         let x_offset = self.variable_registers[x as usize] as usize % DISPLAY_WIDTH;
@@ -404,16 +813,13 @@ impl<R: Rng> Chip8<R> {
 
     /// Pushes the current program counter address to the stack and jumps to a new address
     fn call_subroutine(&mut self, nnn: u16) {
-        self.stack[self.stack_pointer as usize] = self.program_counter;
-        self.stack_pointer += 1;
+        self.push_stack(self.program_counter);
         self.program_counter = nnn;
     }
 
     /// Pops an address from the stack and sets the program counter to it
     fn return_from_subroutine(&mut self) {
-        self.stack_pointer -= 1;
-        let address = self.stack[self.stack_pointer as usize];
-        self.program_counter = address;
+        self.program_counter = self.pop_stack();
     }
 
     /// Skips an instruction by incrementing program counter by 2 if variable register at index x == nn
@@ -550,7 +956,7 @@ impl<R: Rng> Chip8<R> {
 
     /// Generates a random number, does a binary and with nn and puts the value in Vx
     fn randomize_vx(&mut self, x: u8, nn: u8) {
-        let random_number = self.rng.random::<u8>();
+        let random_number = self.rng.random_byte();
         self.variable_registers[x as usize] = random_number & nn;
     }
 
@@ -592,16 +998,9 @@ impl<R: Rng> Chip8<R> {
 
     /// If a key is pressed this puts the key into Vx, otherwise it decrements the program counter by 2
     fn put_key_into_vx(&mut self, x: u8) {
-        let key_option = self.keypad_state.iter()
-            .enumerate()
-            .position(|(i, key_state)| *key_state == KeyState::Up && self.previous_keypad_state[i] == KeyState::Down);
-
-        if let Some(key_index) = key_option {
-            self.previous_keypad_state = self.keypad_state;
-            self.variable_registers[x as usize] = key_index as u8;
-        } else {
-            self.previous_keypad_state = self.keypad_state;
-            self.program_counter -= 2;
+        match self.poll_key_release() {
+            Some(key) => self.variable_registers[x as usize] = key,
+            None => self.program_counter -= 2,
         }
     }
 
@@ -662,10 +1061,31 @@ impl<R: Rng> Chip8<R> {
 
 #[cfg(test)]
 mod tests {
-    use rand::rngs::{StdRng};
-    use rand::SeedableRng;
+    use alloc::vec;
     use super::*;
 
+    /// A small deterministic generator, so the core's tests don't need `rand`
+    #[derive(Debug, Clone)]
+    pub(crate) struct TestRng(u64);
+
+    impl TestRng {
+        /// Creates a generator that always produces the same numbers for a seed
+        pub(crate) fn new(seed: u64) -> Self {
+            Self(seed)
+        }
+    }
+
+    impl Chip8Rng for TestRng {
+        fn random_byte(&mut self) -> u8 {
+            // SplitMix64
+            self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            ((z ^ (z >> 31)) >> 32) as u8
+        }
+    }
+
     fn draw_test_sprite(test_frame_buffer: &mut [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
                         x_offset: u8,
                         y_offset: u8,
@@ -685,7 +1105,7 @@ mod tests {
 
     #[test]
     fn can_create_new_chip_8() {
-        let chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         let mut expected_ram = [0; MEMORY_SIZE];
         expected_ram[0x050..0x050 + FONT.len()].copy_from_slice(&FONT);
@@ -715,7 +1135,7 @@ mod tests {
 
     #[test]
     fn can_create_new_chip_8_with_chip_48_type() {
-        let chip8 = Chip8::new(EmulatorType::Chip48, rand::rng());
+        let chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
 
         assert_eq!(EmulatorType::Chip48, chip8.emulator_type);
     }
@@ -734,7 +1154,7 @@ mod tests {
 
     #[test]
     fn can_load_program() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         let program = [0x00, 0xE0, 0x12, 0x34, 0x56, 0x78];
         chip8.load_program(&program);
 
@@ -742,9 +1162,21 @@ mod tests {
         assert_eq!(program, chip8.ram[start_address..start_address + program.len()]);
     }
 
+    #[test]
+    fn can_convert_keys_to_and_from_values() {
+        for value in 0..16 {
+            let key = Chip8Key::from_value(value).unwrap();
+            assert_eq!(value, key.value());
+            assert_eq!(value as usize, key.key_index());
+        }
+
+        assert_eq!(Some(Chip8Key::A), Chip8Key::from_value(0xA));
+        assert_eq!(None, Chip8Key::from_value(0x10));
+    }
+
     #[test]
     fn can_handle_key_down() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         assert_eq!(KeyState::Up, chip8.keypad_state[Chip8Key::C.key_index()]);
 
@@ -755,7 +1187,7 @@ mod tests {
 
     #[test]
     fn can_handle_key_up() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.key_down(Chip8Key::C);
 
@@ -769,7 +1201,7 @@ mod tests {
     #[test]
     fn can_get_key_state() {
         let key = Chip8Key::C;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.key_down(key);
         assert_eq!(KeyState::Down, chip8.key_state(key));
@@ -780,7 +1212,7 @@ mod tests {
 
     #[test]
     fn can_decrement_timers() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.delay_timer = 100;
         chip8.sound_timer = 100;
 
@@ -792,7 +1224,7 @@ mod tests {
 
     #[test]
     fn decrement_timers_does_nothing_if_timers_are_at_zero() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.delay_timer = 0;
         chip8.sound_timer = 0;
 
@@ -802,9 +1234,139 @@ mod tests {
         assert_eq!(0, chip8.sound_timer);
     }
 
+    #[test]
+    fn run_frame_executes_instructions_then_decrements_timers() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&[0x60, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01]);
+        chip8.delay_timer = 10;
+
+        chip8.run_frame(3);
+
+        assert_eq!(0x206, chip8.program_counter);
+        assert_eq!(3, chip8.variable_registers[0]);
+        assert_eq!(9, chip8.delay_timer);
+    }
+
+    #[test]
+    fn display_wait_limits_draws_to_one_per_frame() {
+        // D001 7101 1200, drawing and counting draws in V1 forever
+        let program = [0xD0, 0x01, 0x71, 0x01, 0x12, 0x00];
+        let mut waiting = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        let mut free = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        waiting.load_program(&program);
+        free.load_program(&program);
+        waiting.set_display_wait(true);
+
+        for _ in 0..3 {
+            waiting.run_frame(30);
+            free.run_frame(30);
+        }
+
+        assert_eq!(3, waiting.variable_registers[1]);
+        assert_eq!(30, free.variable_registers[1]);
+    }
+
+    #[test]
+    fn a_draw_waiting_for_the_display_runs_after_the_timers_tick() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&[0xD0, 0x01, 0xD0, 0x01]);
+        chip8.set_display_wait(true);
+
+        chip8.execute_next_instruction();
+        assert!(chip8.is_waiting_for_display());
+        chip8.execute_next_instruction();
+        assert_eq!(0x202, chip8.program_counter);
+
+        chip8.decrement_timers();
+        assert!(!chip8.is_waiting_for_display());
+        chip8.execute_next_instruction();
+        assert_eq!(0x204, chip8.program_counter);
+    }
+
+    #[test]
+    fn can_get_registers() {
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
+        chip8.program_counter = 0x246;
+        chip8.index_register = 0x123;
+        chip8.variable_registers[0x3] = 0x45;
+        chip8.stack[0] = 0x202;
+        chip8.stack[1] = 0x30A;
+        chip8.stack_pointer = 2;
+        chip8.delay_timer = 7;
+        chip8.sound_timer = 9;
+
+        assert_eq!(0x246, chip8.program_counter());
+        assert_eq!(0x123, chip8.index_register());
+        assert_eq!(0x45, chip8.variable_registers()[0x3]);
+        assert_eq!([0x202, 0x30A], chip8.stack());
+        assert_eq!(2, chip8.stack_pointer());
+        assert_eq!(7, chip8.delay_timer());
+        assert_eq!(9, chip8.sound_timer());
+        assert_eq!(EmulatorType::Chip48, chip8.emulator_type());
+        assert_eq!(MEMORY_SIZE, chip8.ram().len());
+    }
+
+    #[test]
+    fn can_get_and_set_registers() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+
+        chip8.set_register(Register::Variable(0xA), 0x1FF);
+        chip8.set_register(Register::Index, 0xABC);
+        chip8.set_register(Register::ProgramCounter, 0x1234);
+        chip8.set_register(Register::StackPointer, 40);
+        chip8.set_register(Register::DelayTimer, 3);
+        chip8.set_register(Register::SoundTimer, 4);
+
+        assert_eq!(0xFF, chip8.register(Register::Variable(0xA)));
+        assert_eq!(0xABC, chip8.register(Register::Index));
+        assert_eq!(0x234, chip8.register(Register::ProgramCounter));
+        assert_eq!(STACK_SIZE as u16, chip8.register(Register::StackPointer));
+        assert_eq!(3, chip8.register(Register::DelayTimer));
+        assert_eq!(4, chip8.register(Register::SoundTimer));
+    }
+
+    #[test]
+    fn can_write_memory() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+
+        assert!(chip8.write_memory(0x300, &[1, 2, 3]));
+        assert_eq!([1, 2, 3], chip8.ram[0x300..0x303]);
+
+        assert!(!chip8.write_memory(0xFFE, &[1, 2, 3]));
+        assert_eq!([0, 0], chip8.ram[0xFFE..]);
+    }
+
+    #[test]
+    fn observer_sees_state_before_and_after_each_instruction() {
+        /// Records V0 around each instruction
+        #[derive(Default)]
+        struct V0Observer {
+            seen: Vec<(u16, u8, u8)>,
+            before: u8,
+        }
+
+        impl ExecutionObserver for V0Observer {
+            fn before_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, _instruction: &DisassembledInstruction) {
+                self.before = chip8.variable_registers[0];
+            }
+
+            fn after_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+                self.seen.push((instruction.address, self.before, chip8.variable_registers[0]));
+            }
+        }
+
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&[0x60, 0x05, 0x70, 0x01]);
+        let mut observer = V0Observer::default();
+
+        chip8.run_frame_with(2, &mut observer);
+
+        assert_eq!(vec![(0x200, 0, 5), (0x202, 5, 6)], observer.seen);
+    }
+
     #[test]
     fn can_fetch_next_opcode() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x00;
         chip8.ram[0x201] = 0xE0;
         chip8.program_counter = 0x200;
@@ -816,7 +1378,7 @@ mod tests {
 
     #[test]
     fn can_clear_screen() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.frame_buffer = [1; DISPLAY_WIDTH * DISPLAY_HEIGHT];
         chip8.clear_screen();
         assert_eq!([0; DISPLAY_WIDTH * DISPLAY_HEIGHT], chip8.frame_buffer);
@@ -824,7 +1386,7 @@ mod tests {
 
     #[test]
     fn can_jump() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x200;
         chip8.jump(0x300);
         assert_eq!(0x300, chip8.program_counter);
@@ -832,7 +1394,7 @@ mod tests {
 
     #[test]
     fn can_set_variable_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.set_variable_register(0x2, 0x34);
         chip8.set_variable_register(0x7, 0xAA);
@@ -843,7 +1405,7 @@ mod tests {
 
     #[test]
     fn can_add_to_variable_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.set_variable_register(0x2, 0x34);
         chip8.set_variable_register(0x7, 0xAA);
@@ -863,7 +1425,7 @@ mod tests {
         let initial_value: u8 = 0xF3;
         let value_to_add: u8 = 0x34;
         let expected_result = initial_value.wrapping_add(value_to_add);
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.set_variable_register(0x2, initial_value);
 
@@ -876,7 +1438,7 @@ mod tests {
 
     #[test]
     fn can_set_index_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.set_index_register(0x300);
         assert_eq!(0x300, chip8.index_register);
     }
@@ -886,7 +1448,7 @@ mod tests {
         let x_offset = 34;
         let y_offset = 12;
         let sprite_bytes = [0b11111111, 0b01010101, 0b00000000, 0b11011101];
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         let mut test_frame_buffer = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
 
         draw_test_sprite(&mut test_frame_buffer, x_offset, y_offset, &sprite_bytes);
@@ -907,7 +1469,7 @@ mod tests {
         let x_offset = 34;
         let y_offset = 12;
         let sprite_bytes = [0b11111111, 0b01010101];
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         let mut test_frame_buffer = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
 
         test_frame_buffer[12 * DISPLAY_WIDTH + 34..12 * DISPLAY_WIDTH + 34 + 8]
@@ -941,7 +1503,7 @@ mod tests {
         let x_offset = 60;
         let y_offset = 30;
         let sprite_bytes = [0b11111111, 0b01010101, 0b00000000, 0b11011101];
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         let mut test_frame_buffer = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
 
         draw_test_sprite(&mut test_frame_buffer, x_offset, y_offset, &sprite_bytes);
@@ -959,7 +1521,7 @@ mod tests {
 
     #[test]
     fn can_call_subroutine() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x202;
 
         chip8.call_subroutine(0x300);
@@ -971,7 +1533,7 @@ mod tests {
 
     #[test]
     fn can_return_from_subroutine() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.stack[0] = 0x200;
 
         chip8.call_subroutine(0x300);
@@ -986,7 +1548,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_equals_nn_skips_when_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.program_counter = 0x202;
 
@@ -997,7 +1559,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_equals_nn_does_not_skips_when_not_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.program_counter = 0x202;
 
@@ -1008,7 +1570,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_not_equals_nn_skips_when_not_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.program_counter = 0x202;
 
@@ -1019,7 +1581,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_not_equals_nn_does_not_skips_when_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.program_counter = 0x202;
 
@@ -1030,7 +1592,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_equals_vy_skips_when_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0x3] = 0x34;
         chip8.program_counter = 0x202;
@@ -1042,7 +1604,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_equals_vy_does_not_skips_when_not_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0x3] = 0x35;
         chip8.program_counter = 0x202;
@@ -1054,7 +1616,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_not_equals_vy_skips_when_not_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0x3] = 0x35;
         chip8.program_counter = 0x202;
@@ -1066,7 +1628,7 @@ mod tests {
 
     #[test]
     fn skip_instruction_if_vx_not_equals_vy_does_not_skips_when_equal() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0x3] = 0x34;
         chip8.program_counter = 0x202;
@@ -1078,7 +1640,7 @@ mod tests {
 
     #[test]
     fn can_set_vx_to_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0xF] = 0xAF;
         chip8.program_counter = 0x202;
@@ -1090,7 +1652,7 @@ mod tests {
 
     #[test]
     fn can_binary_or_vx_with_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0xC] = 0xAF;
 
@@ -1101,7 +1663,7 @@ mod tests {
 
     #[test]
     fn can_binary_and_vx_with_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0xC] = 0xAF;
 
@@ -1112,7 +1674,7 @@ mod tests {
 
     #[test]
     fn can_binary_xor_vx_with_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0xC] = 0xAF;
 
@@ -1125,7 +1687,7 @@ mod tests {
     fn can_add_vy_to_vx() {
         let x_val: u8 = 0x34;
         let y_val: u8 = 0xAF;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.variable_registers[0xF] = 0x01;
@@ -1140,7 +1702,7 @@ mod tests {
     fn can_add_vy_to_vx_with_carry() {
         let x_val: u8 = 0xD4;
         let y_val: u8 = 0xAF;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.variable_registers[0xF] = 0x00;
@@ -1155,7 +1717,7 @@ mod tests {
     fn can_subtract_vy_from_vx() {
         let x_val: u8 = 0xAF;
         let y_val: u8 = 0x34;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.variable_registers[0xF] = 0x00;
@@ -1170,7 +1732,7 @@ mod tests {
     fn can_subtract_vy_from_vx_with_borrow() {
         let x_val: u8 = 0x34;
         let y_val: u8 = 0xAF;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.variable_registers[0xF] = 0x01;
@@ -1185,7 +1747,7 @@ mod tests {
     fn can_subtract_vx_from_vy_into_vx() {
         let x_val: u8 = 0x34;
         let y_val: u8 = 0xAF;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.variable_registers[0xF] = 0x00;
@@ -1200,7 +1762,7 @@ mod tests {
     fn can_subtract_vx_from_vy_into_vx_with_borrow() {
         let x_val: u8 = 0xAF;
         let y_val: u8 = 0x34;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.variable_registers[0xF] = 0x01;
@@ -1213,7 +1775,7 @@ mod tests {
 
     #[test]
     fn can_shift_vx_right_for_cosmac_vip() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x00;
         chip8.variable_registers[0xC] = 0x13;
 
@@ -1225,7 +1787,7 @@ mod tests {
 
     #[test]
     fn can_shift_vx_right_for_chip_48() {
-        let mut chip8 = Chip8::new(EmulatorType::Chip48, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x13;
         chip8.variable_registers[0xC] = 0x34;
 
@@ -1237,7 +1799,7 @@ mod tests {
 
     #[test]
     fn can_shift_vx_left_for_cosmac_vip() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x00;
         chip8.variable_registers[0xC] = 0x13;
         chip8.variable_registers[0xF] = 0x01;
@@ -1250,7 +1812,7 @@ mod tests {
 
     #[test]
     fn can_shift_vx_left_for_chip_48() {
-        let mut chip8 = Chip8::new(EmulatorType::Chip48, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x13;
         chip8.variable_registers[0xC] = 0x34;
         chip8.variable_registers[0xF] = 0x01;
@@ -1263,7 +1825,7 @@ mod tests {
 
     #[test]
     fn can_jump_with_offset_for_cosmac_vip() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x202;
         chip8.variable_registers[0x0] = 0x12;
 
@@ -1274,7 +1836,7 @@ mod tests {
 
     #[test]
     fn jump_with_offset_does_wrapping_add_for_cosmac_vip() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x202;
         chip8.variable_registers[0x0] = 0x002;
 
@@ -1285,7 +1847,7 @@ mod tests {
 
     #[test]
     fn can_jump_with_offset_for_chip_48() {
-        let mut chip8 = Chip8::new(EmulatorType::Chip48, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
         chip8.program_counter = 0x202;
         chip8.variable_registers[0x2] = 0x12;
 
@@ -1296,7 +1858,7 @@ mod tests {
 
     #[test]
     fn jump_with_offset_does_wrapping_add_for_chip_48() {
-        let mut chip8 = Chip8::new(EmulatorType::Chip48, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
         chip8.program_counter = 0x202;
         chip8.variable_registers[0xF] = 0x02;
 
@@ -1307,20 +1869,31 @@ mod tests {
     
     #[test]
     fn can_randomize_vx() {
-        let rng = StdRng::seed_from_u64(0);
+        let rng = TestRng::new(0);
         let mut test_rng = rng.clone();
         
         let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rng);
         
         chip8.randomize_vx(2, 0x34);
         
-        let random_val = test_rng.random::<u8>();
+        let random_val = test_rng.random_byte();
         assert_eq!(random_val & 0x34, chip8.variable_registers[2])
     }
 
+    #[cfg(feature = "rand")]
+    #[test]
+    fn rand_generators_can_drive_the_interpreter() {
+        use rand::SeedableRng;
+
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rngs::StdRng::seed_from_u64(0));
+        chip8.randomize_vx(2, 0xF0);
+
+        assert_eq!(0, chip8.variable_registers[2] & 0x0F);
+    }
+
     #[test]
     fn can_skip_if_key_down() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x202;
         chip8.variable_registers[0x4] = Chip8Key::C.key_index() as u8;
@@ -1333,7 +1906,7 @@ mod tests {
 
     #[test]
     fn can_skip_if_key_up() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x202;
         chip8.variable_registers[0x4] = Chip8Key::C.key_index() as u8;
@@ -1346,7 +1919,7 @@ mod tests {
 
     #[test]
     fn can_set_vx_to_delay_timer() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.delay_timer = 0x34;
 
@@ -1357,7 +1930,7 @@ mod tests {
 
     #[test]
     fn can_set_delay_timer_to_vx() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 0x34;
 
@@ -1368,7 +1941,7 @@ mod tests {
 
     #[test]
     fn can_set_sound_timer_to_vx() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 0x34;
 
@@ -1379,7 +1952,7 @@ mod tests {
 
     #[test]
     fn can_add_vx_to_index_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 0x34;
         chip8.index_register = 0x22;
@@ -1391,7 +1964,7 @@ mod tests {
 
     #[test]
     fn can_add_vx_to_index_register_with_overflow() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 0xFF;
         chip8.index_register = 0x22;
@@ -1403,7 +1976,7 @@ mod tests {
 
     #[test]
     fn put_key_into_vx_puts_correct_key_into_vx() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.previous_keypad_state[Chip8Key::Four.key_index()] = KeyState::Down;
         chip8.previous_keypad_state[Chip8Key::C.key_index()] = KeyState::Down;
@@ -1420,7 +1993,7 @@ mod tests {
 
     #[test]
     fn put_key_into_vx_decrements_program_counter_if_no_key_has_been_pressed() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x202;
 
         chip8.put_key_into_vx(0x3);
@@ -1430,7 +2003,7 @@ mod tests {
 
     #[test]
     fn can_point_index_register_at_font_character() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 0x0C;
 
@@ -1441,7 +2014,7 @@ mod tests {
 
     #[test]
     fn can_put_vx_decimal_digits_into_memory_for_zero() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 0;
         chip8.index_register = 0x234;
@@ -1458,7 +2031,7 @@ mod tests {
 
     #[test]
     fn can_put_vx_decimal_digits_into_memory_for_one_digit_number() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 3;
         chip8.index_register = 0x234;
@@ -1472,7 +2045,7 @@ mod tests {
 
     #[test]
     fn can_put_vx_decimal_digits_into_memory_for_two_digit_number() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 43;
         chip8.index_register = 0x234;
@@ -1486,7 +2059,7 @@ mod tests {
 
     #[test]
     fn can_put_vx_decimal_digits_into_memory_for_three_digit_number() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x3] = 243;
         chip8.index_register = 0x234;
@@ -1500,7 +2073,7 @@ mod tests {
 
     #[test]
     fn can_store_variable_registers_to_memory_for_cosmac_vip() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x0] = 0x12;
         chip8.variable_registers[0x1] = 0x23;
@@ -1519,7 +2092,7 @@ mod tests {
 
     #[test]
     fn can_store_variable_registers_to_memory_for_chip_48() {
-        let mut chip8 = Chip8::new(EmulatorType::Chip48, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
 
         chip8.variable_registers[0x0] = 0x12;
         chip8.variable_registers[0x1] = 0x23;
@@ -1538,7 +2111,7 @@ mod tests {
 
     #[test]
     fn can_load_variable_registers_from_memory_for_cosmac_vip() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.ram[0xC00] = 0x12;
         chip8.ram[0xC01] = 0x23;
@@ -1557,7 +2130,7 @@ mod tests {
 
     #[test]
     fn can_load_variable_registers_from_memory_for_chip_48() {
-        let mut chip8 = Chip8::new(EmulatorType::Chip48, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(0));
 
         chip8.ram[0xC00] = 0x12;
         chip8.ram[0xC01] = 0x23;
@@ -1576,7 +2149,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_clear_screen() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x00;
         chip8.ram[0x201] = 0xE0;
         chip8.program_counter = 0x200;
@@ -1590,7 +2163,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_jump() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x12;
         chip8.ram[0x201] = 0x34;
         chip8.program_counter = 0x200;
@@ -1602,7 +2175,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_set_variable_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x63;
         chip8.ram[0x201] = 0xBC;
         chip8.program_counter = 0x200;
@@ -1615,7 +2188,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_add_to_variable_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x73;
         chip8.ram[0x201] = 0xBC;
         chip8.program_counter = 0x200;
@@ -1629,7 +2202,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_set_index_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0xA3;
         chip8.ram[0x201] = 0xBC;
         chip8.program_counter = 0x200;
@@ -1645,7 +2218,7 @@ mod tests {
         let x_offset = 34;
         let y_offset = 12;
         let sprite_bytes = [0b11111111, 0b01010101, 0b00000000, 0b11011101];
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         let mut test_frame_buffer = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
 
         draw_test_sprite(&mut test_frame_buffer, x_offset, y_offset, &sprite_bytes);
@@ -1664,7 +2237,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_call_subroutine() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x22;
         chip8.ram[0x201] = 0x11;
         chip8.program_counter = 0x200;
@@ -1678,7 +2251,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_return_from_subroutine() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x211] = 0x00;
         chip8.ram[0x212] = 0xEE;
         chip8.program_counter = 0x211;
@@ -1693,7 +2266,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_skip_instruction_if_vx_equals_nn() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x33;
         chip8.ram[0x201] = 0x34;
         chip8.program_counter = 0x200;
@@ -1706,7 +2279,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_skip_instruction_if_vx_not_equals_nn() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x43;
         chip8.ram[0x201] = 0x34;
         chip8.program_counter = 0x200;
@@ -1719,7 +2292,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_skip_instruction_if_vx_equals_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x53;
         chip8.ram[0x201] = 0x20;
         chip8.program_counter = 0x200;
@@ -1733,7 +2306,7 @@ mod tests {
 
     #[test]
     fn execute_next_instruction_can_execute_skip_instruction_if_vx_not_equals_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.ram[0x200] = 0x93;
         chip8.ram[0x201] = 0x20;
         chip8.program_counter = 0x200;
@@ -1747,7 +2320,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_set_vx_to_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0x82;
         chip8.ram[0x201] = 0xF0;
@@ -1761,7 +2334,7 @@ mod tests {
     
     #[test]
     fn execute_instruction_can_execute_binary_or_vx_with_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0xF] = 0xAF;
         chip8.program_counter = 0x200;
@@ -1775,7 +2348,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_binary_and_vx_with_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0xF] = 0xAF;
         chip8.program_counter = 0x200;
@@ -1789,7 +2362,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_binary_xor_vx_with_vy() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x34;
         chip8.variable_registers[0xF] = 0xAF;
         chip8.program_counter = 0x200;
@@ -1805,7 +2378,7 @@ mod tests {
     fn execute_instruction_can_execute_add_vy_to_vx() {
         let x_val: u8 = 0x34;
         let y_val: u8 = 0xAF;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
@@ -1823,7 +2396,7 @@ mod tests {
     fn execute_instruction_can_execute_subtract_vy_from_vx() {
         let x_val: u8 = 0xAF;
         let y_val: u8 = 0x34;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.program_counter = 0x200;
@@ -1840,7 +2413,7 @@ mod tests {
     fn execute_instruction_can_execute_subtract_vx_from_vy_into_vx() {
         let x_val: u8 = 0x34;
         let y_val: u8 = 0xAF;
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = x_val;
         chip8.variable_registers[0xC] = y_val;
         chip8.program_counter = 0x200;
@@ -1855,7 +2428,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_shift_vx_right() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x22;
         chip8.variable_registers[0xC] = 0x34;
         chip8.program_counter = 0x200;
@@ -1869,7 +2442,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_shift_vx_left() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.variable_registers[0x2] = 0x22;
         chip8.variable_registers[0xC] = 0x34;
         chip8.program_counter = 0x200;
@@ -1883,7 +2456,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_jump_with_offset() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xB2;
         chip8.ram[0x201] = 0x34;
@@ -1896,7 +2469,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_randomize_vx() {
-        let rng = StdRng::seed_from_u64(0);
+        let rng = TestRng::new(0);
         let mut test_rng = rng.clone();
         let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rng);
         chip8.program_counter = 0x200;
@@ -1905,12 +2478,12 @@ mod tests {
 
         chip8.execute_next_instruction();
 
-        assert_eq!(test_rng.random::<u8>() & 0x34, chip8.variable_registers[0x2]);
+        assert_eq!(test_rng.random_byte() & 0x34, chip8.variable_registers[0x2]);
     }
 
     #[test]
     fn execute_instruction_can_execute_skip_if_key_down() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x200;
         chip8.variable_registers[0x4] = Chip8Key::C.key_index() as u8;
         chip8.ram[0x200] = 0xE4;
@@ -1924,7 +2497,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_skip_if_key_up() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
         chip8.program_counter = 0x200;
         chip8.variable_registers[0x4] = Chip8Key::C.key_index() as u8;
         chip8.ram[0x200] = 0xE4;
@@ -1938,7 +2511,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_set_vx_to_delay_timer() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -1952,7 +2525,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_set_delay_timer_to_vx() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -1966,7 +2539,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_set_sound_timer_to_vx() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -1980,7 +2553,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_add_vx_to_index_register() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -1995,7 +2568,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_put_key_int_vx() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -2013,7 +2586,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_point_index_register_at_font_character() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -2027,7 +2600,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_put_vx_decimal_digits_into_memory() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -2044,7 +2617,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_store_variable_registers_to_memory() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -2066,7 +2639,7 @@ mod tests {
 
     #[test]
     fn execute_instruction_can_execute_load_variable_registers_from_memory() {
-        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand::rng());
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
 
         chip8.program_counter = 0x200;
         chip8.ram[0x200] = 0xF3;
@@ -2085,4 +2658,11 @@ mod tests {
         assert_eq!(0x67, chip8.variable_registers[0x3]);
         assert_eq!(0xC04, chip8.index_register);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parses_emulator_types_by_name() {
+        assert_eq!(Ok(EmulatorType::CosmacVip), "cosmac-vip".parse());
+        assert_eq!(Ok(EmulatorType::Chip48), "chip48".parse());
+        assert_eq!(Err(ParseEmulatorTypeError("schip".into())), "schip".parse::<EmulatorType>());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::debugger::Debugger;
+use crate::instruction::{disassemble, DisassembledInstruction};
+use crate::trace::TraceTiming;
+use crate::trace_diff::{compare, Difference, ReferenceState};
+use crate::{Chip8, Chip8Key, Chip8Rng};
+
+/// The first point where two interpreters running in lockstep disagreed
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LockstepDivergence {
+    /// The number of instructions both executed before the one that caused the divergence
+    pub cycle: u64,
+    /// The instruction whose execution made the states differ
+    pub instruction: DisassembledInstruction,
+    /// How the states differ. Expected values are the left interpreter's and actual values the
+    /// right's
+    pub differences: Vec<Difference>,
+}
+
+impl LockstepDivergence {
+    /// Returns whether the instruction that caused the divergence is one whose behavior depends on
+    /// the emulator type, which means the program relies on that quirk
+    pub fn is_quirk_dependent(&self) -> bool {
+        self.instruction.instruction.is_quirk_dependent()
+    }
+}
+
+/// Runs two interpreters with different settings side by side on the same program and input,
+/// one instruction at a time, and finds the first instruction after which their states differ.
+/// Both should be given identically seeded random number generators so random numbers don't
+/// cause differences. After diverging, both keep running so their screens can be compared.
+pub struct Lockstep<R: Chip8Rng> {
+    left: Chip8<R>,
+    right: Chip8<R>,
+    left_debugger: Debugger,
+    right_debugger: Debugger,
+    instructions_per_frame: usize,
+    cycle: u64,
+    divergence: Option<LockstepDivergence>,
+}
+
+impl<R: Chip8Rng> Lockstep<R> {
+    /// Runs two interpreters that already have the program loaded
+    pub fn new(left: Chip8<R>, right: Chip8<R>, instructions_per_frame: usize) -> Self {
+        Self {
+            left,
+            right,
+            left_debugger: Debugger::new(),
+            right_debugger: Debugger::new(),
+            instructions_per_frame,
+            cycle: 0,
+            divergence: None,
+        }
+    }
+
+    /// Gets the left interpreter
+    pub fn left(&self) -> &Chip8<R> {
+        &self.left
+    }
+
+    /// Gets the right interpreter
+    pub fn right(&self) -> &Chip8<R> {
+        &self.right
+    }
+
+    /// Gets the number of instructions executed so far
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Gets the first divergence, if the interpreters have diverged
+    pub fn divergence(&self) -> Option<&LockstepDivergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Presses a key on both interpreters
+    pub fn key_down(&mut self, key: Chip8Key) {
+        self.left.key_down(key);
+        self.right.key_down(key);
+    }
+
+    /// Releases a key on both interpreters
+    pub fn key_up(&mut self, key: Chip8Key) {
+        self.left.key_up(key);
+        self.right.key_up(key);
+    }
+
+    /// Executes one instruction on both interpreters. Returns the divergence if this instruction
+    /// is the one that caused it. An interpreter that reaches an invalid opcode stays there
+    pub fn step(&mut self) -> Option<&LockstepDivergence> {
+        let instruction = disassemble(self.left.ram(), self.left.program_counter(), 1).first().copied();
+
+        self.left_debugger.step(&mut self.left, self.instructions_per_frame);
+        self.right_debugger.step(&mut self.right, self.instructions_per_frame);
+        let cycle = self.cycle;
+        self.cycle += 1;
+
+        if self.divergence.is_some() {
+            return None;
+        }
+
+        let differences = compare(&self.right, &ReferenceState::capture(&self.left), TraceTiming::Before, None);
+        match instruction {
+            Some(instruction) if !differences.is_empty() => {
+                self.divergence = Some(LockstepDivergence { cycle, instruction, differences });
+                self.divergence.as_ref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Runs one frame on both interpreters. Returns the divergence if it happened during the frame
+    pub fn run_frame(&mut self) -> Option<&LockstepDivergence> {
+        let already_diverged = self.divergence.is_some();
+        for _ in 0..self.instructions_per_frame {
+            self.step();
+        }
+
+        if already_diverged { None } else { self.divergence.as_ref() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::instruction::Instruction;
+    use crate::EmulatorType;
+    use super::*;
+
+    fn lockstep(program: &[u8]) -> Lockstep<TestRng> {
+        let mut left = Chip8::new(EmulatorType::CosmacVip, TestRng::new(1));
+        let mut right = Chip8::new(EmulatorType::Chip48, TestRng::new(1));
+        left.load_program(program);
+        right.load_program(program);
+        Lockstep::new(left, right, 10)
+    }
+
+    #[test]
+    fn finds_the_quirked_instruction_that_diverged() {
+        // Loads and addition agree, then the VIP shifts VY into VX while CHIP-48 shifts VX
+        let mut lockstep = lockstep(&[0x60, 0x08, 0x61, 0x06, 0x70, 0x01, 0x80, 0x16, 0x12, 0x08]);
+
+        let divergence = lockstep.run_frame().unwrap().clone();
+
+        assert_eq!(3, divergence.cycle);
+        assert_eq!(Instruction::ShiftVxRight { x: 0, y: 1 }, divergence.instruction.instruction);
+        assert!(divergence.is_quirk_dependent());
+        assert!(divergence.differences.iter().any(|difference| matches!(difference, Difference::VariableRegister { x: 0, .. })));
+        assert_eq!(None, lockstep.run_frame());
+        assert_eq!(20, lockstep.cycle());
+    }
+
+    #[test]
+    fn programs_without_quirks_stay_in_sync() {
+        let mut lockstep = lockstep(&[0xC0, 0xFF, 0x70, 0x01, 0xA2, 0x00, 0xD0, 0x05, 0x12, 0x02]);
+        lockstep.key_down(Chip8Key::Five);
+
+        for _ in 0..10 {
+            assert_eq!(None, lockstep.run_frame());
+        }
+        assert_eq!(None, lockstep.divergence());
+        assert_eq!(lockstep.left().frame_buffer(), lockstep.right().frame_buffer());
+    }
+}
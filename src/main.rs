@@ -1,11 +1,23 @@
-extern crate rand as rand_crate;
-
+mod cli;
+mod gdb_server;
+mod headless;
 mod ui;
 
 use std::collections::HashMap;
+use anyhow::Context;
 use macroquad::prelude::*;
-use chip_8_emulator::{Chip8, EmulatorType, Chip8Key, KeyState};
+use chip_8_emulator::command_line::{exit_on_error, parse_args, seeded_rng};
+use chip_8_emulator::debugger::{Debugger, RunOutcome};
+use chip_8_emulator::detection::Detection;
+use chip_8_emulator::palette::Palette;
+use chip_8_emulator::persistence::Phosphor;
+use chip_8_emulator::{Chip8, Chip8Key, KeyState, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use cli::Options;
 use ui::audio::AudioPlayer;
+use ui::controls::Controls;
+use ui::debugger_overlay::DebuggerOverlay;
+use ui::recording::SessionRecorder;
+use ui::renderer::Renderer;
 
 /// The frequency of the tone to play for the Chip-8 sound
 const TONE_FREQUENCY: f32 = 440.0;
@@ -16,9 +28,54 @@ const TONE_DURATION: f32 = 5.0;
 /// The amplitude of the tone to play for the Chip-8 sound
 const TONE_AMPLITUDE: f32 = 0.5;
 
-#[macroquad::main("Chip-8 Emulator")]
-async fn main() {
-    let key_code_map : HashMap<KeyCode, Chip8Key> = HashMap::from([
+/// The key that switches to the next palette
+const PALETTE_KEY: KeyCode = KeyCode::F2;
+
+/// The key that shows and hides the pixel grid
+const GRID_KEY: KeyCode = KeyCode::F4;
+
+/// The key that starts and stops recording the session
+const RECORD_KEY: KeyCode = KeyCode::F9;
+
+fn main() {
+    let mut options = parse_args(cli::USAGE, Options::parse);
+    let program = exit_on_error(std::fs::read(&options.rom_path)
+        .with_context(|| format!("failed to read {}", options.rom_path.display())));
+
+    if options.detect_emulator_type {
+        let detection = Detection::detect(&program);
+        if !detection.evidence.is_empty() {
+            println!("Detected {} with {:.0}% confidence, running as {:?}",
+                     detection.platform,
+                     detection.confidence * 100.0,
+                     detection.emulator_type());
+            options.emulator_type = detection.emulator_type();
+        }
+    }
+
+    if let Some(address) = &options.gdb_address {
+        return exit_on_error(gdb_server::run(&options, &program, address));
+    }
+
+    if let Some(right_type) = options.lockstep_type {
+        if options.headless {
+            exit_on_error(headless::run_lockstep(&options, &program, right_type));
+        } else {
+            macroquad::Window::new("Chip-8 Emulator", ui::lockstep::run_lockstep_window(options, program, right_type));
+        }
+        return;
+    }
+
+    if options.headless {
+        return exit_on_error(headless::run(&options, &program));
+    }
+
+    macroquad::Window::new("Chip-8 Emulator", run_window(options, program));
+}
+
+/// Maps the left side of a QWERTY keyboard to the Chip-8 keypad
+fn key_code_map() -> HashMap<KeyCode, Chip8Key> {
+    HashMap::from([
         (KeyCode::Key1, Chip8Key::One),
         (KeyCode::Key2, Chip8Key::Two),
         (KeyCode::Key3, Chip8Key::Three),
@@ -35,34 +92,139 @@ async fn main() {
         (KeyCode::X, Chip8Key::Zero),
         (KeyCode::C, Chip8Key::B),
         (KeyCode::V, Chip8Key::F),
-    ]);
+    ])
+}
+
+/// Runs the emulator in a macroquad window until it's closed
+async fn run_window(options: Options, program: Vec<u8>) {
+    let key_code_map = key_code_map();
 
     let mut audio_player = AudioPlayer::build(TONE_FREQUENCY, TONE_DURATION, TONE_AMPLITUDE).await;
 
-    // TODO take Emulator type as a command line argument
-    let mut chip8 = Chip8::new(EmulatorType::CosmacVip, rand_crate::rng());
-    let program = include_bytes!("/home/josh/Downloads/bowling.ch8");
+    let mut chip8 = Chip8::new(options.emulator_type, seeded_rng(options.seed));
+
+    chip8.load_program(&program);
+    chip8.set_display_wait(options.display_wait);
 
-    chip8.load_program(program);
+    // Cycle through the built-in palettes, starting from the one that was asked for
+    let mut palettes = Palette::builtin();
+    let mut palette_index = match palettes.iter().position(|palette| *palette == options.palette) {
+        Some(index) => index,
+        None => {
+            palettes.insert(0, options.palette.clone());
+            0
+        }
+    };
+
+    let mut phosphor = Phosphor::new(options.persistence, DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    let mut renderer = Renderer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    let mut controls = Controls::new(options.instructions_per_frame);
+    controls.set_vip_timing(options.vip_timing);
+    let mut debugger = Debugger::new();
+    debugger.set_vip_timing(options.vip_timing);
+    let mut debugger_overlay = DebuggerOverlay::new();
+
+    let mut session_recorder = SessionRecorder::new(options.record_scale);
+    if let Some(path) = &options.record_path {
+        session_recorder.start(path, palettes[palette_index].colors());
+    }
+
+    let mut tracer = options.create_tracer().unwrap_or_else(|error| {
+        eprintln!("error: {error:#}");
+        None
+    });
+    let mut profiler = options.create_profiler();
+    let mut coverage = options.create_coverage();
+
+    // Handle quitting ourselves so a recording, trace, profile or coverage in progress can be finished first
+    prevent_quit();
 
-    let mut counter: u8 = 0;
     loop {
-        chip8.execute_next_instruction();
+        if is_quit_requested() {
+            session_recorder.stop();
+            if let Some(Err(error)) = tracer.take().map(|tracer| tracer.finish()) {
+                eprintln!("error: failed to write trace: {error}");
+            }
+            if let Some(Err(error)) = profiler.take().map(|profiler| options.write_profile(&profiler, chip8.ram())) {
+                eprintln!("error: failed to write profile: {error:#}");
+            }
+            if let Some(Err(error)) = coverage.take().map(|coverage| options.write_coverage(&coverage, chip8.ram(), program.len())) {
+                eprintln!("error: failed to write coverage: {error:#}");
+            }
+            break;
+        }
+
+        if is_key_pressed(PALETTE_KEY) {
+            palette_index = (palette_index + 1) % palettes.len();
+            println!("Palette: {}", palettes[palette_index].name());
+        }
 
-        if counter % 12 == 0 {
-            ui::renderer::render_frame(chip8.frame_buffer()).await;
+        if is_key_pressed(GRID_KEY) {
+            renderer.toggle_grid();
+        }
+
+        if is_key_pressed(RECORD_KEY) {
+            session_recorder.toggle(palettes[palette_index].colors());
+        }
+
+        let command = controls.update();
+
+        if command.reset {
+            chip8 = Chip8::new(options.emulator_type, seeded_rng(options.seed));
+            chip8.load_program(&program);
+            chip8.set_display_wait(options.display_wait);
+            phosphor.clear();
+            debugger.reset_frame();
+        }
 
-            if chip8.is_playing_sound() {
-                audio_player.play_tone();
+        let mut frames_run = 0;
+        if debugger_overlay.handle_input(&mut debugger, chip8.program_counter(), chip8.ram().len()) {
+            controls.pause();
+            match debugger.step_with(&mut chip8, command.instructions_per_frame, &mut (&mut tracer, (&mut profiler, &mut coverage))) {
+                RunOutcome::FrameCompleted => frames_run += 1,
+                RunOutcome::InvalidOpcode { address, opcode } => {
+                    println!("Invalid opcode {opcode:04X} at {address:03X}");
+                }
+                _ => {}
             }
+        }
 
-            if !chip8.is_playing_sound() {
-                audio_player.stop_tone();
+        for _ in 0..command.frames {
+            match debugger.run_frame_with(&mut chip8, command.instructions_per_frame, &mut (&mut tracer, (&mut profiler, &mut coverage))) {
+                RunOutcome::FrameCompleted | RunOutcome::Stepped => frames_run += 1,
+                RunOutcome::BreakpointHit { address } => {
+                    println!("Breakpoint hit at {address:03X}");
+                    controls.pause();
+                    break;
+                }
+                RunOutcome::InvalidOpcode { address, opcode } => {
+                    println!("Invalid opcode {opcode:04X} at {address:03X}");
+                    controls.pause();
+                    break;
+                }
             }
+        }
+
+        for _ in 0..frames_run {
+            session_recorder.record_frame(chip8.frame_buffer());
+        }
 
-            chip8.decrement_timers();
+        // Keep showing the same image while paused rather than letting it fade out
+        let brightness = if frames_run > 0 {
+            phosphor.update(chip8.frame_buffer())
+        } else {
+            phosphor.brightness()
+        };
+        renderer.draw(brightness, DISPLAY_WIDTH, DISPLAY_HEIGHT, &palettes[palette_index]);
+        debugger_overlay.draw(&chip8, &debugger);
+        controls.draw_status(if session_recorder.is_recording() { "REC" } else { "" });
+        next_frame().await;
+
+        if chip8.is_playing_sound() && !controls.is_paused() {
+            audio_player.play_tone();
+        } else {
+            audio_player.stop_tone();
         }
-        counter = counter.wrapping_add(1);
 
         for key in key_code_map.iter() {
             let is_key_down = is_key_down(*key.0);
@@ -0,0 +1,242 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use crate::persistence::MAX_BRIGHTNESS;
+
+/// A color as red, green and blue components
+pub type Rgb = [u8; 3];
+
+/// A set of colors to draw the display with.
+/// Colors are indexed by pixel value: index 0 is the background and index 1 is a lit pixel. Any
+/// further colors are for pixel values above 1, e.g. combinations of drawing planes. Pixels that
+/// are fading out under a persistence mode blend from the lit color down to the background,
+/// passing through the palette's fade colors if it has any.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Palette {
+    /// The name of the palette
+    name: String,
+    /// The colors indexed by pixel value, always at least two
+    colors: Vec<Rgb>,
+    /// Colors fading pixels pass through, from dimmest to brightest
+    fade: Vec<Rgb>,
+    /// The color for each brightness level, built from the colors and fade colors up front as
+    /// it's looked up for every pixel each frame
+    brightness_colors: [Rgb; BRIGHTNESS_LEVELS],
+}
+
+/// The number of brightness levels a [`Phosphor`](crate::persistence::Phosphor) produces
+const BRIGHTNESS_LEVELS: usize = MAX_BRIGHTNESS as usize + 1;
+
+/// An error parsing a palette
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParsePaletteError {
+    /// A color wasn't a 6 digit hexadecimal RGB value
+    InvalidColor(String),
+    /// Fewer than two colors were given
+    TooFewColors,
+    /// No built-in palette has the given name
+    UnknownName(String),
+}
+
+impl fmt::Display for ParsePaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsePaletteError::InvalidColor(color) => write!(f, "invalid color {color}, expected RRGGBB"),
+            ParsePaletteError::TooFewColors => write!(f, "a palette needs at least two colors"),
+            ParsePaletteError::UnknownName(name) => write!(f, "unknown palette {name}"),
+        }
+    }
+}
+
+impl core::error::Error for ParsePaletteError {}
+
+impl Palette {
+    /// Creates a palette from colors indexed by pixel value. Returns an error if there are fewer
+    /// than two colors
+    pub fn new(name: &str, colors: &[Rgb]) -> Result<Self, ParsePaletteError> {
+        if colors.len() < 2 {
+            return Err(ParsePaletteError::TooFewColors);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            colors: colors.to_vec(),
+            fade: Vec::new(),
+            brightness_colors: brightness_colors(colors, &[]),
+        })
+    }
+
+    /// Returns the palette with fade colors that fading pixels pass through, given from dimmest
+    /// to brightest
+    pub fn with_fade(mut self, fade: &[Rgb]) -> Self {
+        self.fade = fade.to_vec();
+        self.brightness_colors = brightness_colors(&self.colors, fade);
+        self
+    }
+
+    /// Parses a custom palette from comma separated hexadecimal colors, e.g. `000000,33ff66`.
+    /// Colors may be prefixed with `#`
+    pub fn parse_custom(colors: &str) -> Result<Self, ParsePaletteError> {
+        let colors = colors.split(',')
+            .map(parse_color)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::new("custom", &colors)
+    }
+
+    /// Gets a built-in palette by name
+    pub fn named(name: &str) -> Result<Self, ParsePaletteError> {
+        Self::builtin()
+            .into_iter()
+            .find(|palette| palette.name == name)
+            .ok_or_else(|| ParsePaletteError::UnknownName(name.to_string()))
+    }
+
+    /// Returns all built-in palettes, starting with the default classic green
+    pub fn builtin() -> Vec<Self> {
+        let palettes = [
+            Self::new("classic", &[[0, 0, 0], [0, 228, 48], [0, 117, 44], [178, 255, 178]])
+                .map(|palette| palette.with_fade(&[[0, 40, 16], [0, 128, 32]])),
+            Self::new("amber", &[[24, 12, 0], [255, 176, 0], [140, 84, 0], [255, 224, 150]])
+                .map(|palette| palette.with_fade(&[[80, 32, 0], [176, 96, 0]])),
+            Self::new("lcd", &[[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]]),
+            Self::new("high-contrast", &[[0, 0, 0], [255, 255, 255], [255, 255, 0], [0, 255, 255]]),
+            // Okabe-Ito colors, which stay distinguishable with the common forms of color blindness
+            Self::new("colorblind", &[[0, 0, 0], [230, 159, 0], [86, 180, 233], [0, 158, 115]]),
+        ];
+
+        palettes.into_iter()
+            .map(|palette| palette.expect("built-in palettes have at least two colors"))
+            .collect()
+    }
+
+    /// Gets the palette's name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the palette's colors indexed by pixel value
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
+    /// Gets the background color
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    /// Gets the color for a pixel value. Values past the end of the palette use its last color
+    pub fn color(&self, pixel: u8) -> Rgb {
+        self.colors[(pixel as usize).min(self.colors.len() - 1)]
+    }
+
+    /// Gets the color for a brightness level, as produced by
+    /// [`Phosphor`](crate::persistence::Phosphor). The color ramps from the background through the
+    /// fade colors up to the lit color.
+    pub fn color_for_brightness(&self, brightness: u8) -> Rgb {
+        self.brightness_colors[brightness as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::builtin().remove(0)
+    }
+}
+
+/// Builds the color for each brightness level, ramping from the background through the fade colors
+/// up to the lit color
+fn brightness_colors(colors: &[Rgb], fade: &[Rgb]) -> [Rgb; BRIGHTNESS_LEVELS] {
+    let ramp: Vec<Rgb> = core::iter::once(colors[0])
+        .chain(fade.iter().copied())
+        .chain(core::iter::once(colors[1]))
+        .collect();
+
+    core::array::from_fn(|brightness| {
+        let position = brightness as f32 / MAX_BRIGHTNESS as f32 * (ramp.len() - 1) as f32;
+        let segment = (position as usize).min(ramp.len() - 2);
+        let t = position - segment as f32;
+        let (from, to) = (ramp[segment], ramp[segment + 1]);
+
+        // Adding a half before truncating rounds, as colors are never negative
+        [0, 1, 2].map(|i| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * t + 0.5) as u8)
+    })
+}
+
+/// Parses a 6 digit hexadecimal color, optionally prefixed with `#`
+fn parse_color(color: &str) -> Result<Rgb, ParsePaletteError> {
+    let invalid = || ParsePaletteError::InvalidColor(color.to_string());
+    let hex = color.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_get_builtin_palettes_by_name() {
+        for name in ["classic", "amber", "lcd", "high-contrast", "colorblind"] {
+            assert_eq!(name, Palette::named(name).unwrap().name());
+        }
+
+        assert_eq!(Err(ParsePaletteError::UnknownName("mauve".to_string())), Palette::named("mauve"));
+    }
+
+    #[test]
+    fn default_palette_is_classic() {
+        assert_eq!("classic", Palette::default().name());
+    }
+
+    #[test]
+    fn can_parse_custom_palette() {
+        let palette = Palette::parse_custom("000000,#33FF66, 102030").unwrap();
+
+        assert_eq!([[0, 0, 0], [0x33, 0xFF, 0x66], [0x10, 0x20, 0x30]], palette.colors());
+        assert_eq!("custom", palette.name());
+    }
+
+    #[test]
+    fn parse_custom_rejects_invalid_colors() {
+        assert_eq!(Err(ParsePaletteError::InvalidColor("12345".to_string())), Palette::parse_custom("000000,12345"));
+        assert_eq!(Err(ParsePaletteError::InvalidColor("GGGGGG".to_string())), Palette::parse_custom("GGGGGG,000000"));
+        assert_eq!(Err(ParsePaletteError::TooFewColors), Palette::parse_custom("000000"));
+    }
+
+    #[test]
+    fn color_clamps_to_last_color() {
+        let palette = Palette::parse_custom("000000,ffffff,ff0000").unwrap();
+
+        assert_eq!([0, 0, 0], palette.color(0));
+        assert_eq!([255, 255, 255], palette.color(1));
+        assert_eq!([255, 0, 0], palette.color(2));
+        assert_eq!([255, 0, 0], palette.color(3));
+    }
+
+    #[test]
+    fn color_for_brightness_blends_background_and_lit_color() {
+        let palette = Palette::parse_custom("000000,c8c8c8").unwrap();
+
+        assert_eq!([0, 0, 0], palette.color_for_brightness(0));
+        assert_eq!([100, 100, 100], palette.color_for_brightness(MAX_BRIGHTNESS.div_ceil(2)));
+        assert_eq!([200, 200, 200], palette.color_for_brightness(MAX_BRIGHTNESS));
+    }
+
+    #[test]
+    fn color_for_brightness_passes_through_fade_colors() {
+        let palette = Palette::parse_custom("000000,ffffff").unwrap().with_fade(&[[255, 0, 0], [0, 0, 255]]);
+
+        assert_eq!([0, 0, 0], palette.color_for_brightness(0));
+        assert_eq!([255, 0, 0], palette.color_for_brightness(MAX_BRIGHTNESS / 3));
+        assert_eq!([0, 0, 255], palette.color_for_brightness(MAX_BRIGHTNESS / 3 * 2));
+        assert_eq!([255, 255, 255], palette.color_for_brightness(MAX_BRIGHTNESS));
+    }
+}
@@ -0,0 +1,168 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The brightness of a fully lit pixel
+pub const MAX_BRIGHTNESS: u8 = 255;
+
+/// Specifies how pixels fade when they are turned off, to reduce the flicker caused by sprites
+/// being erased and redrawn
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PersistenceMode {
+    /// Pixels turn off instantly, showing the raw frame buffer
+    Off,
+    /// Pixels fade out linearly over the given number of frames after they're turned off, like
+    /// the phosphor on a CRT
+    Decay {
+        /// The number of frames it takes a pixel to fade out completely
+        frames: u8,
+    },
+    /// Pixels are lit if they were lit in either of the last two frames
+    Blend,
+}
+
+/// Turns frame buffers into per pixel brightness levels according to a [`PersistenceMode`]
+#[derive(Debug)]
+pub struct Phosphor {
+    /// The mode used to compute brightness levels
+    mode: PersistenceMode,
+    /// The brightness of each pixel as of the last update
+    brightness: Vec<u8>,
+    /// The frame buffer passed to the previous update
+    previous_frame: Vec<u8>,
+}
+
+impl Phosphor {
+    /// Creates a new Phosphor for a display with pixel_count pixels, with every pixel off
+    pub fn new(mode: PersistenceMode, pixel_count: usize) -> Self {
+        Self {
+            mode,
+            brightness: vec![0; pixel_count],
+            previous_frame: vec![0; pixel_count],
+        }
+    }
+
+    /// Gets the persistence mode
+    pub fn mode(&self) -> PersistenceMode {
+        self.mode
+    }
+
+    /// Sets the persistence mode. Pixels keep their current brightness
+    pub fn set_mode(&mut self, mode: PersistenceMode) {
+        self.mode = mode;
+    }
+
+    /// Gets the brightness of each pixel as of the last update
+    pub fn brightness(&self) -> &[u8] {
+        &self.brightness
+    }
+
+    /// Turns every pixel off and forgets the previous frame, e.g. when the emulator is reset so the
+    /// old image doesn't fade over the new one
+    pub fn clear(&mut self) {
+        self.brightness.fill(0);
+        self.previous_frame.fill(0);
+    }
+
+    /// Advances the phosphor by one frame and returns the resulting brightness of each pixel,
+    /// from 0 for off to [`MAX_BRIGHTNESS`] for fully lit
+    pub fn update(&mut self, frame_buffer: &[u8]) -> &[u8] {
+        match self.mode {
+            PersistenceMode::Off => {
+                for (brightness, pixel) in self.brightness.iter_mut().zip(frame_buffer) {
+                    *brightness = if *pixel != 0 { MAX_BRIGHTNESS } else { 0 };
+                }
+            }
+            PersistenceMode::Decay { frames } => {
+                let step = MAX_BRIGHTNESS.div_ceil(frames.max(1));
+                for (brightness, pixel) in self.brightness.iter_mut().zip(frame_buffer) {
+                    *brightness = if *pixel != 0 { MAX_BRIGHTNESS } else { brightness.saturating_sub(step) };
+                }
+            }
+            PersistenceMode::Blend => {
+                for ((brightness, pixel), previous) in self.brightness.iter_mut().zip(frame_buffer).zip(&self.previous_frame) {
+                    *brightness = if *pixel != 0 || *previous != 0 { MAX_BRIGHTNESS } else { 0 };
+                }
+            }
+        }
+
+        self.previous_frame.copy_from_slice(frame_buffer);
+
+        &self.brightness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_mode_shows_raw_frame_buffer() {
+        let mut phosphor = Phosphor::new(PersistenceMode::Off, 4);
+
+        assert_eq!([255, 0, 255, 0], phosphor.update(&[1, 0, 1, 0]));
+        assert_eq!([0, 255, 0, 0], phosphor.update(&[0, 1, 0, 0]));
+    }
+
+    #[test]
+    fn decay_mode_fades_pixels_over_frames() {
+        let mut phosphor = Phosphor::new(PersistenceMode::Decay { frames: 3 }, 2);
+
+        assert_eq!([255, 0], phosphor.update(&[1, 0]));
+        assert_eq!([170, 255], phosphor.update(&[0, 1]));
+        assert_eq!([85, 170], phosphor.update(&[0, 0]));
+        assert_eq!([0, 85], phosphor.update(&[0, 0]));
+        assert_eq!([0, 0], phosphor.update(&[0, 0]));
+    }
+
+    #[test]
+    fn decay_mode_relights_fading_pixels() {
+        let mut phosphor = Phosphor::new(PersistenceMode::Decay { frames: 4 }, 1);
+
+        phosphor.update(&[1]);
+        phosphor.update(&[0]);
+        assert_eq!([255], phosphor.update(&[1]));
+    }
+
+    #[test]
+    fn decay_mode_with_zero_frames_turns_off_instantly() {
+        let mut phosphor = Phosphor::new(PersistenceMode::Decay { frames: 0 }, 1);
+
+        phosphor.update(&[1]);
+        assert_eq!([0], phosphor.update(&[0]));
+    }
+
+    #[test]
+    fn blend_mode_ors_last_two_frames() {
+        let mut phosphor = Phosphor::new(PersistenceMode::Blend, 3);
+
+        assert_eq!([255, 0, 0], phosphor.update(&[1, 0, 0]));
+        assert_eq!([255, 255, 0], phosphor.update(&[0, 1, 0]));
+        assert_eq!([0, 255, 0], phosphor.update(&[0, 0, 0]));
+        assert_eq!([0, 0, 0], phosphor.update(&[0, 0, 0]));
+    }
+
+    #[test]
+    fn clearing_turns_off_fading_and_blended_pixels() {
+        let mut decay = Phosphor::new(PersistenceMode::Decay { frames: 3 }, 1);
+        decay.update(&[1]);
+        decay.clear();
+        assert_eq!([0], decay.brightness());
+        assert_eq!([0], decay.update(&[0]));
+
+        let mut blend = Phosphor::new(PersistenceMode::Blend, 1);
+        blend.update(&[1]);
+        blend.clear();
+        assert_eq!([0], blend.update(&[0]));
+    }
+
+    #[test]
+    fn switching_modes_keeps_brightness() {
+        let mut phosphor = Phosphor::new(PersistenceMode::Off, 1);
+        phosphor.update(&[1]);
+
+        phosphor.set_mode(PersistenceMode::Decay { frames: 2 });
+
+        assert_eq!(PersistenceMode::Decay { frames: 2 }, phosphor.mode());
+        assert_eq!([127], phosphor.update(&[0]));
+    }
+}
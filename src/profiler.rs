@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use crate::instruction::{disassemble, DisassembledInstruction, Instruction};
+use crate::{Chip8, Chip8Rng, ExecutionObserver, MEMORY_SIZE};
+
+/// The name of the code outside any subroutine in folded stacks
+const ROOT_FRAME: &str = "main";
+
+/// The number of opcode classes, one per value of an opcode's highest nibble
+const OPCODE_CLASSES: usize = 16;
+
+/// Counts executed instructions per address and per opcode class, and attributes them to
+/// subroutines by following 2NNN calls and 00EE returns, as an [`ExecutionObserver`].
+/// Every instruction counts as one sample, attributed to the subroutine executing it, so a call
+/// counts towards the caller and a return towards the subroutine returning.
+pub struct Profiler {
+    /// Executions per address
+    address_counts: Vec<u64>,
+    /// Executions per opcode class
+    class_counts: [u64; OPCODE_CLASSES],
+    /// The entry addresses of the subroutines currently being executed, outermost first
+    call_stack: Vec<u16>,
+    /// Executions per call stack
+    stack_counts: HashMap<Vec<u16>, u64>,
+    /// The total number of executions
+    total: u64,
+}
+
+impl Profiler {
+    /// Creates a profiler with no samples
+    pub fn new() -> Self {
+        Self {
+            address_counts: vec![0; MEMORY_SIZE],
+            class_counts: [0; OPCODE_CLASSES],
+            call_stack: Vec::new(),
+            stack_counts: HashMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Gets the total number of instructions executed
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Gets the number of times the instruction at an address was executed
+    pub fn address_count(&self, address: u16) -> u64 {
+        self.address_counts.get(address as usize).copied().unwrap_or(0)
+    }
+
+    /// Gets the number of instructions executed whose opcode's highest nibble is class
+    pub fn class_count(&self, class: u8) -> u64 {
+        self.class_counts[class as usize & 0xF]
+    }
+
+    /// Gets the addresses that were executed along with their counts, most executed first, ties
+    /// in address order
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        let mut hot_spots: Vec<(u16, u64)> = self.address_counts.iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(address, count)| (address as u16, *count))
+            .collect();
+        hot_spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot_spots
+    }
+
+    /// Writes the samples in the folded stack format read by flame graph tools such as
+    /// flamegraph.pl and inferno, one `main;sub_2A4;sub_300 123` line per call stack
+    pub fn write_folded<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut stacks: Vec<(String, u64)> = self.stack_counts.iter()
+            .map(|(stack, count)| {
+                let frames: Vec<String> = stack.iter().map(|address| subroutine_name(*address)).collect();
+                let folded = std::iter::once(ROOT_FRAME.to_string()).chain(frames).collect::<Vec<_>>().join(";");
+                (folded, *count)
+            })
+            .collect();
+        stacks.sort();
+
+        for (stack, count) in stacks {
+            writeln!(writer, "{stack} {count}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a report of the most executed instructions, joined with their disassembly from
+    /// memory, followed by the executions per opcode class
+    pub fn write_report<W: Write>(&self, writer: &mut W, memory: &[u8], limit: usize) -> io::Result<()> {
+        writeln!(writer, "{} instructions executed", self.total)?;
+        writeln!(writer)?;
+        writeln!(writer, "{:>12}  {:>6}  address  opcode  instruction", "count", "%")?;
+
+        for (address, count) in self.hot_spots().into_iter().take(limit) {
+            let Some(line) = disassemble(memory, address, 1).first().copied() else { continue };
+            writeln!(writer, "{count:>12}  {:>6.2}  {address:03X}      {:04X}    {}",
+                     self.percentage(count),
+                     line.opcode,
+                     line.instruction)?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "{:>12}  {:>6}  class", "count", "%")?;
+        for (class, count) in self.class_counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+            writeln!(writer, "{count:>12}  {:>6.2}  {class:X}NNN", self.percentage(*count))?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets a count as a percentage of all executions
+    fn percentage(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.total.max(1) as f64
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExecutionObserver for Profiler {
+    fn before_instruction<R: Chip8Rng>(&mut self, _chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        self.total += 1;
+        self.address_counts[instruction.address as usize] += 1;
+        self.class_counts[(instruction.opcode >> 12) as usize] += 1;
+
+        match self.stack_counts.get_mut(&self.call_stack) {
+            Some(count) => *count += 1,
+            None => {
+                self.stack_counts.insert(self.call_stack.clone(), 1);
+            }
+        }
+
+        match instruction.instruction {
+            Instruction::Call { nnn } => self.call_stack.push(nnn),
+            Instruction::Return => {
+                self.call_stack.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Names a subroutine by its entry address
+fn subroutine_name(address: u16) -> String {
+    format!("sub_{address:03X}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::EmulatorType;
+    use super::*;
+
+    /// Calls 0x208 twice, which calls 0x20C, then loops forever
+    const PROGRAM: [u8; 16] = [
+        0x22, 0x08, 0x22, 0x08, 0x12, 0x04, 0x00, 0x00,
+        0x70, 0x01, 0x22, 0x0C, 0x00, 0xEE, 0x00, 0x00,
+    ];
+
+    fn profile(instructions: usize) -> (Profiler, Chip8<TestRng>) {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&PROGRAM);
+        let mut profiler = Profiler::new();
+        for _ in 0..instructions {
+            chip8.execute_next_instruction_with(&mut profiler);
+        }
+        (profiler, chip8)
+    }
+
+    #[test]
+    fn counts_executions_per_address_and_class() {
+        let (profiler, _) = profile(12);
+
+        assert_eq!(12, profiler.total());
+        assert_eq!(2, profiler.address_count(0x208));
+        assert_eq!(4, profiler.address_count(0x20C));
+        assert_eq!(2, profiler.address_count(0x204));
+        assert_eq!(4, profiler.class_count(0x0));
+        assert_eq!(4, profiler.class_count(0x2));
+        assert_eq!((0x20C, 4), profiler.hot_spots()[0]);
+    }
+
+    #[test]
+    fn writes_folded_stacks() {
+        let (profiler, _) = profile(12);
+        let mut folded = Vec::new();
+
+        profiler.write_folded(&mut folded).unwrap();
+
+        assert_eq!("main 4\nmain;sub_208 6\nmain;sub_208;sub_20C 2\n", String::from_utf8(folded).unwrap());
+    }
+
+    #[test]
+    fn writes_hot_spot_reports() {
+        let (profiler, chip8) = profile(12);
+        let mut report = Vec::new();
+
+        profiler.write_report(&mut report, chip8.ram(), 2).unwrap();
+
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!("12 instructions executed", lines[0]);
+        assert_eq!("           4   33.33  20C      00EE    RET", lines[3]);
+        assert!(lines[4].ends_with("204      1204    JP 0x204"));
+        assert!(report.contains("           4   33.33  2NNN"));
+    }
+}
@@ -0,0 +1,583 @@
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::analysis::{Analysis, BasicBlock};
+use crate::instruction::Instruction;
+use crate::{EmulatorType, FONT_START_ADDRESS, PROGRAM_START_ADDRESS};
+
+/// The source files of a crate generated from a ROM
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RecompiledCrate {
+    /// The contents of `Cargo.toml`
+    pub manifest: String,
+    /// The contents of `src/lib.rs`, with a function per basic block and a `run_frame` that
+    /// dispatches between them
+    pub lib: String,
+    /// The contents of `src/main.rs`, which runs the recompiled code alongside the interpreter and
+    /// checks their frame buffers match
+    pub main: String,
+}
+
+impl RecompiledCrate {
+    /// Writes the crate's files into a directory, creating it if needed
+    pub fn write_to(&self, directory: &Path) -> io::Result<()> {
+        fs::create_dir_all(directory.join("src"))?;
+        fs::write(directory.join("Cargo.toml"), &self.manifest)?;
+        fs::write(directory.join("src").join("lib.rs"), &self.lib)?;
+        fs::write(directory.join("src").join("main.rs"), &self.main)
+    }
+}
+
+/// Names a crate can't have, because they're Rust keywords or the generated crate's own
+/// dependencies
+const RESERVED_NAMES: [&str; 54] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield", "chip_8_emulator", "rand",
+];
+
+/// The error returned when a crate name can't be used in the generated code
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct InvalidCrateName(pub String);
+
+impl fmt::Display for InvalidCrateName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid crate name {:?}, expected letters, digits, - and _ not starting with a digit and not a Rust keyword", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCrateName {}
+
+/// Checks a crate name is a Rust identifier once its `-`s become `_`s, as its library is named
+fn validate_name(name: &str) -> Result<String, InvalidCrateName> {
+    let identifier = name.replace('-', "_");
+    let mut chars = identifier.chars();
+    let valid = chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+        && identifier != "_"
+        && !RESERVED_NAMES.contains(&identifier.as_str());
+
+    if valid { Ok(identifier) } else { Err(InvalidCrateName(name.to_string())) }
+}
+
+/// Translates a ROM's reachable code into a Rust crate that runs it on a [`crate::Chip8`]. Each
+/// basic block becomes a function of straight-line Rust that carries out its instructions on the
+/// interpreter's state, with their operands and the emulator type's quirks folded in. Program
+/// counters that don't start a block, such as targets of computed jumps, and blocks whose bytes
+/// have been overwritten fall back to the interpreter. `dependency` is the TOML for the
+/// `chip-8-emulator` dependency, which needs its `rand` feature, e.g.
+/// `{ path = "../chip-8-emulator", default-features = false, features = ["rand"] }`. Fails if
+/// `name` isn't usable as a crate name
+pub fn recompile(program: &[u8], name: &str, emulator_type: EmulatorType, dependency: &str) -> Result<RecompiledCrate, InvalidCrateName> {
+    let identifier = validate_name(name)?;
+    let analysis = Analysis::analyze(program);
+
+    Ok(RecompiledCrate {
+        manifest: manifest(name, dependency),
+        lib: lib(program, &analysis, emulator_type),
+        main: MAIN.replace("recompiled::", &format!("{identifier}::")),
+    })
+}
+
+/// Generates the manifest of the recompiled crate. It's its own workspace so it builds wherever
+/// it's written
+fn manifest(name: &str, dependency: &str) -> String {
+    format!("\
+[package]
+name = \"{name}\"
+version = \"0.1.0\"
+edition = \"2021\"
+
+[dependencies]
+chip-8-emulator = {dependency}
+rand = \"0.9\"
+
+[profile.release]
+debug = false
+
+[workspace]
+")
+}
+
+/// Generates the library with a function per basic block
+fn lib(program: &[u8], analysis: &Analysis, emulator_type: EmulatorType) -> String {
+    let mut source = String::new();
+    source.push_str(LIB_HEADER);
+
+    writeln!(source, "/// The emulator type the ROM was recompiled for").unwrap();
+    writeln!(source, "pub const EMULATOR_TYPE: EmulatorType = EmulatorType::{emulator_type:?};").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "/// The original ROM, loaded so data reads and the interpreter fallback see it").unwrap();
+    writeln!(source, "pub const PROGRAM: [u8; {}] = {};", program.len(), byte_array(program)).unwrap();
+    writeln!(source).unwrap();
+
+    writeln!(source, "/// Runs the block starting at the program counter, or interprets one instruction if no block").unwrap();
+    writeln!(source, "/// starts there. Returns false once the frame is over").unwrap();
+    writeln!(source, "fn run_block<R: Chip8Rng, M: Meter<R>>(chip8: &mut Chip8<R>, meter: &mut M) -> bool {{").unwrap();
+    writeln!(source, "    match chip8.program_counter() {{").unwrap();
+    for block in analysis.blocks() {
+        writeln!(source, "        0x{0:03X} => block_{0:03x}(chip8, meter),", block.start()).unwrap();
+    }
+    writeln!(source, "        _ => interpret(chip8, meter),").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+
+    for block in analysis.blocks() {
+        writeln!(source).unwrap();
+        write_block(&mut source, program, block, emulator_type);
+    }
+
+    source
+}
+
+/// Generates the function for a basic block. The program counter is only written when leaving
+/// the block. The block's bytes are checked to still be the ones that were recompiled before it
+/// runs, and again after any instruction that writes to memory
+fn write_block(source: &mut String, program: &[u8], block: &BasicBlock, emulator_type: EmulatorType) {
+    let start = block.start();
+    let end = block.end();
+    writeln!(source, "/// {start:03X}-{:03X}", end - 1).unwrap();
+    writeln!(source, "fn block_{start:03x}<R: Chip8Rng, M: Meter<R>>(chip8: &mut Chip8<R>, meter: &mut M) -> bool {{").unwrap();
+    writeln!(source, "    if chip8.ram()[0x{start:03X}..0x{end:03X}] != {} {{", byte_array(&block_bytes(program, start, end))).unwrap();
+    writeln!(source, "        return interpret(chip8, meter);").unwrap();
+    writeln!(source, "    }}").unwrap();
+
+    for instruction in &block.instructions {
+        let address = instruction.address;
+        let next = address + 2;
+        writeln!(source, "    // {address:03X}  {}", instruction.instruction).unwrap();
+
+        // A draw waiting for the display stays put until the next frame
+        let wait = if let Instruction::Draw { .. } = instruction.instruction { "chip8.draw_would_wait() || " } else { "" };
+        writeln!(source, "    if {wait}!meter.admit(chip8, Instruction::{:?}) {{", instruction.instruction).unwrap();
+        writeln!(source, "        return leave(chip8, 0x{address:03X}, false);").unwrap();
+        writeln!(source, "    }}").unwrap();
+
+        for line in instruction_source(instruction.instruction, address, emulator_type) {
+            writeln!(source, "    {line}").unwrap();
+        }
+
+        if let Instruction::StoreRegisters { .. } | Instruction::StoreDecimalDigits { .. } = instruction.instruction {
+            if next < end {
+                writeln!(source, "    if chip8.ram()[0x{next:03X}..0x{end:03X}] != {} {{", byte_array(&block_bytes(program, next, end))).unwrap();
+                writeln!(source, "        return leave(chip8, 0x{next:03X}, true);").unwrap();
+                writeln!(source, "    }}").unwrap();
+            }
+        }
+    }
+
+    let last = block.instructions[block.instructions.len() - 1].instruction;
+    if !transfers_control(last) {
+        writeln!(source, "    *chip8.program_counter_mut() = 0x{end:03X};").unwrap();
+    }
+    writeln!(source, "    true").unwrap();
+    writeln!(source, "}}").unwrap();
+}
+
+/// Returns whether an instruction sets the program counter, which only happens at the end of a
+/// block
+fn transfers_control(instruction: Instruction) -> bool {
+    matches!(instruction, Instruction::Return | Instruction::Jump { .. } | Instruction::Call { .. } | Instruction::JumpWithOffset { .. })
+        || instruction.is_skip()
+}
+
+/// Generates the statements that carry out an instruction at an address on `chip8`, matching the
+/// interpreter exactly, including the order flags and results are written in when VF is the
+/// destination
+fn instruction_source(instruction: Instruction, address: u16, emulator_type: EmulatorType) -> Vec<String> {
+    let next = address + 2;
+    let skip = |condition: String| vec![
+        format!("let skip = {condition};"),
+        format!("*chip8.program_counter_mut() = if skip {{ 0x{:03X} }} else {{ 0x{next:03X} }};", next + 2),
+    ];
+    let registers = |lines: &[String]| {
+        let mut source = vec!["let v = chip8.variable_registers_mut();".to_string()];
+        source.extend_from_slice(lines);
+        source
+    };
+    let vip = emulator_type == EmulatorType::CosmacVip;
+    // The COSMAC VIP shifts VY into VX, later interpreters shift VX in place
+    let shift = |x: u8, y: u8, flag: String, shift: String| {
+        let copy = if vip { vec![format!("v[{x}] = v[{y}];")] } else { Vec::new() };
+        registers(&[copy, vec![flag, shift]].concat())
+    };
+
+    match instruction {
+        Instruction::ClearScreen => vec!["chip8.frame_buffer_mut().fill(0);".to_string()],
+        Instruction::Return => vec![
+            "let address = chip8.pop_stack();".to_string(),
+            "*chip8.program_counter_mut() = address;".to_string(),
+        ],
+        Instruction::Jump { nnn } => vec![format!("*chip8.program_counter_mut() = 0x{nnn:03X};")],
+        Instruction::Call { nnn } => vec![
+            format!("chip8.push_stack(0x{next:03X});"),
+            format!("*chip8.program_counter_mut() = 0x{nnn:03X};"),
+        ],
+        Instruction::SkipIfVxEqualsNn { x, nn } => skip(format!("chip8.variable_registers()[{x}] == 0x{nn:02X}")),
+        Instruction::SkipIfVxNotEqualsNn { x, nn } => skip(format!("chip8.variable_registers()[{x}] != 0x{nn:02X}")),
+        Instruction::SkipIfVxEqualsVy { x, y } => skip(format!("chip8.variable_registers()[{x}] == chip8.variable_registers()[{y}]")),
+        Instruction::SkipIfVxNotEqualsVy { x, y } => skip(format!("chip8.variable_registers()[{x}] != chip8.variable_registers()[{y}]")),
+        Instruction::SkipIfKeyDown { x } => skip(format!("chip8.key_state(chip_8_emulator::Chip8Key::ALL[chip8.variable_registers()[{x}] as usize]) == chip_8_emulator::KeyState::Down")),
+        Instruction::SkipIfKeyUp { x } => skip(format!("chip8.key_state(chip_8_emulator::Chip8Key::ALL[chip8.variable_registers()[{x}] as usize]) == chip_8_emulator::KeyState::Up")),
+        Instruction::SetVx { x, nn } => vec![format!("chip8.variable_registers_mut()[{x}] = 0x{nn:02X};")],
+        Instruction::AddToVx { x, nn } => registers(&[format!("v[{x}] = v[{x}].wrapping_add(0x{nn:02X});")]),
+        Instruction::SetVxToVy { x, y } => registers(&[format!("v[{x}] = v[{y}];")]),
+        Instruction::OrVxWithVy { x, y } => registers(&[format!("v[{x}] |= v[{y}];")]),
+        Instruction::AndVxWithVy { x, y } => registers(&[format!("v[{x}] &= v[{y}];")]),
+        Instruction::XorVxWithVy { x, y } => registers(&[format!("v[{x}] ^= v[{y}];")]),
+        Instruction::AddVyToVx { x, y } => registers(&[
+            format!("let (sum, carry) = v[{x}].overflowing_add(v[{y}]);"),
+            "v[0xF] = carry as u8;".to_string(),
+            format!("v[{x}] = sum;"),
+        ]),
+        Instruction::SubtractVyFromVx { x, y } => registers(&[
+            format!("let (difference, borrow) = v[{x}].overflowing_sub(v[{y}]);"),
+            "v[0xF] = !borrow as u8;".to_string(),
+            format!("v[{x}] = difference;"),
+        ]),
+        Instruction::SubtractVxFromVyIntoVx { x, y } => registers(&[
+            format!("let (difference, borrow) = v[{y}].overflowing_sub(v[{x}]);"),
+            "v[0xF] = !borrow as u8;".to_string(),
+            format!("v[{x}] = difference;"),
+        ]),
+        Instruction::ShiftVxRight { x, y } => shift(x, y, format!("v[0xF] = v[{x}] & 0x01;"), format!("v[{x}] >>= 1;")),
+        Instruction::ShiftVxLeft { x, y } => shift(x, y, format!("v[0xF] = v[{x}] >> 7;"), format!("v[{x}] <<= 1;")),
+        Instruction::SetIndex { nnn } => vec![format!("*chip8.index_register_mut() = 0x{nnn:03X};")],
+        Instruction::JumpWithOffset { nnn } => {
+            let register = if vip { 0 } else { nnn >> 8 };
+            vec![
+                format!("let offset = chip8.variable_registers()[{register}] as u16;"),
+                format!("*chip8.program_counter_mut() = 0x{nnn:03X}u16.wrapping_add(offset);"),
+            ]
+        }
+        Instruction::Random { x, nn } => vec![
+            "let random = chip8.rng_mut().random_byte();".to_string(),
+            format!("chip8.variable_registers_mut()[{x}] = random & 0x{nn:02X};"),
+        ],
+        Instruction::Draw { x, y, n } => vec![format!("chip8.draw_sprite({x}, {y}, {n});")],
+        Instruction::SetVxToDelayTimer { x } => vec![
+            "let delay = chip8.delay_timer();".to_string(),
+            format!("chip8.variable_registers_mut()[{x}] = delay;"),
+        ],
+        // Until a key is released the instruction runs again, using up the frame
+        Instruction::WaitForKey { x } => vec![
+            "match chip8.poll_key_release() {".to_string(),
+            format!("    Some(key) => chip8.variable_registers_mut()[{x}] = key,"),
+            format!("    None => return leave(chip8, 0x{address:03X}, true),"),
+            "}".to_string(),
+        ],
+        Instruction::SetDelayTimer { x } => vec![
+            format!("let value = chip8.variable_registers()[{x}];"),
+            "chip8.set_register(chip_8_emulator::Register::DelayTimer, value as u16);".to_string(),
+        ],
+        Instruction::SetSoundTimer { x } => vec![
+            format!("let value = chip8.variable_registers()[{x}];"),
+            "chip8.set_register(chip_8_emulator::Register::SoundTimer, value as u16);".to_string(),
+        ],
+        Instruction::AddVxToIndex { x } => vec![
+            format!("let value = chip8.variable_registers()[{x}] as u16;"),
+            "let index = chip8.index_register_mut();".to_string(),
+            "*index = index.wrapping_add(value);".to_string(),
+        ],
+        Instruction::SetIndexToFontCharacter { x } => vec![
+            format!("let character = (chip8.variable_registers()[{x}] & 0x0F) as u16;"),
+            format!("*chip8.index_register_mut() = 0x{FONT_START_ADDRESS:03X} + character * 5;"),
+        ],
+        Instruction::StoreDecimalDigits { x } => vec![
+            format!("let value = chip8.variable_registers()[{x}];"),
+            "let index = chip8.index_register() as usize;".to_string(),
+            "chip8.ram_mut()[index..index + 3].copy_from_slice(&[value / 100, value / 10 % 10, value % 10]);".to_string(),
+        ],
+        Instruction::StoreRegisters { x } => {
+            let count = x as usize + 1;
+            let mut source = vec![
+                "let index = chip8.index_register() as usize;".to_string(),
+                "let registers = *chip8.variable_registers();".to_string(),
+                format!("chip8.ram_mut()[index..index + {count}].copy_from_slice(&registers[..{count}]);"),
+            ];
+            if vip {
+                source.push(format!("*chip8.index_register_mut() += {count};"));
+            }
+            source
+        }
+        Instruction::LoadRegisters { x } => {
+            let count = x as usize + 1;
+            let mut source = vec![
+                "let index = chip8.index_register() as usize;".to_string(),
+                format!("let mut registers = [0; {count}];"),
+                format!("registers.copy_from_slice(&chip8.ram()[index..index + {count}]);"),
+                format!("chip8.variable_registers_mut()[..{count}].copy_from_slice(&registers);"),
+            ];
+            if vip {
+                source.push(format!("*chip8.index_register_mut() += {count};"));
+            }
+            source
+        }
+        // The interpreter reports invalid opcodes
+        Instruction::Invalid { .. } => vec![
+            format!("*chip8.program_counter_mut() = 0x{address:03X};"),
+            "chip8.execute_next_instruction();".to_string(),
+        ],
+    }
+}
+/// Gets the bytes of the program loaded between two addresses. Bytes outside the program are
+/// taken to be zero, so blocks there fail their check and are interpreted
+fn block_bytes(program: &[u8], start: u16, end: u16) -> Vec<u8> {
+    (start..end)
+        .map(|address| {
+            address.checked_sub(PROGRAM_START_ADDRESS)
+                .and_then(|offset| program.get(offset as usize).copied())
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Formats bytes as a Rust array expression
+fn byte_array(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{byte:02X}")).collect();
+    format!("[{}]", bytes.join(", "))
+}
+
+/// The start of every recompiled library, with the parts that don't depend on the ROM
+const LIB_HEADER: &str = "\
+//! A CHIP-8 ROM recompiled to Rust. Generated by chip-8-recompile, don't edit.
+
+use chip_8_emulator::instruction::Instruction;
+use chip_8_emulator::timing::VipTiming;
+use chip_8_emulator::{Chip8, Chip8Rng, EmulatorType};
+
+/// Creates an interpreter with the ROM loaded, ready for [`run_frame`] or [`run_vip_frame`]. With
+/// display wait, a draw waits for the next frame if there's already been one, see
+/// [`Chip8::set_display_wait`]
+pub fn new_chip8<R: Chip8Rng>(rng: R, display_wait: bool) -> Chip8<R> {
+    let mut chip8 = Chip8::new(EMULATOR_TYPE, rng);
+    chip8.load_program(&PROGRAM);
+    chip8.set_display_wait(display_wait);
+    chip8
+}
+
+/// Runs one 60 Hz frame like [`Chip8::run_frame`] using the recompiled code
+pub fn run_frame<R: Chip8Rng>(chip8: &mut Chip8<R>, instructions_per_frame: usize) {
+    let mut instructions_left = instructions_per_frame;
+    while run_block(chip8, &mut instructions_left) {}
+
+    chip8.decrement_timers();
+}
+
+/// Runs one 60 Hz frame like [`VipTiming::run_frame`] using the recompiled code
+pub fn run_vip_frame<R: Chip8Rng>(chip8: &mut Chip8<R>, timing: &mut VipTiming) {
+    while run_block(chip8, timing) {}
+
+    timing.end_frame(chip8);
+}
+
+/// Decides whether each instruction still fits in the current frame
+trait Meter<R: Chip8Rng> {
+    /// Takes an instruction about to run from the frame. Returns false if the frame is over
+    fn admit(&mut self, chip8: &Chip8<R>, instruction: Instruction) -> bool;
+}
+
+/// Frames of a number of instructions, counting down the instructions left
+impl<R: Chip8Rng> Meter<R> for usize {
+    fn admit(&mut self, _chip8: &Chip8<R>, _instruction: Instruction) -> bool {
+        if *self == 0 {
+            return false;
+        }
+
+        *self -= 1;
+        true
+    }
+}
+
+/// Frames timed like the COSMAC VIP
+impl<R: Chip8Rng> Meter<R> for VipTiming {
+    fn admit(&mut self, chip8: &Chip8<R>, instruction: Instruction) -> bool {
+        self.try_charge(chip8, instruction)
+    }
+}
+
+/// Interprets the instruction at the program counter. Returns false once the frame is over
+fn interpret<R: Chip8Rng, M: Meter<R>>(chip8: &mut Chip8<R>, meter: &mut M) -> bool {
+    if chip8.is_waiting_for_display() || !meter.admit(chip8, chip8.next_instruction()) {
+        return false;
+    }
+
+    chip8.execute_next_instruction();
+    true
+}
+
+/// Leaves a block early with the program counter at the next instruction to run, returning
+/// whether to keep running
+fn leave<R: Chip8Rng>(chip8: &mut Chip8<R>, program_counter: u16, keep_running: bool) -> bool {
+    *chip8.program_counter_mut() = program_counter;
+    keep_running
+}
+
+";
+
+/// The recompiled crate's binary, which checks the recompiled code against the interpreter
+const MAIN: &str = "\
+//! Runs the recompiled ROM alongside the interpreter with identically seeded random number
+//! generators and checks their frame buffers and registers match after every frame.
+//!
+//! usage: [--display-wait] [FRAMES] [INSTRUCTIONS PER FRAME|vip] [SEED]
+
+use std::time::{Duration, Instant};
+use chip_8_emulator::timing::VipTiming;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let display_wait = args.iter().any(|arg| arg == \"--display-wait\");
+    args.retain(|arg| arg != \"--display-wait\");
+    let number = |index: usize, default: u64| args.get(index).map_or(default, |arg| arg.parse().expect(\"expected a number\"));
+    let frames = number(0, 600);
+    let vip_timing = args.get(1).is_some_and(|arg| arg == \"vip\");
+    let instructions_per_frame = if vip_timing { 0 } else { number(1, 12) as usize };
+    let seed = number(2, 0);
+
+    let mut recompiled = recompiled::new_chip8(StdRng::seed_from_u64(seed), display_wait);
+    let mut interpreted = recompiled::new_chip8(StdRng::seed_from_u64(seed), display_wait);
+    let (mut recompiled_timing, mut interpreted_timing) = (VipTiming::new(), VipTiming::new());
+    let (mut recompiled_time, mut interpreted_time) = (Duration::ZERO, Duration::ZERO);
+
+    for frame in 0..frames {
+        let start = Instant::now();
+        if vip_timing {
+            recompiled::run_vip_frame(&mut recompiled, &mut recompiled_timing);
+        } else {
+            recompiled::run_frame(&mut recompiled, instructions_per_frame);
+        }
+        recompiled_time += start.elapsed();
+
+        let start = Instant::now();
+        if vip_timing {
+            interpreted_timing.run_frame(&mut interpreted);
+        } else {
+            interpreted.run_frame(instructions_per_frame);
+        }
+        interpreted_time += start.elapsed();
+
+        if recompiled.frame_buffer() != interpreted.frame_buffer()
+            || recompiled.variable_registers() != interpreted.variable_registers()
+            || recompiled.program_counter() != interpreted.program_counter() {
+            eprintln!(\"Recompiled code and the interpreter differ after frame {frame}\");
+            std::process::exit(1);
+        }
+    }
+
+    println!(\"Frame buffers matched for {frames} frames\");
+    println!(\"Recompiled {recompiled_time:?}, interpreted {interpreted_time:?}\");
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use super::*;
+
+    /// Counts V0 up in a loop, drawing a font character each time, with a store that makes the
+    /// rest of its block need checking
+    const PROGRAM: [u8; 14] = [
+        0x60, 0x00, 0xF0, 0x29, 0xD1, 0x15, 0x70, 0x01,
+        0xA3, 0x00, 0xF0, 0x55, 0x12, 0x02,
+    ];
+
+    /// Walks across the screen calling a subroutine that puts a random number through most of the
+    /// instruction set, including flags written to VF, and draws one of its digits
+    const EXERCISE_PROGRAM: [u16; 42] = [
+        0xA300, 0x6A00, 0x6B00, 0x221C, 0x7A05, 0x3A3C, 0x1206, 0x6A00,
+        0x7B06, 0x4B1E, 0x00E0, 0x4B1E, 0x6B00, 0x1206, 0xC0FF, 0x8100,
+        0x8114, 0x8205, 0x8306, 0x840E, 0x8517, 0x8621, 0x8732, 0x8843,
+        0x9120, 0x7901, 0x5340, 0x7902, 0xA300, 0xF033, 0xF265, 0xF029,
+        0xDAB5, 0xF115, 0xF207, 0xF11E, 0xA310, 0xF955, 0xE09E, 0xF018,
+        0x8FF4, 0x00EE,
+    ];
+
+    #[test]
+    fn generates_straight_line_code_per_block() {
+        let recompiled = recompile(&PROGRAM, "recompiled", EmulatorType::CosmacVip, "{ path = \"..\" }").unwrap();
+
+        assert!(recompiled.manifest.contains("chip-8-emulator = { path = \"..\" }"));
+        assert!(recompiled.lib.contains("pub const EMULATOR_TYPE: EmulatorType = EmulatorType::CosmacVip;"));
+        assert!(recompiled.lib.contains("        0x200 => block_200(chip8, meter),\n        0x202 => block_202(chip8, meter),\n        _ => interpret(chip8, meter),\n"));
+        assert!(recompiled.lib.contains("fn block_202<R: Chip8Rng, M: Meter<R>>(chip8: &mut Chip8<R>, meter: &mut M) -> bool {\n    if chip8.ram()[0x202..0x20E] != [0xF0, 0x29, "));
+        assert!(recompiled.lib.contains("    // 204  DRW V1, V1, 5\n    if chip8.draw_would_wait() || !meter.admit(chip8, Instruction::Draw { x: 1, y: 1, n: 5 }) {\n        return leave(chip8, 0x204, false);\n    }\n    chip8.draw_sprite(1, 1, 5);\n"));
+        assert!(recompiled.lib.contains("    v[0] = v[0].wrapping_add(0x01);\n"));
+        assert!(recompiled.lib.contains("    *chip8.index_register_mut() += 1;\n    if chip8.ram()[0x20C..0x20E] != [0x12, 0x02] {\n        return leave(chip8, 0x20C, true);\n    }\n"));
+        assert!(recompiled.lib.trim_end().ends_with("    *chip8.program_counter_mut() = 0x202;\n    true\n}"));
+        assert!(!recompiled.lib.contains("execute_predecoded"));
+    }
+
+    #[test]
+    fn folds_the_emulator_types_quirks_in() {
+        // 8016 B300
+        let vip = recompile(&[0x80, 0x16, 0xB3, 0x00], "recompiled", EmulatorType::CosmacVip, "\"*\"").unwrap();
+        let chip48 = recompile(&[0x80, 0x16, 0xB3, 0x00], "recompiled", EmulatorType::Chip48, "\"*\"").unwrap();
+
+        assert!(vip.lib.contains("    v[0] = v[1];\n    v[0xF] = v[0] & 0x01;\n"));
+        assert!(!chip48.lib.contains("v[0] = v[1];"));
+        assert!(vip.lib.contains("let offset = chip8.variable_registers()[0] as u16;"));
+        assert!(chip48.lib.contains("let offset = chip8.variable_registers()[3] as u16;"));
+    }
+
+    #[test]
+    fn rejects_names_that_arent_identifiers() {
+        let main = recompile(&PROGRAM, "my-game", EmulatorType::Chip48, "\"*\"").unwrap().main;
+        assert!(main.contains("my_game::"));
+
+        for name in ["my.rom", "2048", "", "-", "fn", "rand", "chip-8-emulator"] {
+            assert_eq!(Err(InvalidCrateName(name.to_string())), recompile(&PROGRAM, name, EmulatorType::Chip48, "\"*\""));
+        }
+    }
+
+    #[test]
+    fn writes_crates() {
+        let directory = std::env::temp_dir().join(format!("chip-8-recompiler-test-{}", std::process::id()));
+        let recompiled = recompile(&PROGRAM, "recompiled", EmulatorType::Chip48, "\"*\"").unwrap();
+
+        recompiled.write_to(&directory).unwrap();
+
+        assert_eq!(recompiled.lib, fs::read_to_string(directory.join("src").join("lib.rs")).unwrap());
+        assert!(directory.join("src").join("main.rs").exists());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn recompiled_crates_build_and_match_the_interpreter() {
+        let program: Vec<u8> = EXERCISE_PROGRAM.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+        let manifest_directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dependency = format!("{{ path = {manifest_directory:?}, default-features = false, features = [\"rand\"] }}");
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let runs: [(EmulatorType, &[&str]); 3] = [
+            (EmulatorType::CosmacVip, &["600", "12", "1"]),
+            (EmulatorType::CosmacVip, &["300", "vip", "2"]),
+            (EmulatorType::Chip48, &["--display-wait", "300", "40", "3"]),
+        ];
+
+        for (emulator_type, args) in runs {
+            let directory = std::env::temp_dir().join(format!("chip-8-recompiler-build-{}", std::process::id()));
+            recompile(&program, "recompiled", emulator_type, &dependency).unwrap().write_to(&directory).unwrap();
+            // The workspace's lock file pins the dependencies to ones that are already downloaded
+            let _ = fs::copy(manifest_directory.join("Cargo.lock"), directory.join("Cargo.lock"));
+
+            let output = Command::new(&cargo)
+                .args(["run", "--quiet", "--manifest-path"])
+                .arg(directory.join("Cargo.toml"))
+                .arg("--")
+                .args(args)
+                .env("CARGO_TARGET_DIR", std::env::temp_dir().join("chip-8-recompiler-target"))
+                .output()
+                .unwrap();
+            fs::remove_dir_all(&directory).unwrap();
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(output.status.success(), "{emulator_type:?} {args:?}: {}", String::from_utf8_lossy(&output.stderr));
+            assert!(stdout.contains("Frame buffers matched"), "{stdout}");
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::audio::FRAMES_PER_SECOND;
+use crate::palette::Rgb;
+
+/// The video formats a session can be recorded to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VideoFormat {
+    /// An animated GIF. Runs of identical frames are merged into a single GIF frame
+    Gif,
+    /// A raw YUV4MPEG2 stream with 4:4:4 chroma at 60 frames per second, which encoders such as
+    /// ffmpeg can read directly
+    Y4m,
+    /// A stream of concatenated binary PPM images, one per frame
+    Ppm,
+}
+
+impl VideoFormat {
+    /// Picks a format based on the extension of path
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "gif" => Some(VideoFormat::Gif),
+            "y4m" => Some(VideoFormat::Y4m),
+            "ppm" => Some(VideoFormat::Ppm),
+            _ => None,
+        }
+    }
+}
+
+/// Records frame buffers to a video stream. Each pixel value in the frame buffer is used as an
+/// index into the palette, values past the end of the palette use its last color.
+pub struct FrameRecorder<W: Write> {
+    format: VideoFormat,
+    output: Output<W>,
+    palette: Vec<Rgb>,
+    width: usize,
+    height: usize,
+    scale: usize,
+    frames_recorded: u64,
+}
+
+/// The destination of a recording
+enum Output<W: Write> {
+    /// The GIF encoder along with the frame waiting to be written, which is held back until a
+    /// different frame arrives so that its delay is known
+    Gif {
+        encoder: gif::Encoder<W>,
+        pending_frame: Option<Vec<u8>>,
+        /// The total delay written so far in hundredths of a second
+        written_centiseconds: u64,
+    },
+    /// A raw stream of frames
+    Raw(W),
+}
+
+impl FrameRecorder<BufWriter<File>> {
+    /// Creates a recorder writing to a file at path, in the format given by its extension
+    pub fn create<P: AsRef<Path>>(path: P,
+                                  width: usize,
+                                  height: usize,
+                                  scale: usize,
+                                  palette: &[Rgb]) -> io::Result<Self> {
+        let format = VideoFormat::from_path(&path).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported video format for {}", path.as_ref().display()),
+        ))?;
+        let writer = BufWriter::new(File::create(path)?);
+
+        Self::new(writer, format, width, height, scale, palette)
+    }
+}
+
+impl<W: Write> FrameRecorder<W> {
+    /// Creates a recorder that writes frames of width x height pixels, each scaled up by scale,
+    /// to writer in the given format
+    pub fn new(mut writer: W,
+               format: VideoFormat,
+               width: usize,
+               height: usize,
+               scale: usize,
+               palette: &[Rgb]) -> io::Result<Self> {
+        if palette.is_empty() || palette.len() > 256 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "palette must have 1 to 256 colors"));
+        }
+
+        let scale = scale.max(1);
+        let output = match format {
+            VideoFormat::Gif => {
+                let global_palette: Vec<u8> = palette.iter().flatten().copied().collect();
+                let mut encoder = gif::Encoder::new(writer,
+                                                    (width * scale) as u16,
+                                                    (height * scale) as u16,
+                                                    &global_palette)
+                    .map_err(to_io_error)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(to_io_error)?;
+
+                Output::Gif { encoder, pending_frame: None, written_centiseconds: 0 }
+            }
+            VideoFormat::Y4m => {
+                writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                         width * scale, height * scale, FRAMES_PER_SECOND)?;
+                Output::Raw(writer)
+            }
+            VideoFormat::Ppm => Output::Raw(writer),
+        };
+
+        Ok(Self {
+            format,
+            output,
+            palette: palette.to_vec(),
+            width,
+            height,
+            scale,
+            frames_recorded: 0,
+        })
+    }
+
+    /// Returns the format being recorded to
+    pub fn format(&self) -> VideoFormat {
+        self.format
+    }
+
+    /// Returns the number of frames recorded so far
+    pub fn frames_recorded(&self) -> u64 {
+        self.frames_recorded
+    }
+
+    /// Records one emulated frame
+    pub fn record_frame(&mut self, frame_buffer: &[u8]) -> io::Result<()> {
+        let indices = self.scaled_indices(frame_buffer);
+        let (width, height) = (self.width * self.scale, self.height * self.scale);
+
+        match &mut self.output {
+            Output::Gif { encoder, pending_frame, written_centiseconds } => {
+                if pending_frame.as_ref() != Some(&indices) {
+                    if let Some(previous) = pending_frame.take() {
+                        write_gif_frame(encoder, previous, width, height, self.frames_recorded, written_centiseconds)?;
+                    }
+                    *pending_frame = Some(indices);
+                }
+            }
+            Output::Raw(writer) => match self.format {
+                VideoFormat::Y4m => {
+                    writer.write_all(b"FRAME\n")?;
+                    let yuv: Vec<[u8; 3]> = self.palette.iter().map(|color| rgb_to_yuv(*color)).collect();
+                    let pixels: Vec<[u8; 3]> = indices.iter().map(|index| yuv[*index as usize]).collect();
+                    for plane in 0..3 {
+                        let bytes: Vec<u8> = pixels.iter().map(|pixel| pixel[plane]).collect();
+                        writer.write_all(&bytes)?;
+                    }
+                }
+                _ => {
+                    write!(writer, "P6\n{width} {height}\n255\n")?;
+                    let bytes: Vec<u8> = indices.iter().flat_map(|index| self.palette[*index as usize]).collect();
+                    writer.write_all(&bytes)?;
+                }
+            },
+        }
+
+        self.frames_recorded += 1;
+
+        Ok(())
+    }
+
+    /// Writes out any held back frame and flushes the output
+    pub fn finish(self) -> io::Result<()> {
+        let (width, height) = (self.width * self.scale, self.height * self.scale);
+
+        match self.output {
+            Output::Gif { mut encoder, pending_frame, written_centiseconds: mut written } => {
+                if let Some(frame) = pending_frame {
+                    write_gif_frame(&mut encoder, frame, width, height, self.frames_recorded, &mut written)?;
+                }
+                encoder.into_inner()?.flush()
+            }
+            Output::Raw(mut writer) => writer.flush(),
+        }
+    }
+
+    /// Maps a frame buffer to palette indices, scaled up by the recorder's scale
+    fn scaled_indices(&self, frame_buffer: &[u8]) -> Vec<u8> {
+        let last_index = (self.palette.len() - 1) as u8;
+        let scaled_width = self.width * self.scale;
+        let mut indices = Vec::with_capacity(scaled_width * self.height * self.scale);
+
+        for y in 0..self.height * self.scale {
+            let row = &frame_buffer[(y / self.scale) * self.width..(y / self.scale + 1) * self.width];
+            for x in 0..scaled_width {
+                indices.push(row[x / self.scale].min(last_index));
+            }
+        }
+
+        indices
+    }
+}
+
+/// Writes a GIF frame that lasts until `end_frame` emulated frames into the recording. GIF delays
+/// are in hundredths of a second and most viewers treat delays under 2 as much longer ones, so the
+/// delay is kept at 2 or more and any rounding is carried into later frames.
+fn write_gif_frame<W: Write>(encoder: &mut gif::Encoder<W>,
+                             indices: Vec<u8>,
+                             width: usize,
+                             height: usize,
+                             end_frame: u64,
+                             written_centiseconds: &mut u64) -> io::Result<()> {
+    let end_centiseconds = (end_frame * 100 + FRAMES_PER_SECOND as u64 / 2) / FRAMES_PER_SECOND as u64;
+    let delay = end_centiseconds.saturating_sub(*written_centiseconds).max(2);
+    *written_centiseconds += delay;
+
+    let frame = gif::Frame {
+        width: width as u16,
+        height: height as u16,
+        delay: delay.min(u16::MAX as u64) as u16,
+        buffer: Cow::Owned(indices),
+        ..gif::Frame::default()
+    };
+
+    encoder.write_frame(&frame).map_err(to_io_error)
+}
+
+/// Converts a color to full range BT.601 Y, Cb and Cr components
+fn rgb_to_yuv([r, g, b]: Rgb) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let u = 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b;
+    let v = 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b;
+
+    [y, u, v].map(|component| component.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Converts a GIF encoding error into an io error
+fn to_io_error(error: gif::EncodingError) -> io::Error {
+    match error {
+        gif::EncodingError::Io(error) => error,
+        error => io::Error::other(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    const PALETTE: [Rgb; 2] = [[0, 0, 0], [0, 255, 0]];
+
+    fn test_frame(on_pixel: usize) -> Vec<u8> {
+        let mut frame = vec![0; 4 * 2];
+        frame[on_pixel] = 1;
+        frame
+    }
+
+    #[test]
+    fn can_pick_format_from_path() {
+        assert_eq!(Some(VideoFormat::Gif), VideoFormat::from_path("session.GIF"));
+        assert_eq!(Some(VideoFormat::Y4m), VideoFormat::from_path("out/session.y4m"));
+        assert_eq!(Some(VideoFormat::Ppm), VideoFormat::from_path("session.ppm"));
+        assert_eq!(None, VideoFormat::from_path("session.mp4"));
+        assert_eq!(None, VideoFormat::from_path("session"));
+    }
+
+    #[test]
+    fn can_record_ppm_stream() {
+        let mut output = Vec::new();
+        let mut recorder = FrameRecorder::new(&mut output, VideoFormat::Ppm, 4, 2, 2, &PALETTE).unwrap();
+
+        recorder.record_frame(&test_frame(0)).unwrap();
+        recorder.record_frame(&test_frame(1)).unwrap();
+        recorder.finish().unwrap();
+
+        let header = b"P6\n8 4\n255\n";
+        let frame_length = header.len() + 8 * 4 * 3;
+        assert_eq!(2 * frame_length, output.len());
+        assert_eq!(header, &output[..header.len()]);
+        // the top left 2x2 block of the first frame is on
+        assert_eq!([0, 255, 0, 0, 255, 0, 0, 0, 0], output[header.len()..header.len() + 9]);
+    }
+
+    #[test]
+    fn can_record_y4m_stream() {
+        let mut output = Vec::new();
+        let mut recorder = FrameRecorder::new(&mut output, VideoFormat::Y4m, 4, 2, 1, &PALETTE).unwrap();
+
+        recorder.record_frame(&test_frame(3)).unwrap();
+        recorder.finish().unwrap();
+
+        let header = b"YUV4MPEG2 W4 H2 F60:1 Ip A1:1 C444\nFRAME\n";
+        assert_eq!(header, &output[..header.len()]);
+        assert_eq!(header.len() + 3 * 8, output.len());
+
+        let luma = &output[header.len()..header.len() + 8];
+        assert_eq!(0, luma[0]);
+        assert_eq!(rgb_to_yuv([0, 255, 0])[0], luma[3]);
+    }
+
+    #[test]
+    fn gif_merges_identical_frames() {
+        let mut output = Vec::new();
+        let mut recorder = FrameRecorder::new(&mut output, VideoFormat::Gif, 4, 2, 1, &PALETTE).unwrap();
+
+        for _ in 0..60 {
+            recorder.record_frame(&test_frame(0)).unwrap();
+        }
+        for _ in 0..30 {
+            recorder.record_frame(&test_frame(5)).unwrap();
+        }
+        assert_eq!(90, recorder.frames_recorded());
+        recorder.finish().unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(Cursor::new(output)).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+
+        assert_eq!(vec![100, 50], delays);
+    }
+
+    #[test]
+    fn gif_delays_are_never_shorter_than_two_centiseconds() {
+        let mut output = Vec::new();
+        let mut recorder = FrameRecorder::new(&mut output, VideoFormat::Gif, 4, 2, 1, &PALETTE).unwrap();
+
+        for i in 0..6 {
+            recorder.record_frame(&test_frame(i % 2)).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(Cursor::new(output)).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+
+        assert_eq!(6, delays.len());
+        assert!(delays.iter().all(|delay| *delay >= 2));
+    }
+}
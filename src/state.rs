@@ -0,0 +1,202 @@
+use alloc::vec::Vec;
+use core::fmt;
+use crate::{Chip8, Chip8Rng, EmulatorType, KeyState, DISPLAY_HEIGHT, DISPLAY_WIDTH, MEMORY_SIZE, NUM_KEYS, STACK_SIZE, VARIABLE_REGISTER_COUNT};
+
+/// The bytes every saved state starts with
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// The version of the saved state format
+const VERSION: u8 = 1;
+
+/// The size in bytes of a saved state
+pub const STATE_SIZE: usize = MAGIC.len() + 2
+    + MEMORY_SIZE
+    + DISPLAY_WIDTH * DISPLAY_HEIGHT
+    + STACK_SIZE * 2
+    + 3
+    + 4
+    + VARIABLE_REGISTER_COUNT
+    + NUM_KEYS * 2;
+
+/// The ways loading a saved state can fail
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LoadStateError {
+    /// The state isn't [`STATE_SIZE`] bytes long
+    WrongSize(usize),
+    /// The state doesn't start with the expected bytes, so isn't a saved state
+    NotAState,
+    /// The state was saved in a format version this build can't read
+    UnsupportedVersion(u8),
+    /// The state holds a value out of range, e.g. a stack pointer past the end of the stack
+    Corrupt,
+}
+
+impl fmt::Display for LoadStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadStateError::WrongSize(size) => write!(f, "expected a {STATE_SIZE} byte state, got {size} bytes"),
+            LoadStateError::NotAState => write!(f, "not a saved state"),
+            LoadStateError::UnsupportedVersion(version) => write!(f, "unsupported state version {version}"),
+            LoadStateError::Corrupt => write!(f, "the state is corrupt"),
+        }
+    }
+}
+
+impl core::error::Error for LoadStateError {}
+
+impl<R: Chip8Rng> Chip8<R> {
+    /// Saves everything about the interpreter except its random number generator and display wait
+    /// setting as [`STATE_SIZE`] bytes, for restoring with [`Chip8::load_state`]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(MAGIC);
+        state.push(VERSION);
+        state.push(match self.emulator_type {
+            EmulatorType::CosmacVip => 0,
+            EmulatorType::Chip48 => 1,
+        });
+        state.extend_from_slice(&self.ram);
+        state.extend_from_slice(&self.frame_buffer);
+        for address in self.stack {
+            state.extend_from_slice(&address.to_be_bytes());
+        }
+        state.extend_from_slice(&[self.stack_pointer, self.delay_timer, self.sound_timer]);
+        state.extend_from_slice(&self.program_counter.to_be_bytes());
+        state.extend_from_slice(&self.index_register.to_be_bytes());
+        state.extend_from_slice(&self.variable_registers);
+        for key_state in self.keypad_state.iter().chain(&self.previous_keypad_state) {
+            state.push(matches!(key_state, KeyState::Down) as u8);
+        }
+
+        state
+    }
+
+    /// Restores a state saved with [`Chip8::save_state`], leaving the interpreter unchanged if it
+    /// can't be loaded. A restored interpreter can draw before the next 60 Hz tick
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), LoadStateError> {
+        if state.len() != STATE_SIZE {
+            return Err(LoadStateError::WrongSize(state.len()));
+        }
+        let (magic, state) = state.split_at(MAGIC.len());
+        if magic != MAGIC {
+            return Err(LoadStateError::NotAState);
+        }
+
+        let mut reader = StateReader(state);
+        let version = reader.byte();
+        if version != VERSION {
+            return Err(LoadStateError::UnsupportedVersion(version));
+        }
+        let emulator_type = match reader.byte() {
+            0 => EmulatorType::CosmacVip,
+            1 => EmulatorType::Chip48,
+            _ => return Err(LoadStateError::Corrupt),
+        };
+        let ram = reader.bytes(MEMORY_SIZE);
+        let frame_buffer = reader.bytes(DISPLAY_WIDTH * DISPLAY_HEIGHT);
+        let stack: [u16; STACK_SIZE] = core::array::from_fn(|_| reader.word());
+        let [stack_pointer, delay_timer, sound_timer] = [reader.byte(), reader.byte(), reader.byte()];
+        let program_counter = reader.word();
+        let index_register = reader.word();
+        let variable_registers = reader.bytes(VARIABLE_REGISTER_COUNT);
+        let keys = reader.bytes(NUM_KEYS * 2);
+
+        if stack_pointer as usize > STACK_SIZE || program_counter as usize > MEMORY_SIZE - 2 || keys.iter().any(|key| *key > 1) {
+            return Err(LoadStateError::Corrupt);
+        }
+
+        self.emulator_type = emulator_type;
+        self.ram.copy_from_slice(ram);
+        self.frame_buffer.copy_from_slice(frame_buffer);
+        self.stack = stack;
+        self.stack_pointer = stack_pointer;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.program_counter = program_counter;
+        self.index_register = index_register;
+        self.variable_registers.copy_from_slice(variable_registers);
+        let key_state = |key: &u8| if *key == 1 { KeyState::Down } else { KeyState::Up };
+        self.keypad_state = core::array::from_fn(|index| key_state(&keys[index]));
+        self.previous_keypad_state = core::array::from_fn(|index| key_state(&keys[NUM_KEYS + index]));
+        self.drawn_since_tick = false;
+
+        Ok(())
+    }
+}
+
+/// Reads fields from a saved state in order. The state's size is checked up front, so reads
+/// can't run past its end
+struct StateReader<'a>(&'a [u8]);
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, count: usize) -> &'a [u8] {
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        bytes
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn word(&mut self) -> u16 {
+        u16::from_be_bytes([self.byte(), self.byte()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::{Chip8Key, Register};
+    use super::*;
+
+    /// Calls a subroutine that draws a random digit and loops
+    const PROGRAM: [u8; 12] = [
+        0x22, 0x04, 0x12, 0x00, 0xC0, 0x0F, 0xF0, 0x29,
+        0xD1, 0x15, 0x00, 0xEE,
+    ];
+
+    #[test]
+    fn restores_saved_states() {
+        let mut chip8 = Chip8::new(EmulatorType::Chip48, TestRng::new(1));
+        chip8.load_program(&PROGRAM);
+        chip8.key_down(Chip8Key::C);
+        chip8.run_frame(9);
+        chip8.set_register(Register::DelayTimer, 30);
+        let state = chip8.save_state();
+        assert_eq!(STATE_SIZE, state.len());
+
+        let mut restored = Chip8::new(EmulatorType::CosmacVip, TestRng::new(2));
+        restored.load_state(&state).unwrap();
+
+        assert_eq!(state, restored.save_state());
+        assert_eq!(EmulatorType::Chip48, restored.emulator_type());
+        assert_eq!(chip8.frame_buffer(), restored.frame_buffer());
+        assert_eq!(chip8.stack(), restored.stack());
+        assert_eq!(30, restored.delay_timer());
+        assert_eq!(KeyState::Down, restored.key_state(Chip8Key::C));
+    }
+
+    #[test]
+    fn rejects_invalid_states() {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(1));
+        let mut state = chip8.save_state();
+
+        assert_eq!(Err(LoadStateError::WrongSize(3)), chip8.load_state(&state[..3]));
+        state[4] = 9;
+        assert_eq!(Err(LoadStateError::UnsupportedVersion(9)), chip8.load_state(&state));
+        state[4] = VERSION;
+        state[5] = 2;
+        assert_eq!(Err(LoadStateError::Corrupt), chip8.load_state(&state));
+        state[5] = 0;
+
+        // A program counter on the last byte of memory has no room to fetch the next instruction
+        let program_counter = MAGIC.len() + 2 + MEMORY_SIZE + DISPLAY_WIDTH * DISPLAY_HEIGHT + STACK_SIZE * 2 + 3;
+        state[program_counter..program_counter + 2].copy_from_slice(&(MEMORY_SIZE as u16 - 1).to_be_bytes());
+        assert_eq!(Err(LoadStateError::Corrupt), chip8.load_state(&state));
+        state[program_counter..program_counter + 2].copy_from_slice(&(MEMORY_SIZE as u16 - 2).to_be_bytes());
+        assert_eq!(Ok(()), chip8.load_state(&state));
+        state[0] = b'X';
+        assert_eq!(Err(LoadStateError::NotAState), chip8.load_state(&state));
+    }
+}
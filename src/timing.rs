@@ -0,0 +1,236 @@
+use crate::instruction::Instruction;
+use crate::{Chip8, Chip8Rng, ExecutionObserver};
+
+/// The COSMAC VIP's machine cycles per 60 Hz frame: its 1.76 MHz clock over the 8 clock pulses
+/// each cycle takes
+pub const CYCLES_PER_FRAME: i32 = 3668;
+
+/// The cycles of each frame the display interrupt takes, feeding the screen to the video chip row
+/// by row while the interpreter waits
+pub const INTERRUPT_CYCLES: i32 = 1832;
+
+/// The cycles the interpreter's loop takes to fetch and decode an instruction
+const FETCH_CYCLES: i32 = 40;
+
+/// The extra cycles a skip takes when it skips
+const SKIP_TAKEN_CYCLES: i32 = 4;
+
+/// Gets the machine cycles the VIP interpreter takes to fetch and execute an instruction in the
+/// interpreter's current state. The costs are approximations of published measurements of the
+/// interpreter's routines, and don't include the time a draw waits for the display interrupt
+pub fn instruction_cycles<R: Chip8Rng>(chip8: &Chip8<R>, instruction: Instruction) -> i32 {
+    let v = |register: u8| chip8.variable_registers[register as usize] as i32;
+    let skip = |cycles: i32, taken: bool| if taken { cycles + SKIP_TAKEN_CYCLES } else { cycles };
+
+    let execute = match instruction {
+        Instruction::ClearScreen => 24,
+        Instruction::Return => 10,
+        Instruction::Jump { .. } => 12,
+        Instruction::Call { .. } => 26,
+        Instruction::SkipIfVxEqualsNn { x, nn } => skip(10, v(x) == nn as i32),
+        Instruction::SkipIfVxNotEqualsNn { x, nn } => skip(10, v(x) != nn as i32),
+        Instruction::SkipIfVxEqualsVy { x, y } => skip(14, v(x) == v(y)),
+        Instruction::SetVx { .. } => 6,
+        Instruction::AddToVx { .. } => 10,
+        Instruction::SetVxToVy { .. }
+        | Instruction::OrVxWithVy { .. }
+        | Instruction::AndVxWithVy { .. }
+        | Instruction::XorVxWithVy { .. }
+        | Instruction::AddVyToVx { .. }
+        | Instruction::SubtractVyFromVx { .. }
+        | Instruction::ShiftVxRight { .. }
+        | Instruction::SubtractVxFromVyIntoVx { .. }
+        | Instruction::ShiftVxLeft { .. } => 44,
+        Instruction::SkipIfVxNotEqualsVy { x, y } => skip(14, v(x) != v(y)),
+        Instruction::SetIndex { .. } => 12,
+        Instruction::JumpWithOffset { .. } => 22,
+        Instruction::Random { .. } => 36,
+        // Each sprite byte is shifted into place a bit at a time before it's drawn
+        Instruction::Draw { x, n, .. } => 22 + n as i32 * (18 + 4 * (v(x) % 8)),
+        Instruction::SkipIfKeyDown { .. } | Instruction::SkipIfKeyUp { .. } => 14,
+        Instruction::SetVxToDelayTimer { .. }
+        | Instruction::WaitForKey { .. }
+        | Instruction::SetDelayTimer { .. }
+        | Instruction::SetSoundTimer { .. } => 10,
+        Instruction::AddVxToIndex { .. } => 18,
+        Instruction::SetIndexToFontCharacter { .. } => 20,
+        // The digits are found by repeated subtraction, so bigger digits take longer
+        Instruction::StoreDecimalDigits { x } => 36 + 16 * (v(x) / 100 + v(x) / 10 % 10 + v(x) % 10),
+        Instruction::StoreRegisters { x } | Instruction::LoadRegisters { x } => 14 + 14 * (x as i32 + 1),
+        Instruction::Invalid { .. } => 0,
+    };
+
+    FETCH_CYCLES + execute
+}
+
+/// Runs an interpreter at the speed of the COSMAC VIP. Each frame the interpreter gets the cycles
+/// the display interrupt leaves, each instruction takes its [`instruction_cycles`], and a draw
+/// waits for the next interrupt unless one just happened. The interrupt decrements the timers.
+/// Cycles an instruction overruns a frame by are taken from the next
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VipTiming {
+    /// The cycles left in the current frame, negative if the last instruction overran it
+    cycles_left: i32,
+    /// Whether an instruction has been executed since the last interrupt
+    executed_since_interrupt: bool,
+}
+
+impl Default for VipTiming {
+    fn default() -> Self {
+        Self {
+            cycles_left: CYCLES_PER_FRAME - INTERRUPT_CYCLES,
+            executed_since_interrupt: false,
+        }
+    }
+}
+
+impl VipTiming {
+    /// Creates a timing model at the start of a frame
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the cycles left in the current frame
+    pub fn cycles_left(&self) -> i32 {
+        self.cycles_left
+    }
+
+    /// Returns whether the current frame is over, because its cycles have run out or because the
+    /// next instruction draws and has to wait for the display interrupt
+    pub fn is_frame_over<R: Chip8Rng>(&self, chip8: &Chip8<R>) -> bool {
+        self.is_frame_over_before(chip8.next_instruction())
+    }
+
+    /// Returns whether the current frame is over if the given instruction is next
+    fn is_frame_over_before(&self, instruction: Instruction) -> bool {
+        self.cycles_left <= 0 || (self.executed_since_interrupt && matches!(instruction, Instruction::Draw { .. }))
+    }
+
+    /// Executes the next instruction and takes its cycles from the frame, telling an observer
+    /// about it before and after
+    pub fn execute_next_instruction_with<R: Chip8Rng, O: ExecutionObserver>(&mut self,
+                                                                       chip8: &mut Chip8<R>,
+                                                                       observer: &mut O) {
+        self.charge(chip8, chip8.next_instruction());
+        chip8.execute_next_instruction_with(observer);
+    }
+
+    /// Takes the cycles of an instruction about to be executed from the frame, unless the frame is
+    /// over. Returns whether the instruction can run. This is for callers that execute instructions
+    /// themselves, such as recompiled code, so the program counter isn't read
+    pub fn try_charge<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: Instruction) -> bool {
+        if self.is_frame_over_before(instruction) {
+            return false;
+        }
+
+        self.charge(chip8, instruction);
+        true
+    }
+
+    /// Takes an instruction's cycles from the frame
+    fn charge<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: Instruction) {
+        self.cycles_left -= instruction_cycles(chip8, instruction);
+        self.executed_since_interrupt = true;
+    }
+
+    /// Ends the current frame with the display interrupt, which decrements the timers and starts
+    /// the next frame. Cycles left over while waiting are lost
+    pub fn end_frame<R: Chip8Rng>(&mut self, chip8: &mut Chip8<R>) {
+        chip8.decrement_timers();
+        self.cycles_left = self.cycles_left.min(0) + CYCLES_PER_FRAME - INTERRUPT_CYCLES;
+        self.executed_since_interrupt = false;
+    }
+
+    /// Runs one 60 Hz frame, returning the number of instructions executed
+    pub fn run_frame<R: Chip8Rng>(&mut self, chip8: &mut Chip8<R>) -> usize {
+        self.run_frame_with(chip8, &mut ())
+    }
+
+    /// Runs one 60 Hz frame like [`VipTiming::run_frame`], passing each instruction to an observer
+    pub fn run_frame_with<R: Chip8Rng, O: ExecutionObserver>(&mut self, chip8: &mut Chip8<R>, observer: &mut O) -> usize {
+        let executed = self.execute_frame_with(chip8, observer);
+        self.end_frame(chip8);
+        executed
+    }
+
+    /// Executes instructions until the frame is over like [`VipTiming::run_frame_with`], without
+    /// ending it, returning the number executed
+    pub fn execute_frame_with<R: Chip8Rng, O: ExecutionObserver>(&mut self, chip8: &mut Chip8<R>, observer: &mut O) -> usize {
+        let mut executed = 0;
+        while !self.is_frame_over(chip8) {
+            self.execute_next_instruction_with(chip8, observer);
+            executed += 1;
+        }
+
+        executed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::EmulatorType;
+    use super::*;
+
+    fn chip8(program: &[u8]) -> Chip8<TestRng> {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(1));
+        chip8.load_program(program);
+        chip8
+    }
+
+    #[test]
+    fn costs_depend_on_the_instruction_and_its_operands() {
+        let chip8 = chip8(&[]);
+
+        assert_eq!(46, instruction_cycles(&chip8, Instruction::SetVx { x: 0, nn: 1 }));
+        assert_eq!(50, instruction_cycles(&chip8, Instruction::SkipIfVxNotEqualsNn { x: 0, nn: 0 }));
+        assert_eq!(54, instruction_cycles(&chip8, Instruction::SkipIfVxEqualsNn { x: 0, nn: 0 }));
+        assert!(instruction_cycles(&chip8, Instruction::LoadRegisters { x: 15 })
+            > instruction_cycles(&chip8, Instruction::LoadRegisters { x: 0 }));
+    }
+
+    #[test]
+    fn runs_more_cheap_instructions_than_expensive_ones_per_frame() {
+        // 6000 1200 and FF65 1200
+        let mut cheap = chip8(&[0x60, 0x00, 0x12, 0x00]);
+        let mut expensive = chip8(&[0xFF, 0x65, 0x12, 0x00]);
+
+        let cheap_count = VipTiming::new().run_frame(&mut cheap);
+        let expensive_count = VipTiming::new().run_frame(&mut expensive);
+
+        assert!(cheap_count > expensive_count * 2, "{cheap_count} vs {expensive_count}");
+    }
+
+    #[test]
+    fn try_charge_refuses_instructions_once_the_frame_is_over() {
+        let chip8 = chip8(&[]);
+        let mut timing = VipTiming::new();
+        let draw = Instruction::Draw { x: 0, y: 0, n: 1 };
+
+        assert!(timing.try_charge(&chip8, draw));
+        assert!(!timing.try_charge(&chip8, draw));
+
+        let load = Instruction::LoadRegisters { x: 15 };
+        while timing.try_charge(&chip8, load) {}
+        assert!(timing.cycles_left() <= 0);
+        assert!(timing.is_frame_over(&chip8));
+    }
+
+    #[test]
+    fn draws_wait_for_the_display_interrupt_which_ticks_the_timers() {
+        // 603C F015 D001 1204, drawing a one row sprite in a loop with the delay timer running
+        let mut chip8 = chip8(&[0x60, 0x3C, 0xF0, 0x15, 0xD0, 0x01, 0x12, 0x04]);
+        let mut timing = VipTiming::new();
+
+        assert_eq!(2, timing.run_frame(&mut chip8));
+        assert_eq!(0x204, chip8.program_counter());
+        assert_eq!(0x3B, chip8.delay_timer());
+
+        for frame in 0..4 {
+            assert_eq!(2, timing.run_frame(&mut chip8), "frame {frame}");
+            assert_eq!(0x204, chip8.program_counter());
+        }
+        assert_eq!(0x37, chip8.delay_timer());
+        assert!(timing.cycles_left() > 0);
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use crate::instruction::{DisassembledInstruction, Instruction};
+use crate::{Chip8, Chip8Rng, ExecutionObserver, VARIABLE_REGISTER_COUNT};
+
+/// The bytes binary traces start with
+const BINARY_MAGIC: &[u8; 7] = b"C8TRACE";
+
+/// The version of the binary trace format
+const BINARY_VERSION: u8 = 1;
+
+/// The size of a record in a binary trace
+const BINARY_RECORD_SIZE: usize = 8 + 2 + 2 + VARIABLE_REGISTER_COUNT + 2 + 3;
+
+/// The formats a trace can be written in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraceFormat {
+    /// One human readable line per instruction
+    Text,
+    /// A short header followed by fixed size little endian records, see [`TraceRecord`]
+    Binary,
+}
+
+/// Whether traced state is captured before or after each instruction executes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraceTiming {
+    /// Capture the state the instruction starts with
+    Before,
+    /// Capture the state the instruction leaves behind
+    After,
+}
+
+/// The state of the interpreter around one executed instruction
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraceRecord {
+    /// The number of instructions executed before this one since tracing started
+    pub cycle: u64,
+    /// The address the instruction was fetched from
+    pub address: u16,
+    /// The raw opcode
+    pub opcode: u16,
+    /// V0 to VF
+    pub variable_registers: [u8; VARIABLE_REGISTER_COUNT],
+    /// The index register
+    pub index_register: u16,
+    /// The delay timer
+    pub delay_timer: u8,
+    /// The sound timer
+    pub sound_timer: u8,
+    /// The stack pointer
+    pub stack_pointer: u8,
+}
+
+impl TraceRecord {
+    /// Captures the interpreter's state for an instruction
+    pub fn capture<R: Chip8Rng>(cycle: u64, chip8: &Chip8<R>, instruction: &DisassembledInstruction) -> Self {
+        Self {
+            cycle,
+            address: instruction.address,
+            opcode: instruction.opcode,
+            variable_registers: *chip8.variable_registers(),
+            index_register: chip8.index_register(),
+            delay_timer: chip8.delay_timer(),
+            sound_timer: chip8.sound_timer(),
+            stack_pointer: chip8.stack_pointer(),
+        }
+    }
+
+    /// Writes the record as a line of text. Tracepoints are marked with a `*` before the address
+    pub fn write_text<W: Write>(&self, writer: &mut W, tracepoint: bool) -> io::Result<()> {
+        let marker = if tracepoint { '*' } else { ' ' };
+        let mnemonic = Instruction::decode(self.opcode).to_string();
+        let registers: Vec<String> = self.variable_registers.iter().map(|value| format!("{value:02X}")).collect();
+
+        writeln!(writer, "{:>10} {marker}{:03X} {:04X}  {mnemonic:<18} V={} I={:03X} DT={:02X} ST={:02X} SP={:X}",
+                 self.cycle,
+                 self.address,
+                 self.opcode,
+                 registers.join(" "),
+                 self.index_register,
+                 self.delay_timer,
+                 self.sound_timer,
+                 self.stack_pointer)
+    }
+
+    /// Writes the record in the binary format: the cycle, address, opcode, V0 to VF, I, DT, ST
+    /// and SP, with multibyte values little endian
+    pub fn write_binary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(BINARY_RECORD_SIZE);
+        bytes.extend_from_slice(&self.cycle.to_le_bytes());
+        bytes.extend_from_slice(&self.address.to_le_bytes());
+        bytes.extend_from_slice(&self.opcode.to_le_bytes());
+        bytes.extend_from_slice(&self.variable_registers);
+        bytes.extend_from_slice(&self.index_register.to_le_bytes());
+        bytes.extend_from_slice(&[self.delay_timer, self.sound_timer, self.stack_pointer]);
+
+        writer.write_all(&bytes)
+    }
+
+    /// Parses a record written by [`TraceRecord::write_binary`]
+    fn from_binary(bytes: &[u8; BINARY_RECORD_SIZE]) -> Self {
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let mut cycle = [0; 8];
+        cycle.copy_from_slice(&bytes[..8]);
+        let mut variable_registers = [0; VARIABLE_REGISTER_COUNT];
+        variable_registers.copy_from_slice(&bytes[12..12 + VARIABLE_REGISTER_COUNT]);
+
+        Self {
+            cycle: u64::from_le_bytes(cycle),
+            address: u16_at(8),
+            opcode: u16_at(10),
+            variable_registers,
+            index_register: u16_at(28),
+            delay_timer: bytes[30],
+            sound_timer: bytes[31],
+            stack_pointer: bytes[32],
+        }
+    }
+}
+
+/// Reads a binary trace, returning when its state was captured and its records
+pub fn read_binary_trace<R: Read>(mut reader: R) -> io::Result<(TraceTiming, Vec<TraceRecord>)> {
+    let mut header = [0; BINARY_MAGIC.len() + 2];
+    reader.read_exact(&mut header)?;
+    if &header[..BINARY_MAGIC.len()] != BINARY_MAGIC || header[BINARY_MAGIC.len()] != BINARY_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a binary Chip-8 trace"));
+    }
+
+    let timing = if header[BINARY_MAGIC.len() + 1] == 0 { TraceTiming::Before } else { TraceTiming::After };
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() % BINARY_RECORD_SIZE != 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated trace record"));
+    }
+
+    let records = bytes.chunks_exact(BINARY_RECORD_SIZE)
+        .map(|chunk| TraceRecord::from_binary(chunk.try_into().unwrap()))
+        .collect();
+
+    Ok((timing, records))
+}
+
+/// Decides which instructions are traced, by address and by opcode class, i.e. the opcode's
+/// highest nibble
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraceFilter {
+    /// The address ranges to trace. Empty traces every address
+    address_ranges: Vec<RangeInclusive<u16>>,
+    /// A bit for each opcode class to trace
+    opcode_classes: u16,
+}
+
+impl TraceFilter {
+    /// Creates a filter that traces every instruction
+    pub fn everything() -> Self {
+        Self {
+            address_ranges: Vec::new(),
+            opcode_classes: u16::MAX,
+        }
+    }
+
+    /// Creates a filter that traces nothing, e.g. to log only tracepoints
+    pub fn nothing() -> Self {
+        Self {
+            address_ranges: Vec::new(),
+            opcode_classes: 0,
+        }
+    }
+
+    /// Limits tracing to instructions in the given address ranges. Each call adds another range
+    pub fn with_address_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.address_ranges.push(range);
+        self
+    }
+
+    /// Limits tracing to the given opcode classes, e.g. `[0x8, 0xD]` for arithmetic and drawing
+    pub fn with_opcode_classes(mut self, classes: &[u8]) -> Self {
+        self.opcode_classes = classes.iter().fold(0, |mask, class| mask | 1 << (class & 0xF));
+        self
+    }
+
+    /// Returns whether an instruction should be traced
+    pub fn matches(&self, address: u16, opcode: u16) -> bool {
+        let in_range = self.address_ranges.is_empty() || self.address_ranges.iter().any(|range| range.contains(&address));
+        in_range && self.opcode_classes & 1 << (opcode >> 12) != 0
+    }
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        Self::everything()
+    }
+}
+
+/// Logs executed instructions to a writer as an [`ExecutionObserver`]. Instructions at
+/// tracepoints are logged whether or not they match the filter, so a filter of
+/// [`TraceFilter::nothing`] logs only the tracepoints without ever stopping execution.
+/// Write errors can't be returned while executing, so the first one is kept and returned by
+/// [`Tracer::finish`], and nothing more is written after it.
+pub struct Tracer<W: Write> {
+    writer: W,
+    format: TraceFormat,
+    timing: TraceTiming,
+    filter: TraceFilter,
+    tracepoints: BTreeSet<u16>,
+    /// The number of instructions executed since tracing started, traced or not
+    cycle: u64,
+    error: Option<io::Error>,
+}
+
+impl Tracer<BufWriter<File>> {
+    /// Creates a tracer that writes to a file
+    pub fn create<P: AsRef<Path>>(path: P, format: TraceFormat, timing: TraceTiming) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), format, timing)
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    /// Creates a tracer that traces every instruction. Binary traces get their header written
+    /// straight away
+    pub fn new(mut writer: W, format: TraceFormat, timing: TraceTiming) -> io::Result<Self> {
+        if format == TraceFormat::Binary {
+            writer.write_all(BINARY_MAGIC)?;
+            writer.write_all(&[BINARY_VERSION, (timing == TraceTiming::After) as u8])?;
+        }
+
+        Ok(Self {
+            writer,
+            format,
+            timing,
+            filter: TraceFilter::everything(),
+            tracepoints: BTreeSet::new(),
+            cycle: 0,
+            error: None,
+        })
+    }
+
+    /// Replaces the filter
+    pub fn with_filter(mut self, filter: TraceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Adds an address whose instructions are always logged
+    pub fn add_tracepoint(&mut self, address: u16) {
+        self.tracepoints.insert(address);
+    }
+
+    /// Gets the number of instructions executed since tracing started
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Flushes the trace, returning the writer or the first error writing it
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Logs an instruction if it's at a tracepoint or matches the filter
+    fn log<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        let tracepoint = self.tracepoints.contains(&instruction.address);
+        if self.error.is_some() || !(tracepoint || self.filter.matches(instruction.address, instruction.opcode)) {
+            return;
+        }
+
+        let record = TraceRecord::capture(self.cycle, chip8, instruction);
+        let result = match self.format {
+            TraceFormat::Text => record.write_text(&mut self.writer, tracepoint),
+            TraceFormat::Binary => record.write_binary(&mut self.writer),
+        };
+        self.error = result.err();
+    }
+}
+
+impl<W: Write> ExecutionObserver for Tracer<W> {
+    fn before_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        if self.timing == TraceTiming::Before {
+            self.log(chip8, instruction);
+        }
+    }
+
+    fn after_instruction<R: Chip8Rng>(&mut self, chip8: &Chip8<R>, instruction: &DisassembledInstruction) {
+        if self.timing == TraceTiming::After {
+            self.log(chip8, instruction);
+        }
+        self.cycle += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::EmulatorType;
+    use super::*;
+
+    /// Sets V0 to 5, adds V0 to V1 and jumps back to the add
+    const PROGRAM: [u8; 6] = [0x60, 0x05, 0x81, 0x04, 0x12, 0x02];
+
+    fn traced(tracer: &mut Tracer<Vec<u8>>, instructions: usize) {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&PROGRAM);
+        for _ in 0..instructions {
+            chip8.execute_next_instruction_with(tracer);
+        }
+    }
+
+    #[test]
+    fn writes_text_traces_before_or_after() {
+        let mut before = Tracer::new(Vec::new(), TraceFormat::Text, TraceTiming::Before).unwrap();
+        let mut after = Tracer::new(Vec::new(), TraceFormat::Text, TraceTiming::After).unwrap();
+        traced(&mut before, 2);
+        traced(&mut after, 2);
+
+        let before = String::from_utf8(before.finish().unwrap()).unwrap();
+        let after = String::from_utf8(after.finish().unwrap()).unwrap();
+
+        assert_eq!(2, before.lines().count());
+        assert!(before.starts_with("         0  200 6005  LD V0, 0x05        V=00 00 "));
+        assert!(after.starts_with("         0  200 6005  LD V0, 0x05        V=05 00 "));
+        assert!(after.lines().nth(1).unwrap().contains("V=05 05 00"));
+    }
+
+    #[test]
+    fn binary_traces_round_trip() {
+        let mut tracer = Tracer::new(Vec::new(), TraceFormat::Binary, TraceTiming::After).unwrap();
+        traced(&mut tracer, 4);
+
+        let (timing, records) = read_binary_trace(tracer.finish().unwrap().as_slice()).unwrap();
+
+        assert_eq!(TraceTiming::After, timing);
+        assert_eq!(4, records.len());
+        assert_eq!(3, records[3].cycle);
+        assert_eq!(0x202, records[3].address);
+        assert_eq!(0x8104, records[3].opcode);
+        assert_eq!(10, records[3].variable_registers[1]);
+        assert!(read_binary_trace(&b"C8TRACE\x01\x00\x00"[..]).is_err());
+    }
+
+    #[test]
+    fn filters_by_address_and_opcode_class() {
+        let filter = TraceFilter::everything().with_address_range(0x202..=0x203).with_opcode_classes(&[0x8]);
+
+        assert!(filter.matches(0x202, 0x8104));
+        assert!(!filter.matches(0x204, 0x8104));
+        assert!(!filter.matches(0x202, 0x1202));
+        assert!(TraceFilter::everything().matches(0xFFE, 0xF00A));
+        assert!(!TraceFilter::nothing().matches(0x200, 0x6005));
+    }
+
+    #[test]
+    fn tracepoints_are_logged_without_matching_the_filter() {
+        let mut tracer = Tracer::new(Vec::new(), TraceFormat::Text, TraceTiming::Before).unwrap()
+            .with_filter(TraceFilter::nothing());
+        tracer.add_tracepoint(0x204);
+        traced(&mut tracer, 5);
+
+        assert_eq!(5, tracer.cycles());
+        let trace = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let cycles: Vec<&str> = trace.lines().map(|line| line.split_whitespace().next().unwrap()).collect();
+        assert_eq!(vec!["2", "4"], cycles);
+        assert!(trace.contains("*204 1202"));
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::VecDeque;
+use std::fmt;
+use serde_json::Value;
+use crate::debugger::{Debugger, RunOutcome};
+use crate::instruction::disassemble;
+use crate::trace::{TraceRecord, TraceTiming};
+use crate::{Chip8, Chip8Key, Chip8Rng, DISPLAY_HEIGHT, DISPLAY_WIDTH, VARIABLE_REGISTER_COUNT};
+
+/// The number of hex digits in a bit packed frame buffer
+const SCREEN_HEX_DIGITS: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT / 4;
+
+/// The state another emulator reported for one cycle. Everything but the program counter is
+/// optional, since emulators log different things, and only what's present is compared.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ReferenceState {
+    /// The program counter
+    pub program_counter: u16,
+    /// The index register
+    pub index_register: Option<u16>,
+    /// V0 to VF
+    pub variable_registers: [Option<u8>; VARIABLE_REGISTER_COUNT],
+    /// The delay timer
+    pub delay_timer: Option<u8>,
+    /// The sound timer
+    pub sound_timer: Option<u8>,
+    /// The stack pointer
+    pub stack_pointer: Option<u8>,
+    /// The keys held down during the cycle, one bit per key value. Replayed rather than compared
+    pub keys: Option<u16>,
+    /// The frame buffer, one byte per pixel
+    pub frame_buffer: Option<Vec<u8>>,
+}
+
+impl ReferenceState {
+    /// Captures everything but the keys from an interpreter, e.g. to compare two interpreters
+    pub fn capture<R: Chip8Rng>(chip8: &Chip8<R>) -> Self {
+        Self {
+            program_counter: chip8.program_counter(),
+            index_register: Some(chip8.index_register()),
+            variable_registers: chip8.variable_registers().map(Some),
+            delay_timer: Some(chip8.delay_timer()),
+            sound_timer: Some(chip8.sound_timer()),
+            stack_pointer: Some(chip8.stack_pointer()),
+            keys: None,
+            frame_buffer: Some(chip8.frame_buffer().to_vec()),
+        }
+    }
+}
+
+/// The ways parsing a reference trace can fail
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseTraceError {
+    /// The line or JSON record the error is in, counting from 1
+    pub line: usize,
+    /// What's wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for ParseTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseTraceError {}
+
+/// Parses a CSV trace with a header row naming its columns: `pc`, and optionally `i`, `v0` to
+/// `vf`, `dt`, `st`, `sp`, `keys` and `screen`. Other columns, e.g. `cycle` or `opcode`, are
+/// ignored. Numbers are decimal, or hex with a 0x prefix. The screen is a bit packed frame buffer
+/// in hex, a row at a time with the leftmost pixel in the highest bit.
+pub fn parse_csv(text: &str) -> Result<Vec<ReferenceState>, ParseTraceError> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, header)) = lines.next() else { return Ok(Vec::new()) };
+    let columns: Vec<String> = header.split(',').map(|column| column.trim().to_ascii_lowercase()).collect();
+
+    lines.map(|(index, line)| {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() != columns.len() {
+            return Err(ParseTraceError { line: index + 1, message: format!("expected {} fields", columns.len()) });
+        }
+
+        let fields = columns.iter().map(String::as_str).zip(fields.iter().map(|field| Field::Text(field)));
+        parse_state(fields).map_err(|message| ParseTraceError { line: index + 1, message })
+    }).collect()
+}
+
+/// Parses a JSON trace, either an array of objects or one object per line. Objects use the same
+/// keys as [`parse_csv`]'s columns, except that the registers can also be given as an array `v`.
+/// Numbers can be JSON numbers or strings.
+pub fn parse_json(text: &str) -> Result<Vec<ReferenceState>, ParseTraceError> {
+    let records: Vec<(usize, Value)> = match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(records)) => records.into_iter().enumerate().collect(),
+        _ => text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| serde_json::from_str(line)
+                .map(|record| (index, record))
+                .map_err(|error| ParseTraceError { line: index + 1, message: error.to_string() }))
+            .collect::<Result<_, _>>()?,
+    };
+
+    records.into_iter().map(|(index, record)| {
+        let error = |message: String| ParseTraceError { line: index + 1, message };
+        let Value::Object(object) = record else { return Err(error("expected an object".to_string())) };
+
+        let mut fields: Vec<(String, Field)> = Vec::new();
+        for (key, value) in &object {
+            let key = key.to_ascii_lowercase();
+            match value {
+                Value::Array(registers) if key == "v" => {
+                    for (x, value) in registers.iter().enumerate() {
+                        fields.push((format!("v{x:x}"), Field::from_json(value)));
+                    }
+                }
+                value => fields.push((key, Field::from_json(value))),
+            }
+        }
+
+        parse_state(fields.iter().map(|(key, field)| (key.as_str(), field.clone()))).map_err(error)
+    }).collect()
+}
+
+/// A field from a CSV or JSON trace
+#[derive(Debug, Clone)]
+enum Field<'a> {
+    /// A CSV field or JSON string
+    Text(&'a str),
+    /// A JSON number
+    Number(u64),
+    /// Any other JSON value
+    Other,
+}
+
+impl Field<'_> {
+    /// Wraps a JSON value
+    fn from_json(value: &Value) -> Field<'_> {
+        match value {
+            Value::String(text) => Field::Text(text),
+            Value::Number(number) => number.as_u64().map_or(Field::Other, Field::Number),
+            _ => Field::Other,
+        }
+    }
+
+    /// Parses the field as a number no larger than max
+    fn number(&self, key: &str, max: u64) -> Result<u64, String> {
+        let number = match self {
+            Field::Number(number) => Some(*number),
+            Field::Text(text) => match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(digits) => u64::from_str_radix(digits, 16).ok(),
+                None => text.parse().ok(),
+            },
+            Field::Other => None,
+        };
+
+        number.filter(|number| *number <= max).ok_or_else(|| format!("invalid value for {key}"))
+    }
+}
+
+/// Builds a reference state from named fields
+fn parse_state<'a, I: Iterator<Item = (&'a str, Field<'a>)>>(fields: I) -> Result<ReferenceState, String> {
+    let mut state = ReferenceState::default();
+    let mut program_counter = None;
+
+    for (key, field) in fields {
+        match key {
+            "pc" => program_counter = Some(field.number(key, 0xFFFF)? as u16),
+            "i" => state.index_register = Some(field.number(key, 0xFFFF)? as u16),
+            "dt" => state.delay_timer = Some(field.number(key, 0xFF)? as u8),
+            "st" => state.sound_timer = Some(field.number(key, 0xFF)? as u8),
+            "sp" => state.stack_pointer = Some(field.number(key, 0xFF)? as u8),
+            "keys" => state.keys = Some(field.number(key, 0xFFFF)? as u16),
+            "screen" => {
+                let Field::Text(hex) = field else { return Err("screen must be a hex string".to_string()) };
+                state.frame_buffer = Some(unpack_screen(hex).ok_or("invalid screen")?);
+            }
+            _ => match key.strip_prefix('v').and_then(|digit| usize::from_str_radix(digit, 16).ok()) {
+                Some(x) if x < VARIABLE_REGISTER_COUNT && key.len() == 2 => state.variable_registers[x] = Some(field.number(key, 0xFF)? as u8),
+                _ => {}
+            },
+        }
+    }
+
+    state.program_counter = program_counter.ok_or("missing pc")?;
+
+    Ok(state)
+}
+
+/// Unpacks a hex bit packed frame buffer into a byte per pixel
+fn unpack_screen(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() != SCREEN_HEX_DIGITS {
+        return None;
+    }
+
+    hex.chars()
+        .map(|digit| digit.to_digit(16))
+        .collect::<Option<Vec<u32>>>()
+        .map(|digits| digits.iter().flat_map(|digit| (0..4).rev().map(move |bit| (digit >> bit & 1) as u8)).collect())
+}
+
+/// One way our state differed from the reference
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Difference {
+    /// The program counters differ
+    ProgramCounter { expected: u16, actual: u16 },
+    /// The index registers differ
+    IndexRegister { expected: u16, actual: u16 },
+    /// A variable register differs
+    VariableRegister { x: u8, expected: u8, actual: u8 },
+    /// The delay timers differ
+    DelayTimer { expected: u8, actual: u8 },
+    /// The sound timers differ
+    SoundTimer { expected: u8, actual: u8 },
+    /// The stack pointers differ
+    StackPointer { expected: u8, actual: u8 },
+    /// Some pixels differ
+    FrameBuffer { differing_pixels: usize },
+    /// Our interpreter stopped at an opcode it can't execute while the reference kept going
+    InvalidOpcode { opcode: u16 },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::ProgramCounter { expected, actual } => write!(f, "PC expected {expected:03X}, got {actual:03X}"),
+            Difference::IndexRegister { expected, actual } => write!(f, "I expected {expected:03X}, got {actual:03X}"),
+            Difference::VariableRegister { x, expected, actual } => write!(f, "V{x:X} expected {expected:02X}, got {actual:02X}"),
+            Difference::DelayTimer { expected, actual } => write!(f, "DT expected {expected:02X}, got {actual:02X}"),
+            Difference::SoundTimer { expected, actual } => write!(f, "ST expected {expected:02X}, got {actual:02X}"),
+            Difference::StackPointer { expected, actual } => write!(f, "SP expected {expected:X}, got {actual:X}"),
+            Difference::FrameBuffer { differing_pixels } => write!(f, "{differing_pixels} pixels differ on screen"),
+            Difference::InvalidOpcode { opcode } => write!(f, "invalid opcode {opcode:04X}"),
+        }
+    }
+}
+
+/// The first cycle where our interpreter disagreed with a reference trace
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Divergence {
+    /// The index of the reference record that disagreed
+    pub cycle: usize,
+    /// How the states differed
+    pub differences: Vec<Difference>,
+    /// Our state for the cycles leading up to the divergence and the divergent cycle itself, as
+    /// captured at the trace's timing
+    pub context: Vec<TraceRecord>,
+}
+
+/// Replays a reference trace through an interpreter with its program already loaded, returning
+/// the first cycle where the state differs, if any. Keys recorded in the trace are pressed before
+/// their cycle executes. `context` is the number of cycles before the divergence to keep.
+pub fn find_divergence<R: Chip8Rng>(chip8: &mut Chip8<R>,
+                               reference: &[ReferenceState],
+                               timing: TraceTiming,
+                               instructions_per_frame: usize,
+                               context: usize) -> Option<Divergence> {
+    let mut debugger = Debugger::new();
+    let mut history = VecDeque::with_capacity(context + 1);
+
+    for (cycle, expected) in reference.iter().enumerate() {
+        if let Some(keys) = expected.keys {
+            for key in Chip8Key::ALL {
+                if keys & 1 << key.value() != 0 { chip8.key_down(key) } else { chip8.key_up(key) }
+            }
+        }
+
+        let instruction = disassemble(chip8.ram(), chip8.program_counter(), 1).first().copied();
+        if timing == TraceTiming::After {
+            if let RunOutcome::InvalidOpcode { opcode, .. } = debugger.step(chip8, instructions_per_frame) {
+                let differences = vec![Difference::InvalidOpcode { opcode }];
+                return Some(Divergence { cycle, differences, context: history.into() });
+            }
+        }
+
+        if history.len() > context {
+            history.pop_front();
+        }
+        if let Some(instruction) = instruction {
+            history.push_back(TraceRecord::capture(cycle as u64, chip8, &instruction));
+        }
+
+        let differences = compare(chip8, expected, timing, instruction.map(|instruction| instruction.address));
+        if !differences.is_empty() {
+            return Some(Divergence { cycle, differences, context: history.into() });
+        }
+
+        if timing == TraceTiming::Before && cycle + 1 < reference.len() {
+            if let RunOutcome::InvalidOpcode { opcode, .. } = debugger.step(chip8, instructions_per_frame) {
+                let differences = vec![Difference::InvalidOpcode { opcode }];
+                return Some(Divergence { cycle: cycle + 1, differences, context: history.into() });
+            }
+        }
+    }
+
+    None
+}
+
+/// Compares an interpreter's state to a reference record. After an instruction, the reference's program
+/// counter is the address of the instruction it executed, which is compared to the address ours
+/// executed
+pub(crate) fn compare<R: Chip8Rng>(chip8: &Chip8<R>, expected: &ReferenceState, timing: TraceTiming, executed: Option<u16>) -> Vec<Difference> {
+    let mut differences = Vec::new();
+
+    let program_counter = match timing {
+        TraceTiming::Before => chip8.program_counter(),
+        TraceTiming::After => executed.unwrap_or(chip8.program_counter()),
+    };
+    if program_counter != expected.program_counter {
+        differences.push(Difference::ProgramCounter { expected: expected.program_counter, actual: program_counter });
+    }
+
+    if let Some(index_register) = expected.index_register.filter(|value| *value != chip8.index_register()) {
+        differences.push(Difference::IndexRegister { expected: index_register, actual: chip8.index_register() });
+    }
+
+    for (x, (expected, actual)) in expected.variable_registers.iter().zip(chip8.variable_registers()).enumerate() {
+        if let Some(expected) = expected.filter(|expected| expected != actual) {
+            differences.push(Difference::VariableRegister { x: x as u8, expected, actual: *actual });
+        }
+    }
+
+    if let Some(delay_timer) = expected.delay_timer.filter(|value| *value != chip8.delay_timer()) {
+        differences.push(Difference::DelayTimer { expected: delay_timer, actual: chip8.delay_timer() });
+    }
+
+    if let Some(sound_timer) = expected.sound_timer.filter(|value| *value != chip8.sound_timer()) {
+        differences.push(Difference::SoundTimer { expected: sound_timer, actual: chip8.sound_timer() });
+    }
+
+    if let Some(stack_pointer) = expected.stack_pointer.filter(|value| *value != chip8.stack_pointer()) {
+        differences.push(Difference::StackPointer { expected: stack_pointer, actual: chip8.stack_pointer() });
+    }
+
+    if let Some(frame_buffer) = &expected.frame_buffer {
+        let differing_pixels = frame_buffer.iter().zip(chip8.frame_buffer()).filter(|(a, b)| a != b).count();
+        if differing_pixels > 0 {
+            differences.push(Difference::FrameBuffer { differing_pixels });
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestRng;
+    use crate::EmulatorType;
+    use super::*;
+
+    /// Sets V0 to 5, adds V0 to V1 and jumps back to the add
+    const PROGRAM: [u8; 6] = [0x60, 0x05, 0x81, 0x04, 0x12, 0x02];
+
+    fn chip8() -> Chip8<TestRng> {
+        let mut chip8 = Chip8::new(EmulatorType::CosmacVip, TestRng::new(0));
+        chip8.load_program(&PROGRAM);
+        chip8
+    }
+
+    #[test]
+    fn parses_csv_traces() {
+        let trace = parse_csv("cycle,pc,i,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,dt,keys\n\
+                               0,0x200,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0x0010\n\
+                               \n\
+                               1,0x202,0,5,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0\n").unwrap();
+
+        assert_eq!(2, trace.len());
+        assert_eq!(0x202, trace[1].program_counter);
+        assert_eq!(Some(5), trace[1].variable_registers[0]);
+        assert_eq!(Some(0x10), trace[0].keys);
+        assert_eq!(None, trace[0].sound_timer);
+
+        assert_eq!(2, parse_csv("pc,v0\n0x200\n").unwrap_err().line);
+        assert_eq!("missing pc", parse_csv("v0\n1\n").unwrap_err().message);
+    }
+
+    #[test]
+    fn parses_json_arrays_and_lines() {
+        let array = parse_json(r#"[{"pc": 512, "i": "0x300", "v": [1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16]}]"#).unwrap();
+        let lines = parse_json("{\"PC\": \"0x200\", \"dt\": 3}\n{\"pc\": 514}\n").unwrap();
+
+        assert_eq!(Some(0x300), array[0].index_register);
+        assert_eq!(Some(16), array[0].variable_registers[15]);
+        assert_eq!(2, lines.len());
+        assert_eq!(Some(3), lines[0].delay_timer);
+        assert_eq!(2, parse_json("{\"pc\": 512}\n{\"i\": 1}\n").unwrap_err().line);
+    }
+
+    #[test]
+    fn unpacks_screens() {
+        let mut hex = "8".to_string();
+        hex.push_str(&"0".repeat(SCREEN_HEX_DIGITS - 2));
+        hex.push('1');
+
+        let screen = unpack_screen(&hex).unwrap();
+
+        assert_eq!(DISPLAY_WIDTH * DISPLAY_HEIGHT, screen.len());
+        assert_eq!([1, 0, 0, 0], screen[..4]);
+        assert_eq!(1, screen[screen.len() - 1]);
+        assert_eq!(2, screen.iter().filter(|pixel| **pixel == 1).count());
+        assert_eq!(None, unpack_screen("00"));
+    }
+
+    #[test]
+    fn matching_traces_have_no_divergence() {
+        let reference = parse_csv("pc,v0\n0x200,0\n0x202,5\n0x204,5\n0x202,5\n").unwrap();
+
+        assert_eq!(None, find_divergence(&mut chip8(), &reference, TraceTiming::Before, 10, 2));
+    }
+
+    #[test]
+    fn finds_the_first_divergent_cycle_with_context() {
+        let reference = parse_json(r#"[{"pc": 512, "i": 0}, {"pc": 514}, {"pc": 516}, {"pc": 514, "i": 0}, {"pc": 516, "i": 1}]"#).unwrap();
+
+        let divergence = find_divergence(&mut chip8(), &reference, TraceTiming::Before, 10, 2).unwrap();
+
+        assert_eq!(4, divergence.cycle);
+        assert_eq!(vec![Difference::IndexRegister { expected: 1, actual: 0 }], divergence.differences);
+        assert_eq!(vec![2, 3, 4], divergence.context.iter().map(|record| record.cycle).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn compares_after_timing_by_executed_address() {
+        let reference = parse_csv("pc,v1\n0x200,0\n0x202,5\n0x204,5\n0x202,9\n").unwrap();
+
+        let divergence = find_divergence(&mut chip8(), &reference, TraceTiming::After, 10, 8).unwrap();
+
+        assert_eq!(3, divergence.cycle);
+        assert_eq!(vec![Difference::VariableRegister { x: 1, expected: 9, actual: 10 }], divergence.differences);
+        assert_eq!(4, divergence.context.len());
+    }
+}
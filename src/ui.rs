@@ -1,2 +1,5 @@
 pub mod renderer;
-pub mod audio;
\ No newline at end of file
+pub mod audio;
+pub mod controls;
+pub mod debugger_overlay;
+pub mod recording;pub mod lockstep;
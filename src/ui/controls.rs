@@ -0,0 +1,135 @@
+use macroquad::color::{Color, WHITE};
+use macroquad::input::{is_key_down, is_key_pressed, KeyCode};
+use macroquad::prelude::{draw_rectangle, draw_text, measure_text, screen_height, screen_width};
+
+/// The key that pauses and resumes emulation
+const PAUSE_KEY: KeyCode = KeyCode::P;
+
+/// The key that resets the emulator and reloads the ROM
+const RESET_KEY: KeyCode = KeyCode::F5;
+
+/// The key that runs a single frame while paused
+const FRAME_ADVANCE_KEY: KeyCode = KeyCode::N;
+
+/// The key that increases the number of instructions run per frame
+const SPEED_UP_KEY: KeyCode = KeyCode::Equal;
+
+/// The key that decreases the number of instructions run per frame
+const SLOW_DOWN_KEY: KeyCode = KeyCode::Minus;
+
+/// The key that runs several frames per displayed frame while held
+const FAST_FORWARD_KEY: KeyCode = KeyCode::Tab;
+
+/// The number of emulated frames run per displayed frame while fast forwarding
+const FAST_FORWARD_FRAMES: usize = 4;
+
+/// The most instructions that can be run per frame
+const MAX_INSTRUCTIONS_PER_FRAME: usize = 1000;
+
+/// The font size of the status line
+const STATUS_FONT_SIZE: f32 = 18.0;
+
+/// What the emulator should do this frame, as decided by the control hotkeys
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameCommand {
+    /// Whether to reset the emulator and reload the ROM before running
+    pub reset: bool,
+    /// The number of emulated frames to run
+    pub frames: usize,
+    /// The number of instructions to run per emulated frame
+    pub instructions_per_frame: usize,
+}
+
+/// Tracks the pause, speed and fast forward state controlled by hotkeys
+pub struct Controls {
+    paused: bool,
+    instructions_per_frame: usize,
+    fast_forwarding: bool,
+    /// Whether frames are timed like the COSMAC VIP, so the speed isn't used
+    vip_timing: bool,
+}
+
+impl Controls {
+    /// Creates controls that start running at the given speed
+    pub fn new(instructions_per_frame: usize) -> Self {
+        Self {
+            paused: false,
+            instructions_per_frame: instructions_per_frame.clamp(1, MAX_INSTRUCTIONS_PER_FRAME),
+            fast_forwarding: false,
+            vip_timing: false,
+        }
+    }
+
+    /// Shows that frames are timed like the COSMAC VIP rather than by the speed
+    pub fn set_vip_timing(&mut self, vip_timing: bool) {
+        self.vip_timing = vip_timing;
+    }
+
+    /// Returns whether emulation is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses emulation, e.g. when a breakpoint is hit
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Reads the hotkeys and decides what to run this frame
+    pub fn update(&mut self) -> FrameCommand {
+        if is_key_pressed(PAUSE_KEY) {
+            self.paused = !self.paused;
+        }
+
+        if is_key_pressed(SPEED_UP_KEY) {
+            self.instructions_per_frame = (self.instructions_per_frame + 1).min(MAX_INSTRUCTIONS_PER_FRAME);
+        }
+
+        if is_key_pressed(SLOW_DOWN_KEY) {
+            self.instructions_per_frame = (self.instructions_per_frame - 1).max(1);
+        }
+
+        self.fast_forwarding = is_key_down(FAST_FORWARD_KEY);
+
+        let frames = if self.paused {
+            usize::from(is_key_pressed(FRAME_ADVANCE_KEY))
+        } else if self.fast_forwarding {
+            FAST_FORWARD_FRAMES
+        } else {
+            1
+        };
+
+        FrameCommand {
+            reset: is_key_pressed(RESET_KEY),
+            frames,
+            instructions_per_frame: self.instructions_per_frame,
+        }
+    }
+
+    /// Describes the current state for the status line
+    pub fn status(&self) -> String {
+        let state = if self.paused {
+            "PAUSED"
+        } else if self.fast_forwarding {
+            "FAST FORWARD"
+        } else {
+            "RUNNING"
+        };
+
+        if self.vip_timing {
+            format!("{state}  VIP timing")
+        } else {
+            format!("{state}  {} instr/frame", self.instructions_per_frame)
+        }
+    }
+
+    /// Draws the status line along the bottom of the window
+    pub fn draw_status(&self, extra: &str) {
+        let text = if extra.is_empty() { self.status() } else { format!("{}  {extra}", self.status()) };
+        let dimensions = measure_text(&text, None, STATUS_FONT_SIZE as u16, 1.0);
+        let y = screen_height() - dimensions.height - 8.0;
+
+        draw_rectangle(0.0, y - 4.0, screen_width(), dimensions.height + 12.0, Color::new(0.0, 0.0, 0.0, 0.6));
+        draw_text(&text, 6.0, y + dimensions.offset_y, STATUS_FONT_SIZE, WHITE);
+    }
+}
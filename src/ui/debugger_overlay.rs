@@ -0,0 +1,204 @@
+use macroquad::color::{Color, GRAY, RED, WHITE, YELLOW};
+use macroquad::input::{is_key_pressed, KeyCode};
+use macroquad::prelude::{draw_rectangle, draw_text, screen_height, screen_width};
+use chip_8_emulator::debugger::Debugger;
+use chip_8_emulator::instruction::disassemble;
+use chip_8_emulator::{Chip8, Chip8Key, Chip8Rng, KeyState};
+
+/// The key that shows and hides the overlay
+const TOGGLE_KEY: KeyCode = KeyCode::F1;
+
+/// The key that executes a single instruction
+const STEP_KEY: KeyCode = KeyCode::F10;
+
+/// The key that adds or removes a breakpoint at the disassembly cursor
+const BREAKPOINT_KEY: KeyCode = KeyCode::B;
+
+/// The key that moves the disassembly cursor up
+const CURSOR_UP_KEY: KeyCode = KeyCode::Up;
+
+/// The key that moves the disassembly cursor down
+const CURSOR_DOWN_KEY: KeyCode = KeyCode::Down;
+
+/// The key that puts the disassembly cursor back on the program counter
+const CURSOR_HOME_KEY: KeyCode = KeyCode::Home;
+
+/// The key that scrolls the memory view up
+const MEMORY_UP_KEY: KeyCode = KeyCode::PageUp;
+
+/// The key that scrolls the memory view down
+const MEMORY_DOWN_KEY: KeyCode = KeyCode::PageDown;
+
+/// The font size of the overlay text
+const FONT_SIZE: f32 = 16.0;
+
+/// The vertical distance between lines of text
+const LINE_HEIGHT: f32 = 18.0;
+
+/// The number of instructions shown before the cursor in the disassembly
+const DISASSEMBLY_LINES_BEFORE: usize = 8;
+
+/// The number of lines in the disassembly
+const DISASSEMBLY_LINES: usize = 24;
+
+/// The number of bytes per line in the memory view
+const MEMORY_BYTES_PER_LINE: usize = 8;
+
+/// The number of lines in the memory view
+const MEMORY_LINES: usize = 24;
+
+/// The layout of the keypad, as it appears on the COSMAC VIP
+const KEYPAD_LAYOUT: [[Chip8Key; 4]; 4] = [
+    [Chip8Key::One, Chip8Key::Two, Chip8Key::Three, Chip8Key::C],
+    [Chip8Key::Four, Chip8Key::Five, Chip8Key::Six, Chip8Key::D],
+    [Chip8Key::Seven, Chip8Key::Eight, Chip8Key::Nine, Chip8Key::E],
+    [Chip8Key::A, Chip8Key::Zero, Chip8Key::B, Chip8Key::F],
+];
+
+/// An overlay showing the interpreter's registers, a disassembly around the program counter and a
+/// view of memory. The disassembly has a cursor for placing breakpoints, which follows the
+/// program counter until it's moved.
+pub struct DebuggerOverlay {
+    visible: bool,
+    /// The address of the disassembly cursor, or None to follow the program counter
+    cursor: Option<u16>,
+    /// The address of the first byte in the memory view
+    memory_offset: usize,
+}
+
+impl DebuggerOverlay {
+    /// Creates a hidden overlay
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            cursor: None,
+            memory_offset: 0x200,
+        }
+    }
+
+    /// Handles the overlay's hotkeys. Returns whether a single step was requested
+    pub fn handle_input(&mut self, debugger: &mut Debugger, program_counter: u16, memory_size: usize) -> bool {
+        if is_key_pressed(TOGGLE_KEY) {
+            self.visible = !self.visible;
+        }
+
+        if !self.visible {
+            return false;
+        }
+
+        let cursor = self.cursor.unwrap_or(program_counter);
+        if is_key_pressed(CURSOR_UP_KEY) {
+            self.cursor = Some(cursor.saturating_sub(2));
+        }
+        if is_key_pressed(CURSOR_DOWN_KEY) {
+            self.cursor = Some((cursor + 2).min(memory_size as u16 - 2));
+        }
+        if is_key_pressed(CURSOR_HOME_KEY) {
+            self.cursor = None;
+        }
+
+        if is_key_pressed(BREAKPOINT_KEY) {
+            debugger.toggle_breakpoint(self.cursor.unwrap_or(program_counter));
+        }
+
+        let page = MEMORY_BYTES_PER_LINE * MEMORY_LINES;
+        if is_key_pressed(MEMORY_UP_KEY) {
+            self.memory_offset = self.memory_offset.saturating_sub(page);
+        }
+        if is_key_pressed(MEMORY_DOWN_KEY) {
+            self.memory_offset = (self.memory_offset + page).min(memory_size - page);
+        }
+
+        is_key_pressed(STEP_KEY)
+    }
+
+    /// Draws the overlay if it's showing
+    pub fn draw<R: Chip8Rng>(&self, chip8: &Chip8<R>, debugger: &Debugger) {
+        if !self.visible {
+            return;
+        }
+
+        draw_rectangle(0.0, 0.0, screen_width(), screen_height(), Color::new(0.0, 0.0, 0.0, 0.75));
+
+        self.draw_registers(chip8, 10.0, 20.0);
+        self.draw_disassembly(chip8, debugger, 230.0, 20.0);
+        self.draw_memory(chip8, 500.0, 20.0);
+    }
+
+    /// Draws the registers, timers, stack and keypad in a column
+    fn draw_registers<R: Chip8Rng>(&self, chip8: &Chip8<R>, x: f32, y: f32) {
+        let mut lines = vec![
+            format!("PC {:03X}   I {:03X}", chip8.program_counter(), chip8.index_register()),
+            format!("DT {:02X}    ST {:02X}", chip8.delay_timer(), chip8.sound_timer()),
+            String::new(),
+        ];
+
+        let registers = chip8.variable_registers();
+        for row in 0..8 {
+            lines.push(format!("V{:X} {:02X}    V{:X} {:02X}", row, registers[row], row + 8, registers[row + 8]));
+        }
+
+        lines.push(String::new());
+        lines.push(format!("SP {:X}", chip8.stack_pointer()));
+        for (depth, address) in chip8.stack().iter().enumerate().rev() {
+            lines.push(format!(" {depth:X}: {address:03X}"));
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            draw_text(line, x, y + i as f32 * LINE_HEIGHT, FONT_SIZE, WHITE);
+        }
+
+        let keypad_y = y + (lines.len() + 1) as f32 * LINE_HEIGHT;
+        for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (column, key) in keys.iter().enumerate() {
+                let color = if chip8.key_state(*key) == KeyState::Down { YELLOW } else { GRAY };
+                let label = format!("{:X}", key.value());
+                draw_text(&label, x + column as f32 * 22.0, keypad_y + row as f32 * LINE_HEIGHT, FONT_SIZE, color);
+            }
+        }
+    }
+
+    /// Draws the disassembly around the cursor, marking the program counter and breakpoints
+    fn draw_disassembly<R: Chip8Rng>(&self, chip8: &Chip8<R>, debugger: &Debugger, x: f32, y: f32) {
+        let program_counter = chip8.program_counter();
+        let cursor = self.cursor.unwrap_or(program_counter);
+        let start = cursor.saturating_sub((DISASSEMBLY_LINES_BEFORE * 2) as u16);
+
+        for (i, line) in disassemble(chip8.ram(), start, DISASSEMBLY_LINES).iter().enumerate() {
+            let marker = match (line.address == program_counter, line.address == cursor) {
+                (true, _) => ">",
+                (false, true) => "-",
+                _ => " ",
+            };
+            let breakpoint = if debugger.has_breakpoint(line.address) { "*" } else { " " };
+            let text = format!("{breakpoint}{marker}{:03X}  {:04X}  {}", line.address, line.opcode, line.instruction);
+            let color = if debugger.has_breakpoint(line.address) {
+                RED
+            } else if line.address == program_counter {
+                YELLOW
+            } else {
+                WHITE
+            };
+
+            draw_text(&text, x, y + i as f32 * LINE_HEIGHT, FONT_SIZE, color);
+        }
+    }
+
+    /// Draws a page of memory as hex bytes
+    fn draw_memory<R: Chip8Rng>(&self, chip8: &Chip8<R>, x: f32, y: f32) {
+        let ram = chip8.ram();
+        let index_register = chip8.index_register() as usize;
+
+        for line in 0..MEMORY_LINES {
+            let address = self.memory_offset + line * MEMORY_BYTES_PER_LINE;
+            let Some(bytes) = ram.get(address..address + MEMORY_BYTES_PER_LINE) else { break };
+            let line_y = y + line as f32 * LINE_HEIGHT;
+
+            draw_text(&format!("{address:03X}"), x, line_y, FONT_SIZE, GRAY);
+            for (column, byte) in bytes.iter().enumerate() {
+                let color = if address + column == index_register { YELLOW } else { WHITE };
+                draw_text(&format!("{byte:02X}"), x + 40.0 + column as f32 * 24.0, line_y, FONT_SIZE, color);
+            }
+        }
+    }
+}
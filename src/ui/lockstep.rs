@@ -0,0 +1,67 @@
+use macroquad::color::WHITE;
+use macroquad::input::is_key_down;
+use macroquad::math::Rect;
+use macroquad::prelude::{clear_background, draw_text, next_frame, screen_height, screen_width, BLACK};
+use chip_8_emulator::persistence::Phosphor;
+use chip_8_emulator::{EmulatorType, KeyState, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::cli::Options;
+use crate::headless::{create_lockstep, describe_divergence};
+use crate::key_code_map;
+use crate::ui::renderer::Renderer;
+
+/// The font size of the screen labels
+const LABEL_FONT_SIZE: f32 = 20.0;
+
+/// The height of the area above each screen for its label
+const LABEL_HEIGHT: f32 = 28.0;
+
+/// Runs the program on two interpreter types in lockstep with their screens side by side, the
+/// emulator type from the options on the left. The first divergence is printed and shown under
+/// the screens, and both keep running afterwards
+pub async fn run_lockstep_window(options: Options, program: Vec<u8>, right_type: EmulatorType) {
+    let key_code_map = key_code_map();
+    let mut lockstep = create_lockstep(&options, &program, right_type);
+
+    let mut left_phosphor = Phosphor::new(options.persistence, DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    let mut right_phosphor = Phosphor::new(options.persistence, DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    let mut left_renderer = Renderer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    let mut right_renderer = Renderer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    let mut status = String::from("In sync");
+
+    loop {
+        if let Some(divergence) = lockstep.run_frame() {
+            let description = describe_divergence(divergence, options.emulator_type, right_type);
+            println!("{description}");
+            status = description.lines().next().unwrap_or_default().to_string();
+        }
+
+        clear_background(BLACK);
+        let half_width = screen_width() / 2.0;
+        let screen_area_height = screen_height() - LABEL_HEIGHT * 2.0;
+
+        for (index, emulator_type) in [options.emulator_type, right_type].into_iter().enumerate() {
+            let x = index as f32 * half_width;
+            draw_text(&format!("{emulator_type:?}"), x + 8.0, LABEL_HEIGHT - 8.0, LABEL_FONT_SIZE, WHITE);
+        }
+
+        let left_area = Rect::new(0.0, LABEL_HEIGHT, half_width, screen_area_height);
+        let right_area = Rect::new(half_width, LABEL_HEIGHT, half_width, screen_area_height);
+        left_renderer.draw_in(left_phosphor.update(lockstep.left().frame_buffer()), DISPLAY_WIDTH, DISPLAY_HEIGHT, &options.palette, left_area);
+        right_renderer.draw_in(right_phosphor.update(lockstep.right().frame_buffer()), DISPLAY_WIDTH, DISPLAY_HEIGHT, &options.palette, right_area);
+        draw_text(&status, 8.0, screen_height() - 10.0, LABEL_FONT_SIZE, WHITE);
+
+        next_frame().await;
+
+        for (key_code, key) in key_code_map.iter() {
+            let is_key_down = is_key_down(*key_code);
+            let key_state = lockstep.left().key_state(*key);
+
+            if is_key_down && key_state == KeyState::Up {
+                lockstep.key_down(*key);
+            }
+            if !is_key_down && key_state == KeyState::Down {
+                lockstep.key_up(*key);
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use chip_8_emulator::palette::Rgb;
+use chip_8_emulator::recorder::FrameRecorder;
+use chip_8_emulator::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Records the window's session to a video file. Recording can be started and stopped at any
+/// time, failures are reported on stderr rather than interrupting the session
+pub struct SessionRecorder {
+    recorder: Option<FrameRecorder<BufWriter<File>>>,
+    path: PathBuf,
+    scale: usize,
+}
+
+impl SessionRecorder {
+    /// Creates a recorder that isn't recording yet
+    pub fn new(scale: usize) -> Self {
+        Self {
+            recorder: None,
+            path: PathBuf::new(),
+            scale,
+        }
+    }
+
+    /// Returns whether a recording is in progress
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Starts recording to path, stopping any recording already in progress
+    pub fn start(&mut self, path: &Path, palette: &[Rgb]) {
+        self.stop();
+
+        match FrameRecorder::create(path, DISPLAY_WIDTH, DISPLAY_HEIGHT, self.scale, palette) {
+            Ok(recorder) => {
+                println!("Recording to {}", path.display());
+                self.recorder = Some(recorder);
+                self.path = path.to_path_buf();
+            }
+            Err(error) => eprintln!("error: failed to start recording to {}: {error}", path.display()),
+        }
+    }
+
+    /// Stops recording and finishes the file being written, if there is one
+    pub fn stop(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let frames = recorder.frames_recorded();
+            match recorder.finish() {
+                Ok(()) => println!("Recorded {frames} frames to {}", self.path.display()),
+                Err(error) => eprintln!("error: failed to finish recording {}: {error}", self.path.display()),
+            }
+        }
+    }
+
+    /// Starts a recording to a timestamped GIF in the working directory, or stops the recording in
+    /// progress
+    pub fn toggle(&mut self, palette: &[Rgb]) {
+        if self.is_recording() {
+            self.stop();
+        } else {
+            let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+            self.start(Path::new(&format!("chip-8-{seconds}.gif")), palette);
+        }
+    }
+
+    /// Records a frame if a recording is in progress
+    pub fn record_frame(&mut self, frame_buffer: &[u8]) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(error) = recorder.record_frame(frame_buffer) {
+                eprintln!("error: failed to record frame to {}: {error}", self.path.display());
+                self.recorder = None;
+            }
+        }
+    }
+}
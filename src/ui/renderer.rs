@@ -1,20 +1,161 @@
-use macroquad::color::{BLACK, GREEN};
-use macroquad::prelude::{clear_background, draw_rectangle, next_frame, screen_height, screen_width};
-use chip_8_emulator::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use macroquad::color::{Color, BLACK, WHITE};
+use macroquad::math::{vec2, Rect};
+use macroquad::prelude::{clear_background, draw_line, draw_texture_ex, screen_height, screen_width,
+                         DrawTextureParams, FilterMode, Image, Texture2D};
+use chip_8_emulator::palette::{Palette, Rgb};
 
-// TODO make this more robust
-pub async fn render_frame(frame_buffer: &[u8]) {
-    clear_background(BLACK);
+/// The smallest number of screen pixels per Chip-8 pixel at which the pixel grid is drawn. Below
+/// this the grid lines would cover most of the image
+const MIN_GRID_SCALE: f32 = 4.0;
 
-    let pixel_length = screen_width() / DISPLAY_WIDTH as f32;
-    let pixel_height = screen_height() / DISPLAY_HEIGHT as f32;
+/// The area of the window the display is drawn to
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Viewport {
+    /// Left edge in screen pixels
+    pub x: f32,
+    /// Top edge in screen pixels
+    pub y: f32,
+    /// Width in screen pixels
+    pub width: f32,
+    /// Height in screen pixels
+    pub height: f32,
+    /// The number of screen pixels per display pixel
+    pub scale: f32,
+}
+
+/// Computes the largest centered area of a screen that shows a display at a whole number of screen
+/// pixels per display pixel, leaving black bars around it. Screens too small to fit the display at
+/// 1x get a fractional scale that still preserves the aspect ratio.
+pub fn integer_viewport(screen_width: f32, screen_height: f32, display_width: usize, display_height: usize) -> Viewport {
+    let fit_scale = (screen_width / display_width as f32).min(screen_height / display_height as f32);
+    let scale = if fit_scale >= 1.0 { fit_scale.floor() } else { fit_scale };
+    let width = display_width as f32 * scale;
+    let height = display_height as f32 * scale;
+
+    Viewport {
+        x: ((screen_width - width) / 2.0).floor(),
+        y: ((screen_height - height) / 2.0).floor(),
+        width,
+        height,
+        scale,
+    }
+}
+
+/// Draws the display as a single texture, scaled by a whole number with nearest neighbor filtering
+pub struct Renderer {
+    image: Image,
+    texture: Texture2D,
+    display_width: usize,
+    display_height: usize,
+    show_grid: bool,
+}
+
+impl Renderer {
+    /// Creates a renderer for a display with the given resolution
+    pub fn new(display_width: usize, display_height: usize) -> Self {
+        let image = Image::gen_image_color(display_width as u16, display_height as u16, WHITE);
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Nearest);
+
+        Self {
+            image,
+            texture,
+            display_width,
+            display_height,
+            show_grid: false,
+        }
+    }
 
-    for (i, pixel) in frame_buffer.iter().enumerate() {
-        let x = i % DISPLAY_WIDTH;
-        let y = i / DISPLAY_WIDTH;
-        let color = if *pixel == 0 { BLACK } else { GREEN };
-        draw_rectangle(x as f32 * pixel_length, y as f32 * pixel_height, pixel_length, pixel_height, color);
+    /// Shows or hides lines between pixels
+    pub fn toggle_grid(&mut self) {
+        self.show_grid = !self.show_grid;
     }
 
-    next_frame().await;
+    /// Draws pixel brightness levels, as produced by [`chip_8_emulator::persistence::Phosphor`],
+    /// in the palette's colors, filling the window. If the resolution changed since the last frame
+    /// the texture is recreated to match
+    pub fn draw(&mut self, brightness: &[u8], display_width: usize, display_height: usize, palette: &Palette) {
+        clear_background(BLACK);
+        let area = Rect::new(0.0, 0.0, screen_width(), screen_height());
+        self.draw_in(brightness, display_width, display_height, palette, area);
+    }
+
+    /// Draws pixel brightness levels like [`Renderer::draw`], but letterboxed within an area of
+    /// the window
+    pub fn draw_in(&mut self, brightness: &[u8], display_width: usize, display_height: usize, palette: &Palette, area: Rect) {
+        if display_width != self.display_width || display_height != self.display_height {
+            *self = Self { show_grid: self.show_grid, ..Self::new(display_width, display_height) };
+        }
+
+        for (pixel, level) in self.image.get_image_data_mut().iter_mut().zip(brightness) {
+            let [r, g, b] = palette.color_for_brightness(*level);
+            *pixel = [r, g, b, 255];
+        }
+        self.texture.update(&self.image);
+
+        let mut viewport = integer_viewport(area.w, area.h, display_width, display_height);
+        viewport.x += area.x;
+        viewport.y += area.y;
+
+        draw_texture_ex(&self.texture, viewport.x, viewport.y, WHITE, DrawTextureParams {
+            dest_size: Some(vec2(viewport.width, viewport.height)),
+            ..Default::default()
+        });
+
+        if self.show_grid && viewport.scale >= MIN_GRID_SCALE {
+            draw_grid(&viewport, display_width, display_height, palette.background());
+        }
+    }
+}
+
+/// Draws lines along the pixel boundaries of the viewport in a translucent background color
+fn draw_grid(viewport: &Viewport, display_width: usize, display_height: usize, [r, g, b]: Rgb) {
+    let color = Color::from_rgba(r, g, b, 160);
+
+    for column in 1..display_width {
+        let x = viewport.x + column as f32 * viewport.scale;
+        draw_line(x, viewport.y, x, viewport.y + viewport.height, 1.0, color);
+    }
+
+    for row in 1..display_height {
+        let y = viewport.y + row as f32 * viewport.scale;
+        draw_line(viewport.x, y, viewport.x + viewport.width, y, 1.0, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_uses_largest_whole_scale_and_centers() {
+        let viewport = integer_viewport(800.0, 600.0, 64, 32);
+
+        assert_eq!(Viewport { x: 16.0, y: 108.0, width: 768.0, height: 384.0, scale: 12.0 }, viewport);
+    }
+
+    #[test]
+    fn viewport_keeps_aspect_ratio_for_tall_windows() {
+        let viewport = integer_viewport(300.0, 900.0, 64, 32);
+
+        assert_eq!(4.0, viewport.scale);
+        assert_eq!(256.0, viewport.width);
+        assert_eq!(128.0, viewport.height);
+    }
+
+    #[test]
+    fn viewport_scales_high_resolution_displays() {
+        let viewport = integer_viewport(800.0, 600.0, 128, 64);
+
+        assert_eq!(Viewport { x: 16.0, y: 108.0, width: 768.0, height: 384.0, scale: 6.0 }, viewport);
+    }
+
+    #[test]
+    fn viewport_shrinks_displays_larger_than_the_screen() {
+        let viewport = integer_viewport(32.0, 32.0, 64, 32);
+
+        assert_eq!(0.5, viewport.scale);
+        assert_eq!(32.0, viewport.width);
+        assert_eq!(16.0, viewport.height);
+    }
 }